@@ -0,0 +1,511 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::endpoints::{self, ApiRequest};
+use crate::error::Error;
+use crate::vm_config::VmConfig;
+use crate::{
+    CloudHypervisorInstance, Device, Disk, Fs, Net, PathOrFileDescriptorOption, Pmem, UserDevice,
+    Vdpa, Vsock,
+};
+
+#[derive(Debug)]
+pub enum ApiError {
+    Io(io::Error),
+    // `--api-socket fd=N` where fd N is not a Unix socket bound to a path in this process.
+    UnresolvedSocket(usize),
+    InvalidResponse(String),
+    Json(serde_json::Error),
+    // The request could not be built from the crate's model.
+    Config(Error),
+    Http {
+        status: u16,
+        reason: String,
+        body: String,
+    },
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::Io(err) => write!(f, "api socket: {}", err),
+            ApiError::UnresolvedSocket(fd) => {
+                write!(f, "api socket fd {} is not bound to a path", fd)
+            }
+            ApiError::InvalidResponse(response) => {
+                write!(f, "invalid api response: {}", response)
+            }
+            ApiError::Json(err) => write!(f, "invalid api response: {}", err),
+            ApiError::Config(err) => write!(f, "invalid api request: {}", err),
+            ApiError::Http {
+                status,
+                reason,
+                body,
+            } => {
+                write!(f, "api request failed: {} {}", status, reason)?;
+                if !body.is_empty() {
+                    write!(f, ": {}", body.trim())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApiError::Io(err) => Some(err),
+            ApiError::Json(err) => Some(err),
+            ApiError::Config(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ApiError {
+    fn from(err: io::Error) -> Self {
+        ApiError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(err: serde_json::Error) -> Self {
+        ApiError::Json(err)
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        ApiError::Config(err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VmmPingResponse {
+    pub build_version: String,
+    pub version: String,
+    #[serde(default)]
+    pub pid: Option<i64>,
+    #[serde(default)]
+    pub features: Option<Vec<String>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum VmState {
+    Created,
+    Running,
+    Shutdown,
+    Paused,
+    BreakPoint,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct VmInfo {
+    pub config: VmConfig,
+    pub state: VmState,
+    #[serde(default)]
+    pub memory_actual_size: Option<u64>,
+    #[serde(default)]
+    pub device_tree: Option<serde_json::Value>,
+}
+
+// Returned by the `vm.add-*` endpoints.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PciDeviceInfo {
+    pub id: String,
+    pub bdf: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct RemoveDevice<'a> {
+    pub(crate) id: &'a str,
+}
+
+// Device id -> counter name -> value
+pub type VmCounters = BTreeMap<String, BTreeMap<String, u64>>;
+
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    pub(crate) reason: String,
+    pub(crate) body: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiClient {
+    socket: PathBuf,
+    timeout: Option<Duration>,
+}
+
+impl ApiClient {
+    pub fn from_path(socket: impl Into<PathBuf>) -> Self {
+        ApiClient {
+            socket: socket.into(),
+            timeout: None,
+        }
+    }
+
+    // For `fd=N` the fd is looked up in the calling process, which works when the listening socket
+    // handed to cloud-hypervisor is still open under the same number here.
+    pub fn new(api_socket: &PathOrFileDescriptorOption) -> Result<Self, ApiError> {
+        match api_socket {
+            PathOrFileDescriptorOption::Path(path) => Ok(Self::from_path(path)),
+            PathOrFileDescriptorOption::Fd(fd) => socket_path(*fd)
+                .map(Self::from_path)
+                .ok_or(ApiError::UnresolvedSocket(*fd)),
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    pub fn vmm_ping(&self) -> Result<VmmPingResponse, ApiError> {
+        self.send(endpoints::vmm_ping())
+    }
+
+    pub fn vmm_shutdown(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vmm_shutdown())
+    }
+
+    pub fn vm_create<T: Serialize>(&self, config: &T) -> Result<(), ApiError> {
+        self.send(endpoints::vm_create(config)?)
+    }
+
+    pub fn vm_boot(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_boot())
+    }
+
+    pub fn vm_shutdown(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_shutdown())
+    }
+
+    pub fn vm_reboot(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_reboot())
+    }
+
+    pub fn vm_pause(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_pause())
+    }
+
+    pub fn vm_resume(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_resume())
+    }
+
+    pub fn vm_power_button(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_power_button())
+    }
+
+    pub fn vm_delete(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_delete())
+    }
+
+    pub fn vm_info(&self) -> Result<VmInfo, ApiError> {
+        self.send(endpoints::vm_info())
+    }
+
+    pub fn vm_counters(&self) -> Result<VmCounters, ApiError> {
+        self.send(endpoints::vm_counters())
+    }
+
+    pub fn vm_add_disk(&self, disk: &Disk) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_disk(disk)?)
+    }
+
+    pub fn vm_add_net(&self, net: &Net) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_net(net)?)
+    }
+
+    pub fn vm_add_fs(&self, fs: &Fs) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_fs(fs)?)
+    }
+
+    pub fn vm_add_pmem(&self, pmem: &Pmem) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_pmem(pmem)?)
+    }
+
+    pub fn vm_add_device(&self, device: &Device) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_device(device)?)
+    }
+
+    pub fn vm_add_user_device(&self, user_device: &UserDevice) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_user_device(user_device)?)
+    }
+
+    pub fn vm_add_vdpa(&self, vdpa: &Vdpa) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_vdpa(vdpa)?)
+    }
+
+    pub fn vm_add_vsock(&self, vsock: &Vsock) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_vsock(vsock)?)
+    }
+
+    pub fn vm_remove_device(&self, id: &str) -> Result<(), ApiError> {
+        self.send(endpoints::vm_remove_device(id)?)
+    }
+
+    pub(crate) fn send<R>(&self, request: ApiRequest<R>) -> Result<R, ApiError> {
+        let mut stream = UnixStream::connect(&self.socket)?;
+        stream.set_read_timeout(self.timeout)?;
+        stream.set_write_timeout(self.timeout)?;
+
+        stream
+            .write_all(request_head(request.method, request.endpoint, &request.body).as_bytes())?;
+        stream.write_all(&request.body)?;
+
+        let response = check_status(read_response(BufReader::new(stream))?)?;
+        request.decode(&response.body)
+    }
+}
+
+// The request line and headers; the body follows as is.
+pub(crate) fn request_head(method: &str, endpoint: &str, body: &[u8]) -> String {
+    let mut head = format!(
+        "{} /api/v1/{} HTTP/1.1\r\nHost: localhost\r\n",
+        method, endpoint
+    );
+    if !body.is_empty() {
+        head.push_str("Content-Type: application/json\r\n");
+    }
+    head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+    head
+}
+
+pub(crate) fn parse_status_line(status_line: &str) -> Result<(u16, String), ApiError> {
+    let invalid = || ApiError::InvalidResponse(status_line.trim().to_string());
+
+    let mut parts = status_line.trim_end().splitn(3, ' ');
+    if !parts.next().unwrap_or_default().starts_with("HTTP/1.") {
+        return Err(invalid());
+    }
+    let status = parts
+        .next()
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(invalid)?;
+    Ok((status, parts.next().unwrap_or_default().to_string()))
+}
+
+// The body length announced by a header line, `None` for other headers.
+pub(crate) fn parse_content_length(header: &str) -> Result<Option<usize>, ApiError> {
+    match header.split_once(':') {
+        Some((name, value)) if name.eq_ignore_ascii_case("content-length") => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| ApiError::InvalidResponse(header.to_string())),
+        _ => Ok(None),
+    }
+}
+
+pub(crate) fn check_status(response: Response) -> Result<Response, ApiError> {
+    if !(200..300).contains(&response.status) {
+        return Err(ApiError::Http {
+            status: response.status,
+            reason: response.reason,
+            body: String::from_utf8_lossy(&response.body).into_owned(),
+        });
+    }
+    Ok(response)
+}
+
+fn read_response(mut reader: impl BufRead) -> Result<Response, ApiError> {
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+    let (status, reason) = parse_status_line(&status_line)?;
+
+    let mut content_length = 0usize;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Err(ApiError::InvalidResponse("truncated headers".to_string()));
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(length) = parse_content_length(header)? {
+            content_length = length;
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Response {
+        status,
+        reason,
+        body,
+    })
+}
+
+fn socket_path(fd: usize) -> Option<PathBuf> {
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::sockaddr_un>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockname(
+            fd as libc::c_int,
+            &mut addr as *mut libc::sockaddr_un as *mut libc::sockaddr,
+            &mut len,
+        )
+    };
+    if ret < 0 || addr.sun_family != libc::AF_UNIX as libc::sa_family_t {
+        return None;
+    }
+
+    let offset = mem::size_of::<libc::sa_family_t>();
+    let path_len = (len as usize).checked_sub(offset)?;
+    let path = addr.sun_path[..path_len.min(addr.sun_path.len())]
+        .iter()
+        .map(|c| *c as u8)
+        .take_while(|c| *c != 0)
+        .collect::<Vec<u8>>();
+    // unnamed and abstract sockets have no path
+    if path.is_empty() {
+        return None;
+    }
+    Some(PathBuf::from(std::ffi::OsStr::from_bytes(&path)))
+}
+
+// Drops the devices with the given id from a device list, returning whether any was found.
+fn remove_by_id<T>(
+    devices: &mut Option<Vec<T>>,
+    id: &str,
+    device_id: impl Fn(&T) -> Option<&String>,
+) -> bool {
+    let Some(list) = devices else {
+        return false;
+    };
+    let len = list.len();
+    list.retain(|device| device_id(device).map(String::as_str) != Some(id));
+    let removed = list.len() != len;
+    if list.is_empty() {
+        *devices = None;
+    }
+    removed
+}
+
+impl CloudHypervisorInstance {
+    pub fn api_client(&self) -> Option<Result<ApiClient, ApiError>> {
+        self.api_socket.as_ref().map(ApiClient::new)
+    }
+
+    // The `hotplug_*` calls add the device to the running VM and, once that succeeded, record it
+    // in the instance with the id cloud-hypervisor reported, so it can be unplugged by id later.
+
+    pub fn hotplug_disk(
+        &mut self,
+        api: &ApiClient,
+        mut disk: Disk,
+    ) -> Result<PciDeviceInfo, ApiError> {
+        let info = api.vm_add_disk(&disk)?;
+        disk.id = Some(info.id.clone());
+        self.disk(disk);
+        Ok(info)
+    }
+
+    pub fn hotplug_net(
+        &mut self,
+        api: &ApiClient,
+        mut net: Net,
+    ) -> Result<PciDeviceInfo, ApiError> {
+        let info = api.vm_add_net(&net)?;
+        net.id = Some(info.id.clone());
+        self.net(net);
+        Ok(info)
+    }
+
+    pub fn hotplug_fs(&mut self, api: &ApiClient, mut fs: Fs) -> Result<PciDeviceInfo, ApiError> {
+        let info = api.vm_add_fs(&fs)?;
+        fs.id = Some(info.id.clone());
+        self.fs(fs);
+        Ok(info)
+    }
+
+    pub fn hotplug_pmem(
+        &mut self,
+        api: &ApiClient,
+        mut pmem: Pmem,
+    ) -> Result<PciDeviceInfo, ApiError> {
+        let info = api.vm_add_pmem(&pmem)?;
+        pmem.id = Some(info.id.clone());
+        self.pmem(pmem);
+        Ok(info)
+    }
+
+    pub fn hotplug_device(
+        &mut self,
+        api: &ApiClient,
+        mut device: Device,
+    ) -> Result<PciDeviceInfo, ApiError> {
+        let info = api.vm_add_device(&device)?;
+        device.id = Some(info.id.clone());
+        self.device(device);
+        Ok(info)
+    }
+
+    pub fn hotplug_user_device(
+        &mut self,
+        api: &ApiClient,
+        mut user_device: UserDevice,
+    ) -> Result<PciDeviceInfo, ApiError> {
+        let info = api.vm_add_user_device(&user_device)?;
+        user_device.id = Some(info.id.clone());
+        self.user_device(user_device);
+        Ok(info)
+    }
+
+    pub fn hotplug_vdpa(
+        &mut self,
+        api: &ApiClient,
+        mut vdpa: Vdpa,
+    ) -> Result<PciDeviceInfo, ApiError> {
+        let info = api.vm_add_vdpa(&vdpa)?;
+        vdpa.id = Some(info.id.clone());
+        self.vdpa(vdpa);
+        Ok(info)
+    }
+
+    pub fn hotplug_vsock(
+        &mut self,
+        api: &ApiClient,
+        mut vsock: Vsock,
+    ) -> Result<PciDeviceInfo, ApiError> {
+        let info = api.vm_add_vsock(&vsock)?;
+        vsock.id = Some(info.id.clone());
+        self.vsock(vsock);
+        Ok(info)
+    }
+
+    // Removes the device from the running VM and, if it is one of the instance's devices, from
+    // the instance. Returns whether the instance had a device with that id.
+    pub fn unplug(&mut self, api: &ApiClient, id: &str) -> Result<bool, ApiError> {
+        api.vm_remove_device(id)?;
+
+        let mut removed = false;
+        removed |= remove_by_id(&mut self.disk, id, |d| d.id.as_ref());
+        removed |= remove_by_id(&mut self.net, id, |d| d.id.as_ref());
+        removed |= remove_by_id(&mut self.fs, id, |d| d.id.as_ref());
+        removed |= remove_by_id(&mut self.pmem, id, |d| d.id.as_ref());
+        removed |= remove_by_id(&mut self.device, id, |d| d.id.as_ref());
+        removed |= remove_by_id(&mut self.user_device, id, |d| d.id.as_ref());
+        removed |= remove_by_id(&mut self.vdpa, id, |d| d.id.as_ref());
+        if self.vsock.as_ref().and_then(|vsock| vsock.id.as_deref()) == Some(id) {
+            self.vsock = None;
+            removed = true;
+        }
+        Ok(removed)
+    }
+}
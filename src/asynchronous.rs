@@ -0,0 +1,504 @@
+// Async counterparts of `api::ApiClient` and `process::VmProcess` on tokio, sharing their
+// requests, response and error types.
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use bytesize::ByteSize;
+use serde::Serialize;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::process::{Child, ChildStderr, Command};
+use tokio::task::{self, JoinHandle};
+use tokio::time;
+
+use crate::api::{
+    check_status, parse_content_length, parse_status_line, request_head, ApiError, PciDeviceInfo,
+    Response, VmCounters, VmInfo, VmState, VmmPingResponse,
+};
+use crate::endpoints::{self, ApiRequest};
+use crate::event_monitor::EventReader;
+use crate::process::{InheritedFds, SpawnError, VmExit, STDERR_TAIL};
+use crate::ready::{
+    has_event, log_tail, pipe_has_event, Backoff, ReadyCondition, ReadyError, ReadyStage,
+};
+use crate::resize::VmResize;
+use crate::{
+    CloudHypervisorInstance, Device, Disk, Fs, Net, PathOrFileDescriptorOption, Pmem, UserDevice,
+    Vdpa, Vsock,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsyncApiClient {
+    socket: PathBuf,
+    timeout: Option<Duration>,
+}
+
+impl AsyncApiClient {
+    pub fn from_path(socket: impl Into<PathBuf>) -> Self {
+        AsyncApiClient {
+            socket: socket.into(),
+            timeout: None,
+        }
+    }
+
+    // Bounds each request as a whole.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    pub async fn vmm_ping(&self) -> Result<VmmPingResponse, ApiError> {
+        self.send(endpoints::vmm_ping()).await
+    }
+
+    pub async fn vmm_shutdown(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vmm_shutdown()).await
+    }
+
+    pub async fn vm_create<T: Serialize>(&self, config: &T) -> Result<(), ApiError> {
+        self.send(endpoints::vm_create(config)?).await
+    }
+
+    pub async fn vm_boot(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_boot()).await
+    }
+
+    pub async fn vm_shutdown(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_shutdown()).await
+    }
+
+    pub async fn vm_reboot(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_reboot()).await
+    }
+
+    pub async fn vm_pause(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_pause()).await
+    }
+
+    pub async fn vm_resume(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_resume()).await
+    }
+
+    pub async fn vm_power_button(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_power_button()).await
+    }
+
+    pub async fn vm_delete(&self) -> Result<(), ApiError> {
+        self.send(endpoints::vm_delete()).await
+    }
+
+    pub async fn vm_info(&self) -> Result<VmInfo, ApiError> {
+        self.send(endpoints::vm_info()).await
+    }
+
+    pub async fn vm_counters(&self) -> Result<VmCounters, ApiError> {
+        self.send(endpoints::vm_counters()).await
+    }
+
+    pub async fn vm_add_disk(&self, disk: &Disk) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_disk(disk)?).await
+    }
+
+    pub async fn vm_add_net(&self, net: &Net) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_net(net)?).await
+    }
+
+    pub async fn vm_add_fs(&self, fs: &Fs) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_fs(fs)?).await
+    }
+
+    pub async fn vm_add_pmem(&self, pmem: &Pmem) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_pmem(pmem)?).await
+    }
+
+    pub async fn vm_add_device(&self, device: &Device) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_device(device)?).await
+    }
+
+    pub async fn vm_add_user_device(
+        &self,
+        user_device: &UserDevice,
+    ) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_user_device(user_device)?).await
+    }
+
+    pub async fn vm_add_vdpa(&self, vdpa: &Vdpa) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_vdpa(vdpa)?).await
+    }
+
+    pub async fn vm_add_vsock(&self, vsock: &Vsock) -> Result<PciDeviceInfo, ApiError> {
+        self.send(endpoints::vm_add_vsock(vsock)?).await
+    }
+
+    pub async fn vm_remove_device(&self, id: &str) -> Result<(), ApiError> {
+        self.send(endpoints::vm_remove_device(id)?).await
+    }
+
+    pub async fn vm_resize(&self, resize: &VmResize) -> Result<(), ApiError> {
+        self.send(endpoints::vm_resize(resize)?).await
+    }
+
+    pub async fn vm_resize_zone(&self, id: &str, desired_ram: ByteSize) -> Result<(), ApiError> {
+        self.send(endpoints::vm_resize_zone(id, desired_ram)?).await
+    }
+
+    pub async fn vm_snapshot(&self, destination_url: &str) -> Result<(), ApiError> {
+        self.send(endpoints::vm_snapshot(destination_url)?).await
+    }
+
+    pub async fn vm_restore(&self, source_url: &str, prefault: bool) -> Result<(), ApiError> {
+        self.send(endpoints::vm_restore(source_url, prefault)?)
+            .await
+    }
+
+    pub async fn vm_receive_migration(&self, receiver_url: &str) -> Result<(), ApiError> {
+        self.send(endpoints::vm_receive_migration(receiver_url)?)
+            .await
+    }
+
+    pub async fn vm_send_migration(
+        &self,
+        destination_url: &str,
+        local: bool,
+    ) -> Result<(), ApiError> {
+        self.send(endpoints::vm_send_migration(destination_url, local)?)
+            .await
+    }
+
+    async fn send<R>(&self, request: ApiRequest<R>) -> Result<R, ApiError> {
+        let response = async {
+            let mut stream = UnixStream::connect(&self.socket).await?;
+            stream
+                .write_all(request_head(request.method, request.endpoint, &request.body).as_bytes())
+                .await?;
+            stream.write_all(&request.body).await?;
+            check_status(read_response(BufReader::new(stream)).await?)
+        };
+        let response = match self.timeout {
+            Some(timeout) => time::timeout(timeout, response)
+                .await
+                .map_err(|_| ApiError::Io(io::ErrorKind::TimedOut.into()))?,
+            None => response.await,
+        }?;
+        request.decode(&response.body)
+    }
+}
+
+async fn read_response(mut reader: impl AsyncBufRead + Unpin) -> Result<Response, ApiError> {
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).await?;
+    let (status, reason) = parse_status_line(&status_line)?;
+
+    let mut content_length = 0usize;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            return Err(ApiError::InvalidResponse("truncated headers".to_string()));
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(length) = parse_content_length(header)? {
+            content_length = length;
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Response {
+        status,
+        reason,
+        body,
+    })
+}
+
+#[derive(Debug)]
+pub struct AsyncVmProcess {
+    child: Child,
+    pid: u32,
+    api_socket: Option<PathBuf>,
+    log_file: Option<PathBuf>,
+    event_monitor: Option<PathBuf>,
+    events: Option<EventReader<File>>,
+    stderr: Option<JoinHandle<Vec<u8>>>,
+    exit: Option<VmExit>,
+}
+
+impl CloudHypervisorInstance {
+    // Must be called from within a tokio runtime.
+    pub fn spawn_async(&self, fds: InheritedFds) -> Result<AsyncVmProcess, SpawnError> {
+        let (cmd, events) = self.to_spawn_command(fds)?;
+        let mut cmd = Command::from(cmd);
+        let mut child = cmd
+            .stdin(self.spawn_stdin())
+            .stderr(std::process::Stdio::piped())
+            .spawn()?;
+        drop(cmd);
+        let pid = child.id().unwrap_or_default();
+        let stderr = child.stderr.take().map(|stderr| {
+            tokio::spawn(async move {
+                let mut tail = vec![];
+                let _ = read_tail(stderr, &mut tail).await;
+                tail
+            })
+        });
+
+        Ok(AsyncVmProcess {
+            child,
+            pid,
+            api_socket: match &self.api_socket {
+                Some(PathOrFileDescriptorOption::Path(path)) => Some(path.clone()),
+                _ => None,
+            },
+            log_file: self.log_file.clone(),
+            event_monitor: match &self.event_monitor {
+                Some(PathOrFileDescriptorOption::Path(path)) => Some(path.clone()),
+                _ => None,
+            },
+            events,
+            stderr,
+            exit: None,
+        })
+    }
+}
+
+async fn read_tail(mut stderr: ChildStderr, tail: &mut Vec<u8>) -> io::Result<()> {
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = stderr.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        tail.extend_from_slice(&buf[..n]);
+        if tail.len() > STDERR_TAIL {
+            tail.drain(..tail.len() - STDERR_TAIL);
+        }
+    }
+}
+
+impl AsyncVmProcess {
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    // Only known when `--api-socket` was given a path, not an fd.
+    pub fn api_socket(&self) -> Option<&Path> {
+        self.api_socket.as_deref()
+    }
+
+    pub fn log_file(&self) -> Option<&Path> {
+        self.log_file.as_deref()
+    }
+
+    // Only known when `--event-monitor` was given a path, not an fd.
+    pub fn event_monitor(&self) -> Option<&Path> {
+        self.event_monitor.as_deref()
+    }
+
+    // See `VmProcess::take_events()`. Reads block, e.g. use it from `spawn_blocking`.
+    pub fn take_events(&mut self) -> Option<EventReader<File>> {
+        self.events.take()
+    }
+
+    pub fn api_client(&self) -> Option<AsyncApiClient> {
+        self.api_socket.as_ref().map(AsyncApiClient::from_path)
+    }
+
+    async fn exited(&mut self, status: ExitStatus) -> VmExit {
+        if let Some(exit) = &self.exit {
+            return exit.clone();
+        }
+        let stderr = match self.stderr.take() {
+            Some(stderr) => stderr.await.unwrap_or_default(),
+            None => vec![],
+        };
+        let exit = VmExit::new(status, stderr);
+        self.exit = Some(exit.clone());
+        exit
+    }
+
+    pub async fn wait(&mut self) -> io::Result<VmExit> {
+        let status = self.child.wait().await?;
+        Ok(self.exited(status).await)
+    }
+
+    pub async fn try_wait(&mut self) -> io::Result<Option<VmExit>> {
+        match self.child.try_wait()? {
+            Some(status) => Ok(Some(self.exited(status).await)),
+            None => Ok(None),
+        }
+    }
+
+    async fn wait_timeout(&mut self, timeout: Duration) -> io::Result<Option<VmExit>> {
+        match time::timeout(timeout, self.wait()).await {
+            Ok(exit) => exit.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    // Sends SIGKILL without waiting for the process to exit.
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.child.start_kill()
+    }
+
+    fn terminate(&mut self) -> io::Result<()> {
+        if self.child.try_wait()?.is_some() {
+            return Ok(());
+        }
+        if unsafe { libc::kill(self.pid as libc::pid_t, libc::SIGTERM) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // Like `VmProcess::shutdown`: the API first, then SIGTERM and finally SIGKILL.
+    pub async fn shutdown(&mut self, timeout: Duration) -> io::Result<VmExit> {
+        if let Some(api) = self.api_client() {
+            if api.timeout(timeout).vmm_shutdown().await.is_ok() {
+                if let Some(exit) = self.wait_timeout(timeout).await? {
+                    return Ok(exit);
+                }
+            }
+        }
+
+        self.terminate()?;
+        if let Some(exit) = self.wait_timeout(timeout).await? {
+            return Ok(exit);
+        }
+
+        self.kill()?;
+        self.wait().await
+    }
+
+    // Waits until the VMM answers `vmm.ping`, retrying with a growing delay.
+    pub async fn wait_ready(&mut self, timeout: Duration) -> Result<VmmPingResponse, ReadyError> {
+        self.wait_ready_for(timeout, &ReadyCondition::Api).await
+    }
+
+    // Like `VmProcess::wait_ready_for`, except that a missing api socket counts as the api not
+    // answering yet. Events are read on the blocking pool; dropping the future while it waits for
+    // an event on the pipe from `event_monitor_pipe(true)` drops the pipe's reader with it.
+    pub async fn wait_ready_for(
+        &mut self,
+        timeout: Duration,
+        condition: &ReadyCondition,
+    ) -> Result<VmmPingResponse, ReadyError> {
+        let api = self.api_client().ok_or(ReadyError::NoApiSocket)?;
+        let event_monitor = match condition {
+            ReadyCondition::Event { .. } if self.events.is_none() => Some(
+                self.event_monitor()
+                    .ok_or(ReadyError::NoEventMonitor)?
+                    .to_path_buf(),
+            ),
+            _ => None,
+        };
+        let mut backoff = Backoff::new(timeout);
+
+        let mut last_error = None;
+        let ping = loop {
+            let remaining = self
+                .remaining(&backoff, ReadyStage::Api, &mut last_error)
+                .await?;
+            match api.clone().timeout(remaining).vmm_ping().await {
+                Ok(ping) => break ping,
+                Err(err) => last_error = Some(err),
+            }
+            time::sleep(backoff.next_delay()).await;
+        };
+
+        match (condition, event_monitor) {
+            (ReadyCondition::VmRunning, _) => {
+                let mut last_error = None;
+                loop {
+                    let remaining = self
+                        .remaining(&backoff, ReadyStage::VmRunning, &mut last_error)
+                        .await?;
+                    match api.clone().timeout(remaining).vm_info().await {
+                        Ok(info) if info.state == VmState::Running => break,
+                        Ok(_) => {}
+                        Err(err) => last_error = Some(err),
+                    }
+                    time::sleep(backoff.next_delay()).await;
+                }
+            }
+            (ReadyCondition::Event { source, event }, Some(path)) => {
+                let mut events = None;
+                loop {
+                    self.remaining(&backoff, ReadyStage::Event, &mut None)
+                        .await?;
+                    let (path, source, event) = (path.clone(), source.clone(), event.clone());
+                    let (reader, found) = blocking(move || {
+                        let found = has_event(&mut events, &path, &source, &event);
+                        (events, found)
+                    })
+                    .await?;
+                    events = reader;
+                    if found? {
+                        break;
+                    }
+                    time::sleep(backoff.next_delay()).await;
+                }
+            }
+            (ReadyCondition::Event { source, event }, None) => loop {
+                self.remaining(&backoff, ReadyStage::Event, &mut None)
+                    .await?;
+                if let Some(mut pipe) = self.events.take() {
+                    let (source, event) = (source.clone(), event.clone());
+                    let (pipe, found) = blocking(move || {
+                        let found = pipe_has_event(&mut pipe, &source, &event);
+                        (pipe, found)
+                    })
+                    .await?;
+                    self.events = Some(pipe);
+                    if found? {
+                        break;
+                    }
+                }
+                time::sleep(backoff.next_delay()).await;
+            },
+            _ => {}
+        }
+
+        Ok(ping)
+    }
+
+    // The time left for the next attempt, failing once the process exited or the time is up.
+    async fn remaining(
+        &mut self,
+        backoff: &Backoff,
+        waiting_for: ReadyStage,
+        last_error: &mut Option<ApiError>,
+    ) -> Result<Duration, ReadyError> {
+        if let Some(exit) = self.try_wait().await? {
+            return Err(ReadyError::Exited(exit));
+        }
+        let remaining = backoff.remaining();
+        if remaining.is_zero() {
+            return Err(ReadyError::Timeout {
+                waiting_for,
+                last_error: last_error.take().map(Box::new),
+                log: log_tail(self.log_file()),
+            });
+        }
+        Ok(remaining)
+    }
+}
+
+// Reading an event file or pipe is done with blocking calls, which must not hold up the runtime.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, ReadyError> {
+    task::spawn_blocking(f)
+        .await
+        .map_err(|err| ReadyError::Io(io::Error::other(err)))
+}
@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::encode::{lossy_command, Encoder};
+use crate::error::Error;
+use crate::from_command::split_commas;
+use crate::version::{ChVersion, InvalidVersion};
+use crate::CloudHypervisorInstance;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub version: ChVersion,
+    // Every flag listed by `--help`, with the `key=` names its help text mentions.
+    pub options: BTreeMap<String, Vec<String>>,
+    // Flags listed with a `<value>` placeholder; the argument after them is never a flag.
+    pub takes_value: BTreeSet<String>,
+}
+
+#[derive(Debug)]
+pub enum ProbeError {
+    Spawn(io::Error),
+    Failed { arg: String, stderr: String },
+    Version(InvalidVersion),
+}
+
+impl Display for ProbeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeError::Spawn(err) => write!(f, "cannot run cloud-hypervisor: {}", err),
+            ProbeError::Failed { arg, stderr } => {
+                write!(f, "cloud-hypervisor {} failed: {}", arg, stderr.trim())
+            }
+            ProbeError::Version(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProbeError::Spawn(err) => Some(err),
+            ProbeError::Version(err) => Some(err),
+            ProbeError::Failed { .. } => None,
+        }
+    }
+}
+
+fn run(bin_path: &Path, arg: &str) -> Result<String, ProbeError> {
+    let output = Command::new(bin_path)
+        .arg(arg)
+        .output()
+        .map_err(ProbeError::Spawn)?;
+    if !output.status.success() {
+        return Err(ProbeError::Failed {
+            arg: arg.to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl Capabilities {
+    pub fn probe(bin_path: &Path) -> Result<Self, ProbeError> {
+        let version = run(bin_path, "--version")?;
+        let help = run(bin_path, "--help")?;
+
+        let (options, takes_value) = parse_help(&help);
+        Ok(Capabilities {
+            version: parse_version(&version).map_err(ProbeError::Version)?,
+            options,
+            takes_value,
+        })
+    }
+
+    pub fn supports(&self, option: &str) -> bool {
+        self.options.contains_key(option)
+    }
+
+    pub fn supports_key(&self, option: &str, key: &str) -> bool {
+        self.options
+            .get(option)
+            .map(|keys| keys.iter().any(|k| k == key))
+            .unwrap_or(false)
+    }
+
+    // Flags whose help text mentions no keys (`--kernel`, `--cmdline`, ...) take free-form values,
+    // so only the keys of documented `key=value` options are checked.
+    pub fn check(&self, instance: &CloudHypervisorInstance) -> Result<(), Error> {
+        let args = instance
+            .render(Encoder::lossy())
+            .map(lossy_command)
+            .unwrap_or_default();
+        let mut option: Option<&str> = None;
+        // Values such as `--cmdline -- init=/bin/sh` may start with `-` themselves.
+        let mut expects_value = false;
+
+        for arg in args.iter().skip(1) {
+            if arg.starts_with('-') && !expects_value {
+                if !self.supports(arg) {
+                    return Err(self.unsupported(arg, None));
+                }
+                option = Some(arg);
+                expects_value = self.takes_value.contains(arg.as_str());
+                continue;
+            }
+            expects_value = false;
+
+            let option = match option {
+                Some(option) if !self.options[option].is_empty() && arg.contains('=') => option,
+                _ => continue,
+            };
+            for value in split_commas(option, arg.as_bytes())? {
+                let value = String::from_utf8_lossy(value);
+                let key = value.split('=').next().unwrap_or_default();
+                if value.contains('=') && !self.supports_key(option, key) {
+                    return Err(self.unsupported(option, Some(key)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unsupported(&self, option: &str, key: Option<&str>) -> Error {
+        Error::Unsupported {
+            option: option.to_string(),
+            key: key.map(str::to_string),
+            version: self.version,
+            added: None,
+            removed: None,
+        }
+    }
+}
+
+impl CloudHypervisorInstance {
+    pub fn probe_capabilities(&self) -> Result<Capabilities, ProbeError> {
+        Capabilities::probe(&self.bin_path)
+    }
+}
+
+// `cloud-hypervisor v40.0.0`, the version is the last word of the first line.
+fn parse_version(output: &str) -> Result<ChVersion, InvalidVersion> {
+    output
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().last())
+        .unwrap_or_default()
+        .parse()
+}
+
+// clap lists each flag on a line starting with `-` (`  -V, --version`, `      --cpus <cpus>`),
+// followed by indented help text.
+fn parse_help(help: &str) -> (BTreeMap<String, Vec<String>>, BTreeSet<String>) {
+    let mut options: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut takes_value = BTreeSet::new();
+    let mut current: Vec<String> = vec![];
+
+    for line in help.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('-') {
+            current = flags(trimmed);
+            let placeholder = trimmed
+                .split_whitespace()
+                .find(|word| !word.starts_with('-'))
+                .is_some_and(|word| word.starts_with('<'));
+            for flag in &current {
+                options.entry(flag.clone()).or_default();
+                if placeholder {
+                    takes_value.insert(flag.clone());
+                }
+            }
+        }
+
+        let keys = help_keys(trimmed);
+        for flag in &current {
+            let known = options.entry(flag.clone()).or_default();
+            for key in &keys {
+                if !known.contains(key) {
+                    known.push(key.clone());
+                }
+            }
+        }
+    }
+
+    (options, takes_value)
+}
+
+fn flags(line: &str) -> Vec<String> {
+    line.split_whitespace()
+        .map(|word| word.trim_end_matches(','))
+        .take_while(|word| word.starts_with('-'))
+        .map(|word| word.trim_end_matches("...").to_string())
+        .collect()
+}
+
+// Keys are the identifiers directly followed by `=`, as in `boot=<boot_vcpus>,max=<max_vcpus>`.
+fn help_keys(text: &str) -> Vec<String> {
+    let mut keys = vec![];
+    let mut word = String::new();
+
+    for c in text.chars() {
+        match c {
+            'a'..='z' | '0'..='9' | '_' => word.push(c),
+            '=' if !word.is_empty() => keys.push(std::mem::take(&mut word)),
+            _ => word.clear(),
+        }
+    }
+
+    keys
+}
@@ -0,0 +1,235 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use bytesize::ByteSize;
+
+use crate::encode::{lossy_command, Encoder, ToOptionValue};
+use crate::error::Error;
+use crate::resize::{ResizeError, VmResize};
+use crate::to_command::{ToCommand, TryToCommand};
+use crate::version::ChVersion;
+use crate::{
+    CloudHypervisorInstance, Device, Disk, Fs, Net, PathOrFileDescriptorOption, Pmem, Restore,
+    UserDevice, Vdpa, Vsock,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChRemoteCommand {
+    AddDevice(Device),
+    AddDisk(Disk),
+    AddFs(Fs),
+    AddNet(Net),
+    AddPmem(Pmem),
+    AddUserDevice(UserDevice),
+    AddVdpa(Vdpa),
+    AddVsock(Vsock),
+    Boot,
+    Coredump(PathBuf),
+    Counters,
+    // Path to a JSON `VmConfig`
+    Create(PathBuf),
+    Delete,
+    Info,
+    Nmi,
+    Pause,
+    Ping,
+    PowerButton,
+    Reboot,
+    ReceiveMigration(String),
+    RemoveDevice(String),
+    Resize(VmResize),
+    ResizeZone {
+        id: String,
+        size: ByteSize,
+    },
+    Restore(Restore),
+    Resume,
+    SendMigration {
+        destination_url: String,
+        local: bool,
+    },
+    Shutdown,
+    ShutdownVmm,
+    Snapshot(String),
+}
+
+impl ChRemoteCommand {
+    fn name(&self) -> &'static str {
+        match self {
+            ChRemoteCommand::AddDevice(_) => "add-device",
+            ChRemoteCommand::AddDisk(_) => "add-disk",
+            ChRemoteCommand::AddFs(_) => "add-fs",
+            ChRemoteCommand::AddNet(_) => "add-net",
+            ChRemoteCommand::AddPmem(_) => "add-pmem",
+            ChRemoteCommand::AddUserDevice(_) => "add-user-device",
+            ChRemoteCommand::AddVdpa(_) => "add-vdpa",
+            ChRemoteCommand::AddVsock(_) => "add-vsock",
+            ChRemoteCommand::Boot => "boot",
+            ChRemoteCommand::Coredump(_) => "coredump",
+            ChRemoteCommand::Counters => "counters",
+            ChRemoteCommand::Create(_) => "create",
+            ChRemoteCommand::Delete => "delete",
+            ChRemoteCommand::Info => "info",
+            ChRemoteCommand::Nmi => "nmi",
+            ChRemoteCommand::Pause => "pause",
+            ChRemoteCommand::Ping => "ping",
+            ChRemoteCommand::PowerButton => "power-button",
+            ChRemoteCommand::Reboot => "reboot",
+            ChRemoteCommand::ReceiveMigration(_) => "receive-migration",
+            ChRemoteCommand::RemoveDevice(_) => "remove-device",
+            ChRemoteCommand::Resize(_) => "resize",
+            ChRemoteCommand::ResizeZone { .. } => "resize-zone",
+            ChRemoteCommand::Restore(_) => "restore",
+            ChRemoteCommand::Resume => "resume",
+            ChRemoteCommand::SendMigration { .. } => "send-migration",
+            ChRemoteCommand::Shutdown => "shutdown",
+            ChRemoteCommand::ShutdownVmm => "shutdown-vmm",
+            ChRemoteCommand::Snapshot(_) => "snapshot",
+        }
+    }
+}
+
+// A `ch-remote` invocation. The add-* and restore arguments are rendered like the matching
+// cloud-hypervisor options.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChRemote {
+    bin_path: PathBuf,
+    api_socket: PathBuf,
+    command: ChRemoteCommand,
+    target_version: Option<ChVersion>,
+}
+
+impl ChRemote {
+    pub fn new(
+        bin_path: impl Into<PathBuf>,
+        api_socket: impl Into<PathBuf>,
+        command: ChRemoteCommand,
+    ) -> Self {
+        ChRemote {
+            bin_path: bin_path.into(),
+            api_socket: api_socket.into(),
+            command,
+            target_version: None,
+        }
+    }
+
+    pub fn target_version(mut self, target_version: ChVersion) -> Self {
+        self.target_version = Some(target_version);
+        self
+    }
+
+    pub fn command(&self) -> &ChRemoteCommand {
+        &self.command
+    }
+
+    fn render(&self, encoder: Encoder) -> Result<Vec<OsString>, Error> {
+        let encoder = encoder.with_version(self.target_version);
+        let name = self.command.name();
+        let mut cmd: Vec<OsString> = vec![
+            self.bin_path.clone().into_os_string(),
+            "--api-socket".into(),
+            self.api_socket.clone().into_os_string(),
+            name.into(),
+        ];
+
+        let config = match &self.command {
+            ChRemoteCommand::AddDevice(device) => device.to_option_value(encoder)?,
+            ChRemoteCommand::AddDisk(disk) => disk.to_option_value(encoder)?,
+            ChRemoteCommand::AddFs(fs) => fs.to_option_value(encoder)?,
+            ChRemoteCommand::AddNet(net) => net.to_option_value(encoder)?,
+            ChRemoteCommand::AddPmem(pmem) => pmem.to_option_value(encoder)?,
+            ChRemoteCommand::AddUserDevice(user_device) => user_device.to_option_value(encoder)?,
+            ChRemoteCommand::AddVdpa(vdpa) => vdpa.to_option_value(encoder)?,
+            ChRemoteCommand::AddVsock(vsock) => vsock.to_option_value(encoder)?,
+            ChRemoteCommand::Restore(restore) => restore.to_option_value(encoder)?,
+            ChRemoteCommand::Coredump(path) | ChRemoteCommand::Create(path) => {
+                cmd.push(path.clone().into_os_string());
+                return Ok(cmd);
+            }
+            ChRemoteCommand::ReceiveMigration(url)
+            | ChRemoteCommand::RemoveDevice(url)
+            | ChRemoteCommand::Snapshot(url) => {
+                cmd.push(url.into());
+                return Ok(cmd);
+            }
+            ChRemoteCommand::SendMigration {
+                destination_url,
+                local,
+            } => {
+                if *local {
+                    cmd.push("--local".into());
+                }
+                cmd.push(destination_url.into());
+                return Ok(cmd);
+            }
+            ChRemoteCommand::Resize(resize) => {
+                if encoder.is_strict() && resize == &VmResize::default() {
+                    return Err(Error::Resize(ResizeError::Empty));
+                }
+                if let Some(vcpus) = resize.desired_vcpus {
+                    cmd.push("--cpus".into());
+                    cmd.push(vcpus.to_string().into());
+                }
+                if let Some(ram) = resize.desired_ram {
+                    cmd.push("--memory".into());
+                    cmd.push(ram.as_u64().to_string().into());
+                }
+                if let Some(balloon) = resize.desired_balloon {
+                    cmd.push("--balloon".into());
+                    cmd.push(balloon.as_u64().to_string().into());
+                }
+                return Ok(cmd);
+            }
+            ChRemoteCommand::ResizeZone { id, size } => {
+                cmd.push("--id".into());
+                cmd.push(id.into());
+                cmd.push("--size".into());
+                cmd.push(size.as_u64().to_string().into());
+                return Ok(cmd);
+            }
+            _ => return Ok(cmd),
+        };
+
+        match config {
+            Some(config) => cmd.push(config.into()),
+            None if encoder.is_strict() => {
+                return Err(Error::EmptyOption {
+                    option: name.to_string(),
+                })
+            }
+            None => {}
+        }
+        Ok(cmd)
+    }
+}
+
+impl ToCommand for ChRemote {
+    fn to_command(&self) -> Vec<String> {
+        self.render(Encoder::lossy())
+            .map(lossy_command)
+            .unwrap_or_default()
+    }
+}
+
+impl TryToCommand for ChRemote {
+    fn try_to_command(&self) -> Result<Vec<OsString>, Error> {
+        self.render(Encoder::strict())
+    }
+}
+
+impl CloudHypervisorInstance {
+    // `ch-remote` at `bin_path` driving this instance, `None` without an `--api-socket` path.
+    pub fn ch_remote(
+        &self,
+        bin_path: impl Into<PathBuf>,
+        command: ChRemoteCommand,
+    ) -> Option<ChRemote> {
+        match &self.api_socket {
+            Some(PathOrFileDescriptorOption::Path(api_socket)) => Some(ChRemote {
+                target_version: self.target_version,
+                ..ChRemote::new(bin_path, api_socket, command)
+            }),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::api::{ApiClient, ApiError, VmCounters};
+
+const METRIC_PREFIX: &str = "cloud_hypervisor";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockCounters {
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub read_ops: u64,
+    pub write_ops: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NetCounters {
+    pub rx_bytes: u64,
+    pub rx_frames: u64,
+    pub tx_bytes: u64,
+    pub tx_frames: u64,
+}
+
+// Devices are told apart by their counter names as `vm.counters` does not report the device type.
+// The block latency gauges are dropped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceCounters {
+    Block(BlockCounters),
+    Net(NetCounters),
+    Other(BTreeMap<String, u64>),
+}
+
+impl DeviceCounters {
+    fn new(counters: &BTreeMap<String, u64>) -> Self {
+        let value = |name: &str| counters.get(name).copied().unwrap_or_default();
+        if counters.contains_key("read_bytes") {
+            DeviceCounters::Block(BlockCounters {
+                read_bytes: value("read_bytes"),
+                write_bytes: value("write_bytes"),
+                read_ops: value("read_ops"),
+                write_ops: value("write_ops"),
+            })
+        } else if counters.contains_key("rx_bytes") {
+            DeviceCounters::Net(NetCounters {
+                rx_bytes: value("rx_bytes"),
+                rx_frames: value("rx_frames"),
+                tx_bytes: value("tx_bytes"),
+                tx_frames: value("tx_frames"),
+            })
+        } else {
+            DeviceCounters::Other(counters.clone())
+        }
+    }
+
+    // Counter name and value pairs in `vm.counters` naming.
+    pub fn values(&self) -> Vec<(&str, u64)> {
+        match self {
+            DeviceCounters::Block(block) => vec![
+                ("read_bytes", block.read_bytes),
+                ("write_bytes", block.write_bytes),
+                ("read_ops", block.read_ops),
+                ("write_ops", block.write_ops),
+            ],
+            DeviceCounters::Net(net) => vec![
+                ("rx_bytes", net.rx_bytes),
+                ("rx_frames", net.rx_frames),
+                ("tx_bytes", net.tx_bytes),
+                ("tx_frames", net.tx_frames),
+            ],
+            DeviceCounters::Other(counters) => counters
+                .iter()
+                .map(|(name, value)| (name.as_str(), *value))
+                .collect(),
+        }
+    }
+
+    fn since(&self, earlier: &DeviceCounters) -> DeviceCounters {
+        // A counter that went backwards belongs to a device that was re-added, so it counts from 0.
+        let delta = |later: u64, earlier: u64| later.checked_sub(earlier).unwrap_or(later);
+        match (self, earlier) {
+            (DeviceCounters::Block(later), DeviceCounters::Block(earlier)) => {
+                DeviceCounters::Block(BlockCounters {
+                    read_bytes: delta(later.read_bytes, earlier.read_bytes),
+                    write_bytes: delta(later.write_bytes, earlier.write_bytes),
+                    read_ops: delta(later.read_ops, earlier.read_ops),
+                    write_ops: delta(later.write_ops, earlier.write_ops),
+                })
+            }
+            (DeviceCounters::Net(later), DeviceCounters::Net(earlier)) => {
+                DeviceCounters::Net(NetCounters {
+                    rx_bytes: delta(later.rx_bytes, earlier.rx_bytes),
+                    rx_frames: delta(later.rx_frames, earlier.rx_frames),
+                    tx_bytes: delta(later.tx_bytes, earlier.tx_bytes),
+                    tx_frames: delta(later.tx_frames, earlier.tx_frames),
+                })
+            }
+            (DeviceCounters::Other(later), DeviceCounters::Other(earlier)) => {
+                DeviceCounters::Other(
+                    later
+                        .iter()
+                        .map(|(name, value)| {
+                            let earlier = earlier.get(name).copied().unwrap_or_default();
+                            (name.clone(), delta(*value, earlier))
+                        })
+                        .collect(),
+                )
+            }
+            (later, _) => later.clone(),
+        }
+    }
+}
+
+// One `vm.counters` reading, keyed by device id.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CounterSample {
+    pub taken_at: Instant,
+    pub devices: BTreeMap<String, DeviceCounters>,
+}
+
+impl CounterSample {
+    pub fn new(counters: &VmCounters, taken_at: Instant) -> Self {
+        CounterSample {
+            taken_at,
+            devices: counters
+                .iter()
+                .map(|(id, counters)| (id.clone(), DeviceCounters::new(counters)))
+                .collect(),
+        }
+    }
+
+    pub fn disk(&self, id: &str) -> Option<&BlockCounters> {
+        match self.devices.get(id) {
+            Some(DeviceCounters::Block(block)) => Some(block),
+            _ => None,
+        }
+    }
+
+    pub fn net(&self, id: &str) -> Option<&NetCounters> {
+        match self.devices.get(id) {
+            Some(DeviceCounters::Net(net)) => Some(net),
+            _ => None,
+        }
+    }
+
+    // What was counted between `earlier` and this sample. Devices that appeared in between count
+    // from 0, devices that went away are left out.
+    pub fn since(&self, earlier: &CounterSample) -> CounterDelta {
+        CounterDelta {
+            elapsed: self.taken_at.saturating_duration_since(earlier.taken_at),
+            devices: self
+                .devices
+                .iter()
+                .map(|(id, later)| {
+                    let delta = match earlier.devices.get(id) {
+                        Some(earlier) => later.since(earlier),
+                        None => later.clone(),
+                    };
+                    (id.clone(), delta)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_prometheus(&self, vm: &str) -> String {
+        to_prometheus([(vm, self)])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CounterDelta {
+    pub elapsed: Duration,
+    pub devices: BTreeMap<String, DeviceCounters>,
+}
+
+impl CounterDelta {
+    pub fn disk(&self, id: &str) -> Option<&BlockCounters> {
+        match self.devices.get(id) {
+            Some(DeviceCounters::Block(block)) => Some(block),
+            _ => None,
+        }
+    }
+
+    pub fn net(&self, id: &str) -> Option<&NetCounters> {
+        match self.devices.get(id) {
+            Some(DeviceCounters::Net(net)) => Some(net),
+            _ => None,
+        }
+    }
+
+    // `value` per second over the elapsed time, 0 when no time passed.
+    pub fn rate(&self, value: u64) -> f64 {
+        let elapsed = self.elapsed.as_secs_f64();
+        if elapsed == 0.0 {
+            return 0.0;
+        }
+        value as f64 / elapsed
+    }
+
+    // Device id -> counter name -> rate per second
+    pub fn rates(&self) -> BTreeMap<String, BTreeMap<String, f64>> {
+        self.devices
+            .iter()
+            .map(|(id, counters)| {
+                let rates = counters
+                    .values()
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), self.rate(value)))
+                    .collect();
+                (id.clone(), rates)
+            })
+            .collect()
+    }
+}
+
+impl ApiClient {
+    pub fn vm_counter_sample(&self) -> Result<CounterSample, ApiError> {
+        let counters = self.vm_counters()?;
+        Ok(CounterSample::new(&counters, Instant::now()))
+    }
+}
+
+// Renders the samples of several VMs, labelled by VM name, in the Prometheus text exposition
+// format. Block and net counters become `cloud_hypervisor_{block,net}_<name>_total` counters,
+// everything else `cloud_hypervisor_device_counter` with a `counter` label.
+pub fn to_prometheus<'a>(
+    samples: impl IntoIterator<Item = (&'a str, &'a CounterSample)>,
+) -> String {
+    // Metric name -> (type, samples); all samples of a metric have to follow its TYPE line.
+    let mut metrics: BTreeMap<String, (&str, Vec<String>)> = BTreeMap::new();
+    for (vm, sample) in samples {
+        for (id, counters) in &sample.devices {
+            let labels = format!(
+                "vm=\"{}\",device=\"{}\"",
+                escape_label(vm),
+                escape_label(id)
+            );
+            for (name, value) in counters.values() {
+                let (metric, kind, labels) = match counters {
+                    DeviceCounters::Block(_) => (
+                        format!("{}_block_{}_total", METRIC_PREFIX, name),
+                        "counter",
+                        labels.clone(),
+                    ),
+                    DeviceCounters::Net(_) => (
+                        format!("{}_net_{}_total", METRIC_PREFIX, name),
+                        "counter",
+                        labels.clone(),
+                    ),
+                    DeviceCounters::Other(_) => (
+                        format!("{}_device_counter", METRIC_PREFIX),
+                        "untyped",
+                        format!("{},counter=\"{}\"", labels, escape_label(name)),
+                    ),
+                };
+                metrics
+                    .entry(metric)
+                    .or_insert_with(|| (kind, vec![]))
+                    .1
+                    .push(format!("{{{}}} {}", labels, value));
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for (metric, (kind, samples)) in metrics {
+        let _ = writeln!(out, "# TYPE {} {}", metric, kind);
+        for sample in samples {
+            let _ = writeln!(out, "{}{}", metric, sample);
+        }
+    }
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,132 @@
+use std::ffi::{OsStr, OsString};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::from_command::ParseError;
+use crate::{CloudHypervisorInstance, PathOrFileDescriptorOption};
+
+const BINARY_NAME: &str = "cloud-hypervisor";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunningInstance {
+    pub pid: u32,
+    pub instance: CloudHypervisorInstance,
+    pub api_socket: Option<PathOrFileDescriptorOption>,
+    pub log_file: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum DiscoveryError {
+    // An entry of the proc directory could not be read.
+    Io(io::Error),
+    Parse {
+        pid: u32,
+        args: Vec<OsString>,
+        error: ParseError,
+    },
+}
+
+impl DiscoveryError {
+    // `None` for entries that could not be read.
+    pub fn pid(&self) -> Option<u32> {
+        match self {
+            DiscoveryError::Io(_) => None,
+            DiscoveryError::Parse { pid, .. } => Some(*pid),
+        }
+    }
+}
+
+impl Display for DiscoveryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiscoveryError::Io(err) => write!(f, "{}", err),
+            DiscoveryError::Parse { pid, error, .. } => write!(f, "pid {}: {}", pid, error),
+        }
+    }
+}
+
+impl std::error::Error for DiscoveryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DiscoveryError::Io(err) => Some(err),
+            DiscoveryError::Parse { error, .. } => Some(error),
+        }
+    }
+}
+
+pub fn running_instances() -> io::Result<Vec<Result<RunningInstance, DiscoveryError>>> {
+    running_instances_in(Path::new("/proc"))
+}
+
+pub fn running_instances_in(
+    proc_root: &Path,
+) -> io::Result<Vec<Result<RunningInstance, DiscoveryError>>> {
+    let mut instances = vec![];
+
+    for entry in fs::read_dir(proc_root)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                instances.push(Err(DiscoveryError::Io(err)));
+                continue;
+            }
+        };
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+
+        // Processes can exit while we scan, skip anything that is no longer readable.
+        let cmdline = match fs::read(entry.path().join("cmdline")) {
+            Ok(cmdline) => cmdline,
+            Err(_) => continue,
+        };
+
+        let args = split_cmdline(&cmdline);
+        if !is_cloud_hypervisor(&args) {
+            continue;
+        }
+
+        instances.push(match CloudHypervisorInstance::from_os_command(&args) {
+            Ok(instance) => Ok(RunningInstance {
+                pid,
+                api_socket: instance.api_socket.clone(),
+                log_file: instance.log_file.clone(),
+                instance,
+            }),
+            Err(error) => Err(DiscoveryError::Parse { pid, args, error }),
+        });
+    }
+
+    instances.sort_by_key(|instance| match instance {
+        Ok(instance) => instance.pid,
+        Err(error) => error.pid().unwrap_or_default(),
+    });
+
+    Ok(instances)
+}
+
+fn split_cmdline(cmdline: &[u8]) -> Vec<OsString> {
+    let cmdline = cmdline.strip_suffix(&[0]).unwrap_or(cmdline);
+    if cmdline.is_empty() {
+        return vec![];
+    }
+    cmdline
+        .split(|b| *b == 0)
+        .map(|arg| OsStr::from_bytes(arg).to_os_string())
+        .collect()
+}
+
+fn is_cloud_hypervisor(args: &[OsString]) -> bool {
+    args.first()
+        .and_then(|arg0| Path::new(arg0).file_name())
+        .map(|name| name == BINARY_NAME)
+        .unwrap_or(false)
+}
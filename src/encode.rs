@@ -0,0 +1,310 @@
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use crate::error::Error;
+use crate::version::{self, ChVersion};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EncodeErrorReason {
+    ContainsQuote,
+    ContainsNul,
+    ListItemContains(char),
+    NotUtf8,
+    Empty,
+}
+
+impl Display for EncodeErrorReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodeErrorReason::ContainsQuote => write!(f, "values cannot contain `\"`"),
+            EncodeErrorReason::ContainsNul => write!(f, "values cannot contain NUL bytes"),
+            EncodeErrorReason::ListItemContains(c) => {
+                write!(f, "list items cannot contain `{}`", c)
+            }
+            EncodeErrorReason::NotUtf8 => write!(f, "paths must be valid UTF-8"),
+            EncodeErrorReason::Empty => write!(f, "value cannot be empty"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncodeError {
+    pub option: String,
+    pub id: Option<String>,
+    pub key: String,
+    pub value: String,
+    pub reason: EncodeErrorReason,
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.option)?;
+        if let Some(id) = &self.id {
+            write!(f, " (id={})", id)?;
+        }
+        write!(
+            f,
+            " {}: cannot encode `{}`: {}",
+            self.key, self.value, self.reason
+        )
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+// cloud-hypervisor splits option values on commas outside of brackets and quotes, and trims
+// whitespace around values. Anything that would be split or trimmed is wrapped in quotes, which
+// leaves `"` itself as the only character that cannot be represented.
+pub fn encode_value(value: &str) -> Result<Cow<'_, str>, EncodeErrorReason> {
+    if value.contains('"') {
+        return Err(EncodeErrorReason::ContainsQuote);
+    }
+    if value.contains('\0') {
+        return Err(EncodeErrorReason::ContainsNul);
+    }
+    if value.contains([',', '[', ']', '='])
+        || value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+    {
+        Ok(Cow::Owned(format!("\"{}\"", value)))
+    } else {
+        Ok(Cow::Borrowed(value))
+    }
+}
+
+// List items are split on every comma once the brackets are stripped, quotes do not protect them.
+pub fn encode_list<T: AsRef<str>>(items: &[T]) -> Result<String, EncodeErrorReason> {
+    for item in items {
+        let item = item.as_ref();
+        if let Some(c) = item.chars().find(|c| matches!(c, ',' | '[' | ']' | '"')) {
+            return Err(EncodeErrorReason::ListItemContains(c));
+        }
+        if item.contains('\0') {
+            return Err(EncodeErrorReason::ContainsNul);
+        }
+    }
+    Ok(format!(
+        "[{}]",
+        items
+            .iter()
+            .map(|item| item.as_ref())
+            .collect::<Vec<&str>>()
+            .join(",")
+    ))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Encoder {
+    strict: bool,
+    version: Option<ChVersion>,
+}
+
+impl Encoder {
+    // Renders unrepresentable values as-is, keeping `ToCommand` infallible.
+    pub(crate) fn lossy() -> Self {
+        Encoder {
+            strict: false,
+            version: None,
+        }
+    }
+
+    pub(crate) fn strict() -> Self {
+        Encoder {
+            strict: true,
+            version: None,
+        }
+    }
+
+    pub(crate) fn with_version(self, version: Option<ChVersion>) -> Self {
+        Encoder { version, ..self }
+    }
+
+    pub(crate) fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    // Lossy rendering keeps options the target release does not know about, like it keeps values
+    // it cannot encode.
+    pub(crate) fn check_supported(&self, option: &str, key: Option<&str>) -> Result<(), Error> {
+        let version = match self.version {
+            Some(version) if self.strict => version,
+            _ => return Ok(()),
+        };
+        match version::unavailable(version, option, key) {
+            None => Ok(()),
+            Some(availability) => Err(Error::Unsupported {
+                option: option.to_string(),
+                key: key.map(str::to_string),
+                version,
+                added: availability.added,
+                removed: availability.removed,
+            }),
+        }
+    }
+}
+
+pub(crate) struct OptionArgs {
+    encoder: Encoder,
+    option: &'static str,
+    id: Option<String>,
+    args: Vec<String>,
+}
+
+impl OptionArgs {
+    pub(crate) fn new(encoder: Encoder, option: &'static str) -> Self {
+        OptionArgs {
+            encoder,
+            option,
+            id: None,
+            args: vec![],
+        }
+    }
+
+    // Device options report their id in errors so the offending device can be found.
+    pub(crate) fn with_id(encoder: Encoder, option: &'static str, id: Option<&String>) -> Self {
+        OptionArgs {
+            id: id.cloned(),
+            ..Self::new(encoder, option)
+        }
+    }
+
+    fn error(&self, key: &str, value: String, reason: EncodeErrorReason) -> Error {
+        Error::Encode(EncodeError {
+            option: self.option.to_string(),
+            id: self.id.clone(),
+            key: key.to_string(),
+            value,
+            reason,
+        })
+    }
+
+    pub(crate) fn push(&mut self, key: &str, value: impl Display) -> Result<(), Error> {
+        self.encoder.check_supported(self.option, Some(key))?;
+        let value = value.to_string();
+        let encoded = match encode_value(&value) {
+            Ok(encoded) => encoded.into_owned(),
+            Err(reason) if self.encoder.strict => return Err(self.error(key, value, reason)),
+            Err(_) => value,
+        };
+        self.push_key(key, encoded);
+        Ok(())
+    }
+
+    pub(crate) fn push_list<T: Display>(&mut self, key: &str, items: &[T]) -> Result<(), Error> {
+        self.encoder.check_supported(self.option, Some(key))?;
+        let items = items.iter().map(|v| v.to_string()).collect::<Vec<String>>();
+        let encoded = match encode_list(&items) {
+            Ok(encoded) => encoded,
+            Err(reason) if self.encoder.strict => {
+                return Err(self.error(key, format!("[{}]", items.join(",")), reason))
+            }
+            Err(_) => format!("[{}]", items.join(",")),
+        };
+        self.push_key(key, encoded);
+        Ok(())
+    }
+
+    pub(crate) fn push_path(&mut self, key: &str, path: &Path) -> Result<(), Error> {
+        if self.encoder.strict {
+            if path.as_os_str().is_empty() {
+                return Err(self.error(key, String::new(), EncodeErrorReason::Empty));
+            }
+            if path.to_str().is_none() {
+                let value = path.display().to_string();
+                return Err(self.error(key, value, EncodeErrorReason::NotUtf8));
+            }
+        }
+        self.push(key, path.display())
+    }
+
+    fn push_key(&mut self, key: &str, encoded: String) {
+        self.args.push(format!("{}={}", key, encoded));
+    }
+
+    // For values produced by the crate itself (`pty`, `topology=1:2:3:4`, ...) that need no encoding.
+    pub(crate) fn push_raw(&mut self, arg: impl Into<String>) {
+        self.args.push(arg.into());
+    }
+
+    pub(crate) fn finish(self) -> Option<String> {
+        if self.args.is_empty() {
+            None
+        } else {
+            Some(self.args.join(","))
+        }
+    }
+}
+
+pub(crate) trait ToOptionValue {
+    const OPTION: &'static str;
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error>;
+}
+
+fn empty_option<T: ToOptionValue>(encoder: Encoder) -> Result<(), Error> {
+    if encoder.strict {
+        Err(Error::EmptyOption {
+            option: T::OPTION.to_string(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn push_value<T: ToOptionValue>(
+    cmd: &mut Vec<OsString>,
+    encoder: Encoder,
+    item: &T,
+) -> Result<(), Error> {
+    encoder.check_supported(T::OPTION, None)?;
+    match item.to_option_value(encoder)? {
+        Some(value) => {
+            cmd.push(T::OPTION.into());
+            cmd.push(value.into());
+        }
+        None => empty_option::<T>(encoder)?,
+    }
+    Ok(())
+}
+
+pub(crate) fn push_values<T: ToOptionValue>(
+    cmd: &mut Vec<OsString>,
+    encoder: Encoder,
+    items: &[T],
+) -> Result<(), Error> {
+    if !items.is_empty() {
+        encoder.check_supported(T::OPTION, None)?;
+    }
+    let mut added = false;
+
+    for item in items {
+        match item.to_option_value(encoder)? {
+            Some(value) => {
+                if !added {
+                    cmd.push(T::OPTION.into());
+                    added = true;
+                }
+                cmd.push(value.into());
+            }
+            None => empty_option::<T>(encoder)?,
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn option_command<T: ToOptionValue>(
+    item: &T,
+    encoder: Encoder,
+) -> Result<Vec<OsString>, Error> {
+    let mut cmd = vec![];
+    push_value(&mut cmd, encoder, item)?;
+    Ok(cmd)
+}
+
+pub(crate) fn lossy_command(cmd: Vec<OsString>) -> Vec<String> {
+    cmd.into_iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect()
+}
@@ -0,0 +1,212 @@
+// Every REST endpoint the API clients cover: what is sent and how the response body is decoded.
+// `api::ApiClient` and `asynchronous::AsyncApiClient` both send these, so only the transport
+// differs between them.
+
+use bytesize::ByteSize;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::api::{ApiError, PciDeviceInfo, RemoveDevice, VmCounters, VmInfo, VmmPingResponse};
+use crate::migration::{VmReceiveMigrationData, VmSendMigrationData};
+use crate::resize::{VmResize, VmResizeData, VmResizeZoneData};
+use crate::snapshot::{RestoreConfig, VmSnapshotConfig};
+use crate::vm_config::{
+    DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, UserDeviceConfig, VdpaConfig,
+    VsockConfig,
+};
+use crate::{Device, Disk, Fs, Net, Pmem, UserDevice, Vdpa, Vsock};
+
+pub(crate) struct ApiRequest<R> {
+    pub(crate) method: &'static str,
+    pub(crate) endpoint: &'static str,
+    pub(crate) body: Vec<u8>,
+    decode: fn(&[u8]) -> Result<R, ApiError>,
+}
+
+impl<R> ApiRequest<R> {
+    pub(crate) fn decode(&self, body: &[u8]) -> Result<R, ApiError> {
+        (self.decode)(body)
+    }
+}
+
+fn no_content(_: &[u8]) -> Result<(), ApiError> {
+    Ok(())
+}
+
+fn json<R: DeserializeOwned>(body: &[u8]) -> Result<R, ApiError> {
+    Ok(serde_json::from_slice(body)?)
+}
+
+fn get<R: DeserializeOwned>(endpoint: &'static str) -> ApiRequest<R> {
+    ApiRequest {
+        method: "GET",
+        endpoint,
+        body: vec![],
+        decode: json,
+    }
+}
+
+fn put(endpoint: &'static str) -> ApiRequest<()> {
+    ApiRequest {
+        method: "PUT",
+        endpoint,
+        body: vec![],
+        decode: no_content,
+    }
+}
+
+fn put_json<T: Serialize>(endpoint: &'static str, body: &T) -> Result<ApiRequest<()>, ApiError> {
+    Ok(ApiRequest {
+        body: serde_json::to_vec(body)?,
+        ..put(endpoint)
+    })
+}
+
+fn put_json_response<T: Serialize, R: DeserializeOwned>(
+    endpoint: &'static str,
+    body: &T,
+) -> Result<ApiRequest<R>, ApiError> {
+    Ok(ApiRequest {
+        method: "PUT",
+        endpoint,
+        body: serde_json::to_vec(body)?,
+        decode: json,
+    })
+}
+
+pub(crate) fn vmm_ping() -> ApiRequest<VmmPingResponse> {
+    get("vmm.ping")
+}
+
+pub(crate) fn vmm_shutdown() -> ApiRequest<()> {
+    put("vmm.shutdown")
+}
+
+pub(crate) fn vm_create<T: Serialize>(config: &T) -> Result<ApiRequest<()>, ApiError> {
+    put_json("vm.create", config)
+}
+
+pub(crate) fn vm_boot() -> ApiRequest<()> {
+    put("vm.boot")
+}
+
+pub(crate) fn vm_shutdown() -> ApiRequest<()> {
+    put("vm.shutdown")
+}
+
+pub(crate) fn vm_reboot() -> ApiRequest<()> {
+    put("vm.reboot")
+}
+
+pub(crate) fn vm_pause() -> ApiRequest<()> {
+    put("vm.pause")
+}
+
+pub(crate) fn vm_resume() -> ApiRequest<()> {
+    put("vm.resume")
+}
+
+pub(crate) fn vm_power_button() -> ApiRequest<()> {
+    put("vm.power-button")
+}
+
+pub(crate) fn vm_delete() -> ApiRequest<()> {
+    put("vm.delete")
+}
+
+pub(crate) fn vm_info() -> ApiRequest<VmInfo> {
+    get("vm.info")
+}
+
+pub(crate) fn vm_counters() -> ApiRequest<VmCounters> {
+    get("vm.counters")
+}
+
+pub(crate) fn vm_add_disk(disk: &Disk) -> Result<ApiRequest<PciDeviceInfo>, ApiError> {
+    put_json_response("vm.add-disk", &DiskConfig::try_from(disk)?)
+}
+
+pub(crate) fn vm_add_net(net: &Net) -> Result<ApiRequest<PciDeviceInfo>, ApiError> {
+    put_json_response("vm.add-net", &NetConfig::try_from(net)?)
+}
+
+pub(crate) fn vm_add_fs(fs: &Fs) -> Result<ApiRequest<PciDeviceInfo>, ApiError> {
+    put_json_response("vm.add-fs", &FsConfig::try_from(fs)?)
+}
+
+pub(crate) fn vm_add_pmem(pmem: &Pmem) -> Result<ApiRequest<PciDeviceInfo>, ApiError> {
+    put_json_response("vm.add-pmem", &PmemConfig::try_from(pmem)?)
+}
+
+pub(crate) fn vm_add_device(device: &Device) -> Result<ApiRequest<PciDeviceInfo>, ApiError> {
+    put_json_response("vm.add-device", &DeviceConfig::try_from(device)?)
+}
+
+pub(crate) fn vm_add_user_device(
+    user_device: &UserDevice,
+) -> Result<ApiRequest<PciDeviceInfo>, ApiError> {
+    put_json_response(
+        "vm.add-user-device",
+        &UserDeviceConfig::try_from(user_device)?,
+    )
+}
+
+pub(crate) fn vm_add_vdpa(vdpa: &Vdpa) -> Result<ApiRequest<PciDeviceInfo>, ApiError> {
+    put_json_response("vm.add-vdpa", &VdpaConfig::try_from(vdpa)?)
+}
+
+pub(crate) fn vm_add_vsock(vsock: &Vsock) -> Result<ApiRequest<PciDeviceInfo>, ApiError> {
+    put_json_response("vm.add-vsock", &VsockConfig::try_from(vsock)?)
+}
+
+pub(crate) fn vm_remove_device(id: &str) -> Result<ApiRequest<()>, ApiError> {
+    put_json("vm.remove-device", &RemoveDevice { id })
+}
+
+pub(crate) fn vm_resize(resize: &VmResize) -> Result<ApiRequest<()>, ApiError> {
+    put_json("vm.resize", &VmResizeData::from(resize))
+}
+
+pub(crate) fn vm_resize_zone(id: &str, desired_ram: ByteSize) -> Result<ApiRequest<()>, ApiError> {
+    put_json(
+        "vm.resize-zone",
+        &VmResizeZoneData {
+            id,
+            desired_ram: desired_ram.as_u64(),
+        },
+    )
+}
+
+pub(crate) fn vm_snapshot(destination_url: &str) -> Result<ApiRequest<()>, ApiError> {
+    put_json("vm.snapshot", &VmSnapshotConfig { destination_url })
+}
+
+pub(crate) fn vm_restore(source_url: &str, prefault: bool) -> Result<ApiRequest<()>, ApiError> {
+    put_json(
+        "vm.restore",
+        &RestoreConfig {
+            source_url,
+            prefault,
+        },
+    )
+}
+
+pub(crate) fn vm_receive_migration(receiver_url: &str) -> Result<ApiRequest<()>, ApiError> {
+    put_json(
+        "vm.receive-migration",
+        &VmReceiveMigrationData { receiver_url },
+    )
+}
+
+pub(crate) fn vm_send_migration(
+    destination_url: &str,
+    local: bool,
+) -> Result<ApiRequest<()>, ApiError> {
+    put_json(
+        "vm.send-migration",
+        &VmSendMigrationData {
+            destination_url,
+            local,
+        },
+    )
+}
@@ -0,0 +1,105 @@
+use std::fmt::{Display, Formatter};
+
+use crate::encode::EncodeError;
+use crate::from_command::ParseError;
+use crate::resize::ResizeError;
+use crate::validate::ValidationError;
+use crate::version::ChVersion;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    Parse(ParseError),
+    Encode(EncodeError),
+    EmptyOption {
+        option: String,
+    },
+    Conflict {
+        option: String,
+        other: String,
+        reason: String,
+    },
+    Validation(Vec<ValidationError>),
+    Unsupported {
+        option: String,
+        key: Option<String>,
+        version: ChVersion,
+        added: Option<ChVersion>,
+        removed: Option<ChVersion>,
+    },
+    MissingFd {
+        option: String,
+        fd: i32,
+    },
+    Resize(ResizeError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(err) => write!(f, "{}", err),
+            Error::Encode(err) => write!(f, "{}", err),
+            Error::EmptyOption { option } => write!(f, "{}: no values set", option),
+            Error::Conflict {
+                option,
+                other,
+                reason,
+            } => write!(f, "{} conflicts with {}: {}", option, other, reason),
+            Error::Validation(errors) => {
+                for (i, err) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", err)?;
+                }
+                Ok(())
+            }
+            Error::Unsupported {
+                option,
+                key,
+                version,
+                added,
+                removed,
+            } => {
+                write!(f, "{}", option)?;
+                if let Some(key) = key {
+                    write!(f, " {}", key)?;
+                }
+                write!(f, " is not supported by cloud-hypervisor {}", version)?;
+                if let Some(added) = added {
+                    write!(f, " (added in {})", added)?;
+                }
+                if let Some(removed) = removed {
+                    write!(f, " (removed in {})", removed)?;
+                }
+                Ok(())
+            }
+            Error::MissingFd { option, fd } => {
+                write!(f, "{} references fd {} which was not provided", option, fd)
+            }
+            Error::Resize(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse(err) => Some(err),
+            Error::Encode(err) => Some(err),
+            Error::Resize(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error::Parse(err)
+    }
+}
+
+impl From<EncodeError> for Error {
+    fn from(err: EncodeError) -> Self {
+        Error::Encode(err)
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+const READ_CHUNK: usize = 4096;
+
+// The events cloud-hypervisor writes to `--event-monitor`. Events this crate does not know about
+// are kept as `Unknown` with their source and name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    VmmStarting,
+    VmmShutdown,
+    VmmPanic,
+    VmBooting,
+    VmBooted,
+    VmPausing,
+    VmPaused,
+    VmResuming,
+    VmResumed,
+    VmSnapshotting,
+    VmSnapshotted,
+    VmRestoring,
+    VmRestored,
+    VmRebooting,
+    VmRebooted,
+    VmShutdown,
+    VmDeleted,
+    DeviceAdded { id: String },
+    DeviceRemoved { id: String },
+    VirtioDeviceActivated { id: String },
+    VirtioDeviceReset { id: String },
+    GuestPanic,
+    Unknown { source: String, event: String },
+}
+
+impl Event {
+    pub fn new(source: &str, event: &str, properties: &BTreeMap<String, String>) -> Self {
+        let id = || properties.get("id").cloned().unwrap_or_default();
+        match (source, event) {
+            ("vmm", "starting") => Event::VmmStarting,
+            ("vmm", "shutdown") => Event::VmmShutdown,
+            ("vmm", "panic") => Event::VmmPanic,
+            ("vm", "booting") => Event::VmBooting,
+            ("vm", "booted") => Event::VmBooted,
+            ("vm", "pausing") => Event::VmPausing,
+            ("vm", "paused") => Event::VmPaused,
+            ("vm", "resuming") => Event::VmResuming,
+            ("vm", "resumed") => Event::VmResumed,
+            ("vm", "snapshotting") => Event::VmSnapshotting,
+            ("vm", "snapshotted") => Event::VmSnapshotted,
+            ("vm", "restoring") => Event::VmRestoring,
+            ("vm", "restored") => Event::VmRestored,
+            ("vm", "rebooting") => Event::VmRebooting,
+            ("vm", "rebooted") => Event::VmRebooted,
+            ("vm", "shutdown") => Event::VmShutdown,
+            ("vm", "deleted") => Event::VmDeleted,
+            ("vm", "device-added") => Event::DeviceAdded { id: id() },
+            ("vm", "device-removed") => Event::DeviceRemoved { id: id() },
+            ("virtio-device", "activated") => Event::VirtioDeviceActivated { id: id() },
+            ("virtio-device", "reset") => Event::VirtioDeviceReset { id: id() },
+            ("guest", "panic") => Event::GuestPanic,
+            _ => Event::Unknown {
+                source: source.to_string(),
+                event: event.to_string(),
+            },
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        match self {
+            Event::VmmStarting | Event::VmmShutdown | Event::VmmPanic => "vmm",
+            Event::VirtioDeviceActivated { .. } | Event::VirtioDeviceReset { .. } => {
+                "virtio-device"
+            }
+            Event::GuestPanic => "guest",
+            Event::Unknown { source, .. } => source,
+            _ => "vm",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Event::VmmStarting => "starting",
+            Event::VmmShutdown | Event::VmShutdown => "shutdown",
+            Event::VmmPanic | Event::GuestPanic => "panic",
+            Event::VmBooting => "booting",
+            Event::VmBooted => "booted",
+            Event::VmPausing => "pausing",
+            Event::VmPaused => "paused",
+            Event::VmResuming => "resuming",
+            Event::VmResumed => "resumed",
+            Event::VmSnapshotting => "snapshotting",
+            Event::VmSnapshotted => "snapshotted",
+            Event::VmRestoring => "restoring",
+            Event::VmRestored => "restored",
+            Event::VmRebooting => "rebooting",
+            Event::VmRebooted => "rebooted",
+            Event::VmDeleted => "deleted",
+            Event::DeviceAdded { .. } => "device-added",
+            Event::DeviceRemoved { .. } => "device-removed",
+            Event::VirtioDeviceActivated { .. } => "activated",
+            Event::VirtioDeviceReset { .. } => "reset",
+            Event::Unknown { event, .. } => event,
+        }
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.source(), self.name())?;
+        match self {
+            Event::DeviceAdded { id }
+            | Event::DeviceRemoved { id }
+            | Event::VirtioDeviceActivated { id }
+            | Event::VirtioDeviceReset { id } => write!(f, " {}", id),
+            _ => Ok(()),
+        }
+    }
+}
+
+// One event as written by the VMM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventRecord {
+    // Time since the VMM started.
+    pub timestamp: Duration,
+    pub event: Event,
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct RawEvent {
+    timestamp: Duration,
+    source: String,
+    event: String,
+    #[serde(default)]
+    properties: Option<BTreeMap<String, String>>,
+}
+
+impl From<RawEvent> for EventRecord {
+    fn from(raw: RawEvent) -> Self {
+        let properties = raw.properties.unwrap_or_default();
+        EventRecord {
+            timestamp: raw.timestamp,
+            event: Event::new(&raw.source, &raw.event, &properties),
+            properties,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EventError {
+    Io(io::Error),
+    // The stream holds something other than an event. The rest of what was read so far is
+    // dropped.
+    Json(serde_json::Error),
+}
+
+impl Display for EventError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventError::Io(err) => write!(f, "event monitor: {}", err),
+            EventError::Json(err) => write!(f, "event monitor: invalid event: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for EventError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EventError::Io(err) => Some(err),
+            EventError::Json(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for EventError {
+    fn from(err: io::Error) -> Self {
+        EventError::Io(err)
+    }
+}
+
+// Reads the JSON objects cloud-hypervisor writes one after another, with or without whitespace in
+// between. An object split across writes is kept until the rest arrives, so reading a file that is
+// still being written can continue after `next_event()` returned `None`.
+#[derive(Debug)]
+pub struct EventReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+}
+
+impl<R: Read> EventReader<R> {
+    pub fn new(reader: R) -> Self {
+        EventReader {
+            reader,
+            buf: vec![],
+        }
+    }
+
+    // The next complete event, or `None` once the reader is at its end for now.
+    pub fn next_event(&mut self) -> Result<Option<EventRecord>, EventError> {
+        loop {
+            if let Some(record) = self.parse()? {
+                return Ok(Some(record));
+            }
+            let mut chunk = [0; READ_CHUNK];
+            let n = match self.reader.read(&mut chunk) {
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                // A non-blocking reader with nothing written yet.
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+            if n == 0 {
+                return Ok(None);
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn parse(&mut self) -> Result<Option<EventRecord>, EventError> {
+        let mut stream = serde_json::Deserializer::from_slice(&self.buf).into_iter::<RawEvent>();
+        let result = stream.next();
+        let consumed = stream.byte_offset();
+        match result {
+            None => {
+                // Only whitespace so far.
+                self.buf.clear();
+                Ok(None)
+            }
+            Some(Ok(raw)) => {
+                self.buf.drain(..consumed);
+                Ok(Some(raw.into()))
+            }
+            Some(Err(err)) if err.is_eof() => Ok(None),
+            Some(Err(err)) => {
+                self.buf.clear();
+                Err(EventError::Json(err))
+            }
+        }
+    }
+}
+
+impl EventReader<File> {
+    // Opening a fifo blocks until the VMM opens it for writing.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(EventReader::new(File::open(path)?))
+    }
+
+    // E.g. the read end of a pipe whose write end is passed as `--event-monitor fd=N`.
+    pub fn from_fd(fd: OwnedFd) -> Self {
+        EventReader::new(File::from(fd))
+    }
+
+    // Buffers what has been written so far without blocking, even on a blocking fd.
+    pub(crate) fn fill(&mut self) -> io::Result<()> {
+        loop {
+            let mut pollfd = libc::pollfd {
+                fd: self.reader.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            match unsafe { libc::poll(&mut pollfd, 1, 0) } {
+                0 => return Ok(()),
+                n if n < 0 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(err);
+                }
+                _ => {}
+            }
+
+            let mut chunk = [0; READ_CHUNK];
+            match self.reader.read(&mut chunk) {
+                // The writer is gone.
+                Ok(0) => return Ok(()),
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // The complete events buffered so far. They are still returned by `next_event()`.
+    pub(crate) fn buffered(&self) -> Vec<EventRecord> {
+        serde_json::Deserializer::from_slice(&self.buf)
+            .into_iter::<RawEvent>()
+            .map_while(Result::ok)
+            .map(EventRecord::from)
+            .collect()
+    }
+}
+
+impl<R: Read> Iterator for EventReader<R> {
+    type Item = Result<EventRecord, EventError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
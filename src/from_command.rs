@@ -0,0 +1,1248 @@
+use std::ffi::OsStr;
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::net::IpAddr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use bytesize::ByteSize;
+
+use crate::{
+    Balloon, CloudHypervisorInstance, Console, CpuAffinity, CpuFeatures, CpuTopology, Cpus,
+    DebugConsole, DebugConsoleType, Device, Disk, Fs, LandlockRule, Memory, MemoryHotplugMethod,
+    MemoryZone, Net, Numa, NumaDistance, OnOff, PathOrFileDescriptorOption, Platform, Pmem,
+    RateLimitGroup, Restore, Rng, SecComp, Serial, SgxEpc, UserDevice, Vdpa, VhostMode, Vsock,
+};
+
+pub trait FromCommand: Sized {
+    fn from_command<S: AsRef<str>>(args: &[S]) -> Result<Self, ParseError>;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    MissingBinary,
+    UnknownFlag,
+    MissingValue,
+    RepeatedFlag,
+    UnknownKey,
+    InvalidSyntax(String),
+    InvalidValue(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub flag: String,
+    pub key: Option<String>,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    fn flag(flag: &str, kind: ParseErrorKind) -> Self {
+        ParseError {
+            flag: flag.to_string(),
+            key: None,
+            kind,
+        }
+    }
+
+    fn key(flag: &str, key: &str, kind: ParseErrorKind) -> Self {
+        ParseError {
+            flag: flag.to_string(),
+            key: Some(key.to_string()),
+            kind,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.key {
+            None => write!(f, "{}: ", self.flag)?,
+            Some(key) => write!(f, "{} {}: ", self.flag, key)?,
+        }
+        match &self.kind {
+            ParseErrorKind::MissingBinary => write!(f, "missing binary path"),
+            ParseErrorKind::UnknownFlag => write!(f, "unknown flag"),
+            ParseErrorKind::MissingValue => write!(f, "missing value"),
+            ParseErrorKind::RepeatedFlag => write!(f, "flag may only be given once"),
+            ParseErrorKind::UnknownKey => write!(f, "unknown key"),
+            ParseErrorKind::InvalidSyntax(value) => write!(f, "invalid syntax in `{}`", value),
+            ParseErrorKind::InvalidValue(value) => write!(f, "invalid value `{}`", value),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn to_path(value: &[u8]) -> PathBuf {
+    PathBuf::from(OsStr::from_bytes(value))
+}
+
+// Arguments are parsed as bytes so paths keep whatever bytes they hold; everything else must be
+// UTF-8.
+fn utf8<'v>(flag: &str, key: Option<&str>, value: &'v [u8]) -> Result<&'v str, ParseError> {
+    std::str::from_utf8(value).map_err(|_| {
+        let kind = ParseErrorKind::InvalidValue(lossy(value));
+        match key {
+            Some(key) => ParseError::key(flag, key, kind),
+            None => ParseError::flag(flag, kind),
+        }
+    })
+}
+
+fn trim(mut value: &[u8]) -> &[u8] {
+    while let [first, rest @ ..] = value {
+        if !first.is_ascii_whitespace() {
+            break;
+        }
+        value = rest;
+    }
+    while let [rest @ .., last] = value {
+        if !last.is_ascii_whitespace() {
+            break;
+        }
+        value = rest;
+    }
+    value
+}
+
+// Mirrors cloud-hypervisor's option_parser: commas inside brackets or quotes do not split.
+pub(crate) fn split_commas<'i>(flag: &str, input: &'i [u8]) -> Result<Vec<&'i [u8]>, ParseError> {
+    let input = trim(input);
+    let invalid = || ParseError::flag(flag, ParseErrorKind::InvalidSyntax(lossy(input)));
+
+    let mut list = vec![];
+    let mut opened_brackets = 0usize;
+    let mut in_quotes = false;
+    let mut start = 0;
+
+    for (i, c) in input.iter().enumerate() {
+        match c {
+            b'[' if !in_quotes => opened_brackets += 1,
+            b']' if !in_quotes => {
+                opened_brackets = opened_brackets.checked_sub(1).ok_or_else(invalid)?;
+            }
+            b'"' => in_quotes = !in_quotes,
+            b',' if opened_brackets == 0 && !in_quotes => {
+                list.push(&input[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    list.push(&input[start..]);
+
+    if opened_brackets != 0 || in_quotes {
+        return Err(invalid());
+    }
+
+    Ok(list)
+}
+
+fn unquote(value: &[u8]) -> &[u8] {
+    let value = trim(value);
+    match value {
+        [b'"', inner @ .., b'"'] => inner,
+        _ => value,
+    }
+}
+
+fn unbracket(value: &str) -> &str {
+    let value = value.trim();
+    if value.len() >= 2 && value.starts_with('[') && value.ends_with(']') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+pub(crate) fn parse_byte_size(value: &str) -> Option<ByteSize> {
+    let value = value.trim();
+    let (digits, shift) = match value.char_indices().last() {
+        Some((i, 'K')) => (&value[..i], 10),
+        Some((i, 'M')) => (&value[..i], 20),
+        Some((i, 'G')) => (&value[..i], 30),
+        _ => (value, 0),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|v| v.checked_mul(1 << shift))
+        .map(ByteSize)
+}
+
+pub(crate) fn prefix_len(mask: IpAddr) -> Option<u8> {
+    let IpAddr::V4(mask) = mask else {
+        return None;
+    };
+    let mask = u32::from(mask);
+    let prefix = mask.leading_ones();
+    // only contiguous masks have a prefix length
+    if mask.checked_shl(prefix).unwrap_or(0) == 0 {
+        Some(prefix as u8)
+    } else {
+        None
+    }
+}
+
+fn parse_on_off(value: &str) -> Option<OnOff> {
+    match value {
+        "on" | "true" => Some(OnOff::On),
+        "off" | "false" => Some(OnOff::Off),
+        _ => None,
+    }
+}
+
+struct OptionValues<'a> {
+    flag: &'a str,
+    values: Vec<(&'a str, Option<&'a [u8]>)>,
+}
+
+impl<'a> OptionValues<'a> {
+    fn parse(flag: &'a str, input: &'a [u8], keys: &[&str]) -> Result<Self, ParseError> {
+        let mut values = vec![];
+
+        for option in split_commas(flag, input)? {
+            if option.is_empty() {
+                continue;
+            }
+            let (key, value) = match option.iter().position(|c| *c == b'=') {
+                None => (option, None),
+                Some(i) => (&option[..i], Some(unquote(&option[i + 1..]))),
+            };
+            let key = std::str::from_utf8(key)
+                .ok()
+                .filter(|key| keys.contains(key))
+                .ok_or_else(|| ParseError::key(flag, &lossy(key), ParseErrorKind::UnknownKey))?;
+            values.push((key, value));
+        }
+
+        Ok(OptionValues { flag, values })
+    }
+
+    fn has(&self, key: &str) -> bool {
+        self.values.iter().any(|(k, _)| *k == key)
+    }
+
+    fn get(&self, key: &str) -> Result<Option<&'a str>, ParseError> {
+        match self.get_raw(key)? {
+            None => Ok(None),
+            Some(value) => utf8(self.flag, Some(key), value).map(Some),
+        }
+    }
+
+    // Like `get()`, but for paths, which may hold bytes that are not UTF-8.
+    fn get_raw(&self, key: &str) -> Result<Option<&'a [u8]>, ParseError> {
+        match self.values.iter().rev().find(|(k, _)| *k == key) {
+            None => Ok(None),
+            Some((_, Some(value))) => Ok(Some(value)),
+            Some((_, None)) => Err(ParseError::key(
+                self.flag,
+                key,
+                ParseErrorKind::MissingValue,
+            )),
+        }
+    }
+
+    fn invalid(&self, key: &str, value: &str) -> ParseError {
+        ParseError::key(
+            self.flag,
+            key,
+            ParseErrorKind::InvalidValue(value.to_string()),
+        )
+    }
+
+    fn string(&self, key: &str) -> Result<Option<String>, ParseError> {
+        Ok(self.get(key)?.map(|v| v.to_string()))
+    }
+
+    fn path(&self, key: &str) -> Result<Option<PathBuf>, ParseError> {
+        Ok(self.get_raw(key)?.map(to_path))
+    }
+
+    fn parsed<T: FromStr>(&self, key: &str) -> Result<Option<T>, ParseError> {
+        match self.get(key)? {
+            None => Ok(None),
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| self.invalid(key, value)),
+        }
+    }
+
+    fn on_off(&self, key: &str) -> Result<Option<OnOff>, ParseError> {
+        match self.get(key)? {
+            None => Ok(None),
+            Some(value) => parse_on_off(value)
+                .map(Some)
+                .ok_or_else(|| self.invalid(key, value)),
+        }
+    }
+
+    fn byte_size(&self, key: &str) -> Result<Option<ByteSize>, ParseError> {
+        match self.get(key)? {
+            None => Ok(None),
+            Some(value) => parse_byte_size(value)
+                .map(Some)
+                .ok_or_else(|| self.invalid(key, value)),
+        }
+    }
+
+    // Like cloud-hypervisor, items are split on every comma: quotes do not protect them, so a
+    // quoted or nested item is rejected instead of being split apart.
+    fn list_items<'v>(&self, key: &str, value: &'v str) -> Result<Vec<&'v str>, ParseError> {
+        let items = unbracket(value)
+            .split(',')
+            .map(str::trim)
+            .collect::<Vec<&str>>();
+        if items.iter().any(|item| item.contains(['"', '[', ']'])) {
+            return Err(self.invalid(key, value));
+        }
+        Ok(items)
+    }
+
+    fn list<T: FromStr>(&self, key: &str) -> Result<Option<Vec<T>>, ParseError> {
+        match self.get(key)? {
+            None => Ok(None),
+            Some(value) => self
+                .list_items(key, value)?
+                .into_iter()
+                .map(|v| v.parse().map_err(|_| self.invalid(key, value)))
+                .collect::<Result<Vec<T>, ParseError>>()
+                .map(Some),
+        }
+    }
+
+    fn usize_list(&self, key: &str) -> Result<Option<Vec<usize>>, ParseError> {
+        match self.get(key)? {
+            None => Ok(None),
+            Some(value) => parse_usize_ranges(unbracket(value))
+                .map(Some)
+                .ok_or_else(|| self.invalid(key, value)),
+        }
+    }
+}
+
+// Accepts both plain values and cloud-hypervisor's `a-b` ranges, e.g. `0-2,5`.
+fn parse_usize_ranges(value: &str) -> Option<Vec<usize>> {
+    let mut list = vec![];
+    for item in value.split(',') {
+        match item.split_once('-') {
+            None => list.push(item.trim().parse().ok()?),
+            Some((start, end)) => {
+                let start: usize = start.trim().parse().ok()?;
+                let end: usize = end.trim().parse().ok()?;
+                if start > end {
+                    return None;
+                }
+                list.extend(start..=end);
+            }
+        }
+    }
+    Some(list)
+}
+
+// `[<index>@[<host cpus>],...]`, shared by `--cpus affinity` and `--disk queue_affinity`.
+pub(crate) fn parse_affinity<T: FromStr>(
+    flag: &str,
+    key: &str,
+    value: &str,
+) -> Result<Vec<(T, Vec<usize>)>, ParseError> {
+    let invalid = || ParseError::key(flag, key, ParseErrorKind::InvalidValue(value.to_string()));
+
+    let mut affinity = vec![];
+    for item in split_commas(flag, unbracket(value).as_bytes())? {
+        let item = std::str::from_utf8(item).map_err(|_| invalid())?;
+        let (index, host_cpus) = item.split_once('@').ok_or_else(invalid)?;
+        affinity.push((
+            index.trim().parse().map_err(|_| invalid())?,
+            parse_usize_ranges(unbracket(host_cpus)).ok_or_else(invalid)?,
+        ));
+    }
+    Ok(affinity)
+}
+
+fn single_arg<'a, S: AsRef<str>>(flag: &str, args: &'a [S]) -> Result<&'a [u8], ParseError> {
+    match args {
+        [f, value] if f.as_ref() == flag => Ok(value.as_ref().as_bytes()),
+        [f, ..] if f.as_ref() != flag => {
+            Err(ParseError::flag(f.as_ref(), ParseErrorKind::UnknownFlag))
+        }
+        _ => Err(ParseError::flag(flag, ParseErrorKind::MissingValue)),
+    }
+}
+
+fn parse_cpus(flag: &str, input: &[u8]) -> Result<Cpus, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &[
+            "boot",
+            "max",
+            "topology",
+            "kvm_hyperv",
+            "max_phys_bits",
+            "affinity",
+            "features",
+        ],
+    )?;
+
+    let topology = match o.get("topology")? {
+        None => None,
+        Some(value) => {
+            let parts = value
+                .split(':')
+                .map(|v| v.parse::<u8>())
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| o.invalid("topology", value))?;
+            match parts.as_slice() {
+                [threads_per_core, cores_per_die, dies_per_package, packages] => {
+                    Some(CpuTopology {
+                        threads_per_core: *threads_per_core,
+                        cores_per_die: *cores_per_die,
+                        dies_per_package: *dies_per_package,
+                        packages: *packages,
+                    })
+                }
+                _ => return Err(o.invalid("topology", value)),
+            }
+        }
+    };
+
+    let affinity = match o.get("affinity")? {
+        None => None,
+        Some(value) => Some(
+            parse_affinity(flag, "affinity", value)?
+                .into_iter()
+                .map(|(vcpu, host_cpus)| CpuAffinity { vcpu, host_cpus })
+                .collect(),
+        ),
+    };
+
+    let features = match o.get("features")? {
+        None => None,
+        Some(value) => {
+            let mut features = CpuFeatures::default();
+            for feature in unbracket(value).split(',') {
+                match feature.trim() {
+                    "amx" => features.amx = Some(true),
+                    _ => return Err(o.invalid("features", value)),
+                }
+            }
+            Some(features)
+        }
+    };
+
+    Ok(Cpus {
+        boot: o.parsed("boot")?,
+        max: o.parsed("max")?,
+        topology,
+        kvm_hyperv: o.on_off("kvm_hyperv")?,
+        max_phys_bits: o.parsed("max_phys_bits")?,
+        affinity,
+        features,
+    })
+}
+
+impl FromCommand for Cpus {
+    fn from_command<S: AsRef<str>>(args: &[S]) -> Result<Self, ParseError> {
+        parse_cpus("--cpus", single_arg("--cpus", args)?)
+    }
+}
+
+fn parse_platform(flag: &str, input: &[u8]) -> Result<Platform, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &[
+            "num_pci_segments",
+            "iommu_segments",
+            "serial_number",
+            "uuid",
+            "oem_strings",
+        ],
+    )?;
+
+    Ok(Platform {
+        num_pci_segments: o.parsed("num_pci_segments")?,
+        iommu_segments: o.parsed("iommu_segments")?,
+        serial_number: o.string("serial_number")?,
+        uuid: o.string("uuid")?,
+        oem_strings: o.list("oem_strings")?,
+    })
+}
+
+impl FromCommand for Platform {
+    fn from_command<S: AsRef<str>>(args: &[S]) -> Result<Self, ParseError> {
+        parse_platform("--platform", single_arg("--platform", args)?)
+    }
+}
+
+fn parse_memory(flag: &str, input: &[u8]) -> Result<Memory, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &[
+            "size",
+            "mergeable",
+            "shared",
+            "hugepages",
+            "hugepage_size",
+            "hotplug_method",
+            "hotplug_size",
+            "hotplugged_size",
+            "prefault",
+            "thp",
+        ],
+    )?;
+
+    let hotplug_method = match o.get("hotplug_method")? {
+        None => None,
+        Some("acpi") => Some(MemoryHotplugMethod::Acpi),
+        Some("virtio-mem") => Some(MemoryHotplugMethod::VirtioMem),
+        Some(value) => return Err(o.invalid("hotplug_method", value)),
+    };
+
+    Ok(Memory {
+        size: o.byte_size("size")?,
+        mergeable: o.on_off("mergeable")?,
+        shared: o.on_off("shared")?,
+        hugepages: o.on_off("hugepages")?,
+        hugepage_size: o.byte_size("hugepage_size")?,
+        hotplug_method,
+        hotplug_size: o.byte_size("hotplug_size")?,
+        hotplugged_size: o.byte_size("hotplugged_size")?,
+        prefault: o.on_off("prefault")?,
+        thp: o.on_off("thp")?,
+    })
+}
+
+impl FromCommand for Memory {
+    fn from_command<S: AsRef<str>>(args: &[S]) -> Result<Self, ParseError> {
+        parse_memory("--memory", single_arg("--memory", args)?)
+    }
+}
+
+fn parse_memory_zone(flag: &str, input: &[u8]) -> Result<MemoryZone, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &[
+            "size",
+            "file",
+            "shared",
+            "hugepages",
+            "hugepage_size",
+            "host_numa_node",
+            "id",
+            "hotplug_size",
+            "hotplugged_size",
+            "prefault",
+        ],
+    )?;
+
+    Ok(MemoryZone {
+        size: o.byte_size("size")?,
+        file: o.path("file")?,
+        shared: o.on_off("shared")?,
+        hugepages: o.on_off("hugepages")?,
+        hugepage_size: o.byte_size("hugepage_size")?,
+        host_numa_node: o.parsed("host_numa_node")?,
+        id: o.string("id")?,
+        hotplug_size: o.byte_size("hotplug_size")?,
+        hotplugged_size: o.byte_size("hotplugged_size")?,
+        prefault: o.on_off("prefault")?,
+    })
+}
+
+impl FromCommand for MemoryZone {
+    fn from_command<S: AsRef<str>>(args: &[S]) -> Result<Self, ParseError> {
+        parse_memory_zone("--memory-zone", single_arg("--memory-zone", args)?)
+    }
+}
+
+fn parse_rate_limit_group(flag: &str, input: &[u8]) -> Result<RateLimitGroup, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &[
+            "bw_size",
+            "bw_one_time_burst",
+            "bw_refill_time",
+            "ops_size",
+            "ops_one_time_burst",
+            "ops_refill_time",
+            "id",
+        ],
+    )?;
+
+    Ok(RateLimitGroup {
+        bw_size: o.byte_size("bw_size")?,
+        bw_one_time_burst: o.byte_size("bw_one_time_burst")?,
+        bw_refill_time: o.parsed("bw_refill_time")?,
+        ops_size: o.parsed("ops_size")?,
+        ops_one_time_burst: o.parsed("ops_one_time_burst")?,
+        ops_refill_time: o.parsed("ops_refill_time")?,
+        id: o.string("id")?,
+    })
+}
+
+fn parse_disk(flag: &str, input: &[u8]) -> Result<Disk, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &[
+            "path",
+            "readonly",
+            "direct",
+            "iommu",
+            "num_queues",
+            "queue_size",
+            "vhost_user",
+            "socket",
+            "bw_size",
+            "bw_one_time_burst",
+            "bw_refill_time",
+            "ops_size",
+            "ops_one_time_burst",
+            "ops_refill_time",
+            "id",
+            "pci_segment",
+            "rate_limit_group",
+            "queue_affinity",
+        ],
+    )?;
+
+    Ok(Disk {
+        path: o.path("path")?,
+        readonly: o.on_off("readonly")?,
+        direct: o.on_off("direct")?,
+        iommu: o.on_off("iommu")?,
+        num_queues: o.parsed("num_queues")?,
+        queue_size: o.parsed("queue_size")?,
+        vhost_user: o.on_off("vhost_user")?,
+        socket: o.path("socket")?,
+        bw_size: o.byte_size("bw_size")?,
+        bw_one_time_burst: o.byte_size("bw_one_time_burst")?,
+        bw_refill_time: o.parsed("bw_refill_time")?,
+        ops_size: o.parsed("ops_size")?,
+        ops_one_time_burst: o.parsed("ops_one_time_burst")?,
+        ops_refill_time: o.parsed("ops_refill_time")?,
+        id: o.string("id")?,
+        pci_segment: o.string("pci_segment")?,
+        rate_limit_group: o.string("rate_limit_group")?,
+        queue_affinity: o.string("queue_affinity")?,
+    })
+}
+
+fn parse_net(flag: &str, input: &[u8]) -> Result<Net, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &[
+            "tap",
+            "ip",
+            "mask",
+            "mac",
+            "fd",
+            "iommu",
+            "num_queues",
+            "queue_size",
+            "id",
+            "vhost_user",
+            "socket",
+            "vhost_mode",
+            "bw_size",
+            "bw_one_time_burst",
+            "bw_refill_time",
+            "ops_size",
+            "ops_one_time_burst",
+            "ops_refill_time",
+            "pci_segment",
+            "offload_tso",
+            "offload_ufo",
+            "offload_csum",
+        ],
+    )?;
+
+    // `mask=255.255.255.0` as cloud-hypervisor documents it, or the prefix length itself.
+    let mask = match o.get("mask")? {
+        None => None,
+        Some(value) => Some(
+            value
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= 32)
+                .or_else(|| value.parse().ok().and_then(prefix_len))
+                .ok_or_else(|| o.invalid("mask", value))?,
+        ),
+    };
+
+    let vhost_mode = match o.get("vhost_mode")? {
+        None => None,
+        Some("client") => Some(VhostMode::Client),
+        Some("server") => Some(VhostMode::Server),
+        Some(value) => return Err(o.invalid("vhost_mode", value)),
+    };
+
+    Ok(Net {
+        tap: o.string("tap")?,
+        ip: o.parsed("ip")?,
+        mask,
+        mac: o.string("mac")?,
+        fd: o.list("fd")?,
+        iommu: o.on_off("iommu")?,
+        num_queues: o.parsed("num_queues")?,
+        queue_size: o.parsed("queue_size")?,
+        id: o.string("id")?,
+        vhost_user: o.on_off("vhost_user")?,
+        socket: o.path("socket")?,
+        vhost_mode,
+        bw_size: o.byte_size("bw_size")?,
+        bw_one_time_burst: o.byte_size("bw_one_time_burst")?,
+        bw_refill_time: o.parsed("bw_refill_time")?,
+        ops_size: o.parsed("ops_size")?,
+        ops_one_time_burst: o.parsed("ops_one_time_burst")?,
+        ops_refill_time: o.parsed("ops_refill_time")?,
+        pci_segment: o.string("pci_segment")?,
+        offload_tso: o.on_off("offload_tso")?,
+        offload_ufo: o.on_off("offload_ufo")?,
+        offload_csum: o.on_off("offload_csum")?,
+    })
+}
+
+fn parse_rng(flag: &str, input: &[u8]) -> Result<Rng, ParseError> {
+    let o = OptionValues::parse(flag, input, &["src", "iommu"])?;
+
+    if let Some(src) = o.path("src")? {
+        Ok(Rng::Src(src))
+    } else if let Some(iommu) = o.on_off("iommu")? {
+        Ok(Rng::Iommu(iommu))
+    } else {
+        Err(ParseError::flag(flag, ParseErrorKind::MissingValue))
+    }
+}
+
+fn parse_balloon(flag: &str, input: &[u8]) -> Result<Balloon, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &["size", "deflate_on_oom", "free_page_reporting"],
+    )?;
+
+    Ok(Balloon {
+        size: o.byte_size("size")?,
+        deflate_on_oom: o.on_off("deflate_on_oom")?,
+        free_page_reporting: o.on_off("free_page_reporting")?,
+    })
+}
+
+fn parse_fs(flag: &str, input: &[u8]) -> Result<Fs, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &[
+            "tag",
+            "socket",
+            "num_queues",
+            "queue_size",
+            "id",
+            "pci_segment",
+        ],
+    )?;
+
+    Ok(Fs {
+        tag: o.string("tag")?,
+        socket: o.path("socket")?,
+        num_queues: o.parsed("num_queues")?,
+        queue_size: o.parsed("queue_size")?,
+        id: o.string("id")?,
+        pci_segment: o.string("pci_segment")?,
+    })
+}
+
+fn parse_pmem(flag: &str, input: &[u8]) -> Result<Pmem, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &[
+            "file",
+            "size",
+            "iommu",
+            "discard_writes",
+            "id",
+            "pci_segment",
+        ],
+    )?;
+
+    Ok(Pmem {
+        file: o.path("file")?,
+        size: o.byte_size("size")?.map(|size| size.0 as usize),
+        iommu: o.on_off("iommu")?,
+        discard_writes: o.on_off("discard_writes")?,
+        id: o.string("id")?,
+        pci_segment: o.string("pci_segment")?,
+    })
+}
+
+fn parse_serial(flag: &str, input: &[u8]) -> Result<Serial, ParseError> {
+    match input {
+        b"off" => Ok(Serial::Off),
+        b"null" => Ok(Serial::Null),
+        b"pty" => Ok(Serial::Pty),
+        b"tty" => Ok(Serial::Tty),
+        _ => {
+            let o = OptionValues::parse(flag, input, &["file", "socket"])?;
+            if let Some(file) = o.path("file")? {
+                Ok(Serial::File(file))
+            } else if let Some(socket) = o.path("socket")? {
+                Ok(Serial::Socket(socket))
+            } else {
+                Err(ParseError::flag(
+                    flag,
+                    ParseErrorKind::InvalidValue(lossy(input)),
+                ))
+            }
+        }
+    }
+}
+
+fn parse_console(flag: &str, input: &[u8]) -> Result<Console, ParseError> {
+    match input {
+        b"off" => Ok(Console::Off),
+        b"null" => Ok(Console::Null),
+        b"pty" => Ok(Console::Pty),
+        b"tty" => Ok(Console::Tty),
+        _ => {
+            let o = OptionValues::parse(flag, input, &["file", "iommu"])?;
+            if let Some(file) = o.path("file")? {
+                Ok(Console::File(file))
+            } else if let Some(iommu) = o.on_off("iommu")? {
+                Ok(Console::Iommu(iommu))
+            } else {
+                Err(ParseError::flag(
+                    flag,
+                    ParseErrorKind::InvalidValue(lossy(input)),
+                ))
+            }
+        }
+    }
+}
+
+fn parse_device(flag: &str, input: &[u8]) -> Result<Device, ParseError> {
+    let o = OptionValues::parse(flag, input, &["path", "iommu", "id", "pci_segment"])?;
+
+    Ok(Device {
+        path: o.path("path")?,
+        iommu: o.on_off("iommu")?,
+        id: o.string("id")?,
+        pci_segment: o.string("pci_segment")?,
+    })
+}
+
+fn parse_user_device(flag: &str, input: &[u8]) -> Result<UserDevice, ParseError> {
+    let o = OptionValues::parse(flag, input, &["socket", "id", "pci_segment"])?;
+
+    Ok(UserDevice {
+        socket: o.path("socket")?,
+        id: o.string("id")?,
+        pci_segment: o.string("pci_segment")?,
+    })
+}
+
+fn parse_vdpa(flag: &str, input: &[u8]) -> Result<Vdpa, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &["path", "num_queues", "iommu", "id", "pci_segment"],
+    )?;
+
+    Ok(Vdpa {
+        path: o.path("path")?,
+        num_queues: o.parsed("num_queues")?,
+        iommu: o.on_off("iommu")?,
+        id: o.string("id")?,
+        pci_segment: o.string("pci_segment")?,
+    })
+}
+
+fn parse_vsock(flag: &str, input: &[u8]) -> Result<Vsock, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &["cid", "socket", "iommu", "id", "pci_segment"],
+    )?;
+
+    Ok(Vsock {
+        cid: o.string("cid")?,
+        socket: o.path("socket")?,
+        iommu: o.on_off("iommu")?,
+        id: o.string("id")?,
+        pci_segment: o.string("pci_segment")?,
+    })
+}
+
+fn parse_numa(flag: &str, input: &[u8]) -> Result<Numa, ParseError> {
+    let o = OptionValues::parse(
+        flag,
+        input,
+        &[
+            "guest_numa_id",
+            "cpus",
+            "distances",
+            "memory_zones",
+            "sgx_epc_sections",
+            "pci_segments",
+        ],
+    )?;
+
+    let distances = match o.get("distances")? {
+        None => None,
+        Some(value) => Some(
+            o.list_items("distances", value)?
+                .into_iter()
+                .map(|item| {
+                    let (destination, distance) = item.split_once('@')?;
+                    Some(NumaDistance {
+                        destination: destination.trim().parse().ok()?,
+                        distance: distance.trim().parse().ok()?,
+                    })
+                })
+                .collect::<Option<Vec<NumaDistance>>>()
+                .ok_or_else(|| o.invalid("distances", value))?,
+        ),
+    };
+
+    Ok(Numa {
+        guest_numa_id: o.string("guest_numa_id")?,
+        cpus: o.usize_list("cpus")?,
+        distances,
+        memory_zones: o.list("memory_zones")?,
+        sgx_epc_sections: o.list("sgx_epc_sections")?,
+        pci_segments: o.list("pci_segments")?,
+    })
+}
+
+fn parse_path_or_fd(flag: &str, input: &[u8]) -> Result<PathOrFileDescriptorOption, ParseError> {
+    if !input.contains(&b'=') {
+        return Ok(PathOrFileDescriptorOption::Path(to_path(input)));
+    }
+
+    let o = OptionValues::parse(flag, input, &["path", "fd"])?;
+    if let Some(path) = o.path("path")? {
+        Ok(PathOrFileDescriptorOption::Path(path))
+    } else if let Some(fd) = o.parsed("fd")? {
+        Ok(PathOrFileDescriptorOption::Fd(fd))
+    } else {
+        Err(ParseError::flag(flag, ParseErrorKind::MissingValue))
+    }
+}
+
+fn parse_restore(flag: &str, input: &[u8]) -> Result<Restore, ParseError> {
+    let o = OptionValues::parse(flag, input, &["source_url", "prefault"])?;
+
+    Ok(Restore {
+        source_url: o.string("source_url")?,
+        prefault: o.on_off("prefault")?,
+    })
+}
+
+fn parse_seccomp(flag: &str, input: &[u8]) -> Result<SecComp, ParseError> {
+    match input {
+        b"true" => Ok(SecComp::True),
+        b"false" => Ok(SecComp::False),
+        b"log" => Ok(SecComp::Log),
+        _ => Err(ParseError::flag(
+            flag,
+            ParseErrorKind::InvalidValue(lossy(input)),
+        )),
+    }
+}
+
+fn parse_tpm(flag: &str, input: &[u8]) -> Result<PathBuf, ParseError> {
+    if !input.contains(&b'=') {
+        return Ok(to_path(input));
+    }
+
+    let o = OptionValues::parse(flag, input, &["socket"])?;
+    o.path("socket")?
+        .ok_or_else(|| ParseError::flag(flag, ParseErrorKind::MissingValue))
+}
+
+fn parse_sgx_epc(flag: &str, input: &[u8]) -> Result<SgxEpc, ParseError> {
+    let o = OptionValues::parse(flag, input, &["id", "size", "prefault"])?;
+
+    Ok(SgxEpc {
+        id: o.string("id")?,
+        size: o.string("size")?,
+        prefault: o.on_off("prefault")?,
+    })
+}
+
+fn parse_landlock_rule(flag: &str, input: &[u8]) -> Result<LandlockRule, ParseError> {
+    let o = OptionValues::parse(flag, input, &["path", "access"])?;
+
+    Ok(LandlockRule {
+        path: o.path("path")?,
+        access: o.string("access")?,
+    })
+}
+
+fn parse_debug_console(flag: &str, input: &[u8]) -> Result<DebugConsole, ParseError> {
+    let o = OptionValues::parse(flag, input, &["off", "pty", "tty", "file", "iobase"])?;
+
+    let console_type = if o.has("off") {
+        Some(DebugConsoleType::Off)
+    } else if o.has("pty") {
+        Some(DebugConsoleType::Pty)
+    } else if o.has("tty") {
+        Some(DebugConsoleType::Tty)
+    } else {
+        o.path("file")?.map(DebugConsoleType::File)
+    };
+
+    Ok(DebugConsole {
+        console_type,
+        iobase: o.string("iobase")?,
+    })
+}
+
+fn set_once<T>(slot: &mut Option<T>, flag: &str, value: T) -> Result<(), ParseError> {
+    if slot.is_some() {
+        return Err(ParseError::flag(flag, ParseErrorKind::RepeatedFlag));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+// Flags taking one value always consume the next argument, so `--cmdline` may start with `-`.
+fn value<'a, I: Iterator<Item = &'a [u8]>>(
+    flag: &str,
+    inline: Option<&'a [u8]>,
+    args: &mut Peekable<I>,
+) -> Result<&'a [u8], ParseError> {
+    match inline {
+        Some(value) => Ok(value),
+        None => args
+            .next()
+            .ok_or_else(|| ParseError::flag(flag, ParseErrorKind::MissingValue)),
+    }
+}
+
+// Flags taking several values consume arguments up to the next flag.
+fn values<'a, I: Iterator<Item = &'a [u8]>>(
+    flag: &str,
+    inline: Option<&'a [u8]>,
+    args: &mut Peekable<I>,
+) -> Result<Vec<&'a [u8]>, ParseError> {
+    let mut values: Vec<&[u8]> = inline.into_iter().collect();
+    while let Some(value) = args.next_if(|arg| !arg.starts_with(b"-")) {
+        values.push(value);
+    }
+    if values.is_empty() {
+        return Err(ParseError::flag(flag, ParseErrorKind::MissingValue));
+    }
+    Ok(values)
+}
+
+impl CloudHypervisorInstance {
+    // Like `from_command()` for argv that may hold paths that are not UTF-8, e.g. as read from
+    // `/proc/<pid>/cmdline`. Other values must be UTF-8.
+    pub fn from_os_command<S: AsRef<OsStr>>(args: &[S]) -> Result<Self, ParseError> {
+        parse_instance(args.iter().map(|arg| arg.as_ref().as_bytes()))
+    }
+}
+
+impl FromCommand for CloudHypervisorInstance {
+    fn from_command<S: AsRef<str>>(args: &[S]) -> Result<Self, ParseError> {
+        parse_instance(args.iter().map(|arg| arg.as_ref().as_bytes()))
+    }
+}
+
+fn parse_instance<'a>(
+    args: impl Iterator<Item = &'a [u8]>,
+) -> Result<CloudHypervisorInstance, ParseError> {
+    let mut args = args.peekable();
+
+    let bin_path = args
+        .next()
+        .ok_or_else(|| ParseError::flag("", ParseErrorKind::MissingBinary))?;
+    let mut ch = CloudHypervisorInstance::new(to_path(bin_path));
+
+    while let Some(arg) = args.next() {
+        if arg.len() > 1 && arg.starts_with(b"-") && arg[1..].iter().all(|c| *c == b'v') {
+            for _ in 1..arg.len() {
+                ch.v();
+            }
+            continue;
+        }
+
+        let (flag, inline) = match arg.iter().position(|c| *c == b'=') {
+            Some(i) if arg.starts_with(b"--") => (&arg[..i], Some(&arg[i + 1..])),
+            _ => (arg, None),
+        };
+        let flag = std::str::from_utf8(flag)
+            .map_err(|_| ParseError::flag(&lossy(flag), ParseErrorKind::UnknownFlag))?;
+
+        match flag {
+            "--cpus" => {
+                let cpus = parse_cpus(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.cpus, flag, cpus)?;
+            }
+            "--platform" => {
+                let platform = parse_platform(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.platform, flag, platform)?;
+            }
+            "--memory" => {
+                let memory = parse_memory(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.memory, flag, memory)?;
+            }
+            "--memory-zone" => {
+                // cloud-hypervisor takes several zones after one flag, the model holds only one.
+                let memory_zone = match values(flag, inline, &mut args)?.as_slice() {
+                    [value] => parse_memory_zone(flag, value)?,
+                    _ => return Err(ParseError::flag(flag, ParseErrorKind::RepeatedFlag)),
+                };
+                set_once(&mut ch.memory_zone, flag, memory_zone)?;
+            }
+            "--firmware" => {
+                let firmware = to_path(value(flag, inline, &mut args)?);
+                set_once(&mut ch.firmware, flag, firmware)?;
+            }
+            "--kernel" => {
+                let kernel = to_path(value(flag, inline, &mut args)?);
+                set_once(&mut ch.kernel, flag, kernel)?;
+            }
+            "--initramfs" => {
+                let initramfs = to_path(value(flag, inline, &mut args)?);
+                set_once(&mut ch.initramfs, flag, initramfs)?;
+            }
+            "--cmdline" => {
+                let cmdline = utf8(flag, None, value(flag, inline, &mut args)?)?.to_string();
+                set_once(&mut ch.cmdline, flag, cmdline)?;
+            }
+            "--rate-limit-group" => {
+                for v in values(flag, inline, &mut args)? {
+                    ch.rate_limit_group(parse_rate_limit_group(flag, v)?);
+                }
+            }
+            "--disk" => {
+                for v in values(flag, inline, &mut args)? {
+                    ch.disk(parse_disk(flag, v)?);
+                }
+            }
+            "--net" => {
+                for v in values(flag, inline, &mut args)? {
+                    ch.net(parse_net(flag, v)?);
+                }
+            }
+            "--rng" => {
+                let rng = parse_rng(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.rng, flag, rng)?;
+            }
+            "--balloon" => {
+                let balloon = parse_balloon(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.balloon, flag, balloon)?;
+            }
+            "--fs" => {
+                for v in values(flag, inline, &mut args)? {
+                    ch.fs(parse_fs(flag, v)?);
+                }
+            }
+            "--pmem" => {
+                for v in values(flag, inline, &mut args)? {
+                    ch.pmem(parse_pmem(flag, v)?);
+                }
+            }
+            "--serial" => {
+                let serial = parse_serial(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.serial, flag, serial)?;
+            }
+            "--console" => {
+                let console = parse_console(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.console, flag, console)?;
+            }
+            "--device" => {
+                for v in values(flag, inline, &mut args)? {
+                    ch.device(parse_device(flag, v)?);
+                }
+            }
+            "--user-device" => {
+                for v in values(flag, inline, &mut args)? {
+                    ch.user_device(parse_user_device(flag, v)?);
+                }
+            }
+            "--vdpa" => {
+                for v in values(flag, inline, &mut args)? {
+                    ch.vdpa(parse_vdpa(flag, v)?);
+                }
+            }
+            "--vsock" => {
+                let vsock = parse_vsock(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.vsock, flag, vsock)?;
+            }
+            "--pvpanic" => {
+                ch.pvpanic(true);
+            }
+            "--numa" => {
+                for v in values(flag, inline, &mut args)? {
+                    ch.numa(parse_numa(flag, v)?);
+                }
+            }
+            "--watchdog" => {
+                ch.watchdog(true);
+            }
+            "--log-file" => {
+                let log_file = to_path(value(flag, inline, &mut args)?);
+                set_once(&mut ch.log_file, flag, log_file)?;
+            }
+            "--api-socket" => {
+                let api_socket = parse_path_or_fd(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.api_socket, flag, api_socket)?;
+            }
+            "--event-monitor" => {
+                let event_monitor = parse_path_or_fd(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.event_monitor, flag, event_monitor)?;
+            }
+            "--restore" => {
+                let restore = parse_restore(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.restore, flag, restore)?;
+            }
+            "--seccomp" => {
+                let seccomp = parse_seccomp(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.seccomp, flag, seccomp)?;
+            }
+            "--tpm" => {
+                let tpm = parse_tpm(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.tpm, flag, tpm)?;
+            }
+            "--sgx-epc" => {
+                for v in values(flag, inline, &mut args)? {
+                    ch.sgx_epc(parse_sgx_epc(flag, v)?);
+                }
+            }
+            "--debug-console" => {
+                let debug_console = parse_debug_console(flag, value(flag, inline, &mut args)?)?;
+                set_once(&mut ch.debug_console, flag, debug_console)?;
+            }
+            "--landlock" => {
+                ch.landlock(true);
+            }
+            "--landlock-rules" => {
+                for v in values(flag, inline, &mut args)? {
+                    ch.landlock_rules(parse_landlock_rule(flag, v)?);
+                }
+            }
+            _ => {
+                return Err(ParseError::flag(flag, ParseErrorKind::UnknownFlag));
+            }
+        }
+    }
+
+    Ok(ch)
+}
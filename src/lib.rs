@@ -1,5 +1,31 @@
+pub mod api;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+pub mod capabilities;
+pub mod ch_remote;
+pub mod counters;
+pub mod discovery;
+pub mod encode;
+mod endpoints;
+pub mod error;
+pub mod event_monitor;
+pub mod from_command;
+pub mod lifecycle;
+pub mod migration;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod process;
+pub mod ready;
+pub mod resize;
+#[cfg(feature = "serial")]
+pub mod serial;
+pub mod snapshot;
 pub mod to_command;
+pub mod validate;
+pub mod version;
+pub mod vm_config;
 
+use std::ffi::OsString;
 use std::fmt::{Display, Formatter};
 use std::net::IpAddr;
 use std::path::PathBuf;
@@ -8,7 +34,12 @@ use bytesize::ByteSize;
 use derive_builder::Builder;
 use serde::{Deserialize, Serialize};
 
-use crate::to_command::ToCommand;
+use crate::encode::{
+    lossy_command, option_command, push_value, push_values, Encoder, OptionArgs, ToOptionValue,
+};
+use crate::error::Error;
+use crate::to_command::{ToCommand, TryToCommand};
+use crate::version::ChVersion;
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OnOff {
@@ -35,6 +66,21 @@ pub enum PathOrFileDescriptorOption {
     Fd(usize),
 }
 
+impl PathOrFileDescriptorOption {
+    fn to_option_value(&self, encoder: Encoder, option: &'static str) -> Result<String, Error> {
+        let mut arg = OptionArgs::new(encoder, option);
+        match self {
+            PathOrFileDescriptorOption::Path(path) => {
+                arg.push_path("path", path)?;
+            }
+            PathOrFileDescriptorOption::Fd(fd) => {
+                arg.push("fd", fd)?;
+            }
+        }
+        Ok(arg.finish().unwrap_or_default())
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 pub struct CloudHypervisorInstance {
     bin_path: PathBuf,
@@ -65,12 +111,16 @@ pub struct CloudHypervisorInstance {
     log_file: Option<PathBuf>,
     api_socket: Option<PathOrFileDescriptorOption>,
     event_monitor: Option<PathOrFileDescriptorOption>,
+    event_monitor_pipe: Option<bool>,
     restore: Option<Restore>,
     seccomp: Option<SecComp>,
     tpm: Option<PathBuf>,
     sgx_epc: Option<Vec<SgxEpc>>,
     debug_console: Option<DebugConsole>,
+    landlock: Option<bool>,
+    landlock_rules: Option<Vec<LandlockRule>>,
     v: Option<u8>,
+    target_version: Option<ChVersion>,
 }
 
 impl CloudHypervisorInstance {
@@ -253,6 +303,12 @@ impl CloudHypervisorInstance {
         self.event_monitor = Some(event_monitor);
         self
     }
+    // When spawned, `--event-monitor` is a pipe created for the process, replacing any
+    // `event_monitor()`. The read end is returned by `VmProcess::take_events()`.
+    pub fn event_monitor_pipe(&mut self, event_monitor_pipe: bool) -> &mut Self {
+        self.event_monitor_pipe = Some(event_monitor_pipe);
+        self
+    }
     pub fn restore(&mut self, restore: Restore) -> &mut Self {
         self.restore = Some(restore);
         self
@@ -281,6 +337,22 @@ impl CloudHypervisorInstance {
         self.debug_console = Some(debug_console);
         self
     }
+    pub fn landlock(&mut self, landlock: bool) -> &mut Self {
+        self.landlock = Some(landlock);
+        self
+    }
+    pub fn landlock_rules(&mut self, landlock_rule: LandlockRule) -> &mut Self {
+        match &mut self.landlock_rules {
+            None => {
+                self.landlock_rules = Some(vec![landlock_rule]);
+            }
+            Some(landlock_rules) => {
+                landlock_rules.push(landlock_rule);
+            }
+        }
+
+        self
+    }
     pub fn v(&mut self) -> &mut Self {
         match &mut self.v {
             None => self.v = Some(1),
@@ -290,661 +362,209 @@ impl CloudHypervisorInstance {
         }
         self
     }
+    // `TryToCommand` rejects options and keys the target release does not support. No keys were
+    // renamed between v38 and v41, so the spelling is the same for every release.
+    pub fn target_version(&mut self, target_version: ChVersion) -> &mut Self {
+        self.target_version = Some(target_version);
+        self
+    }
 }
 
-impl ToCommand for CloudHypervisorInstance {
-    fn to_command(&self) -> Vec<String> {
-        let mut cmd = vec![self.bin_path.display().to_string()];
+impl CloudHypervisorInstance {
+    // A VMM with the process level options of this instance (binary, api socket, event monitor,
+    // log file, seccomp, verbosity, target version) but no VM, as started for restoring a
+    // snapshot or receiving a migration.
+    pub(crate) fn vmm_instance(&self) -> CloudHypervisorInstance {
+        CloudHypervisorInstance {
+            bin_path: self.bin_path.clone(),
+            log_file: self.log_file.clone(),
+            api_socket: self.api_socket.clone(),
+            event_monitor: self.event_monitor.clone(),
+            event_monitor_pipe: self.event_monitor_pipe,
+            seccomp: self.seccomp.clone(),
+            v: self.v,
+            target_version: self.target_version,
+            ..CloudHypervisorInstance::default()
+        }
+    }
+}
+
+impl CloudHypervisorInstance {
+    fn render(&self, encoder: Encoder) -> Result<Vec<OsString>, Error> {
+        let encoder = encoder.with_version(self.target_version);
+        if encoder.is_strict() {
+            self.check_conflicts()?;
+        }
+
+        let mut cmd = vec![self.bin_path.clone().into_os_string()];
 
         if let Some(cpus) = &self.cpus {
-            cmd.append(cpus.to_command().as_mut());
+            push_value(&mut cmd, encoder, cpus)?;
         }
         if let Some(platform) = &self.platform {
-            cmd.append(platform.to_command().as_mut());
+            push_value(&mut cmd, encoder, platform)?;
         }
         if let Some(memory) = &self.memory {
-            cmd.append(memory.to_command().as_mut());
+            push_value(&mut cmd, encoder, memory)?;
         }
         if let Some(memory_zone) = &self.memory_zone {
-            cmd.append(memory_zone.to_command().as_mut());
+            push_value(&mut cmd, encoder, memory_zone)?;
         }
         if let Some(firmware) = &self.firmware {
-            cmd.push("--firmware".to_string());
-            cmd.push(firmware.display().to_string());
+            cmd.push("--firmware".into());
+            cmd.push(firmware.clone().into_os_string());
         }
         if let Some(kernel) = &self.kernel {
-            cmd.push("--kernel".to_string());
-            cmd.push(kernel.display().to_string());
+            cmd.push("--kernel".into());
+            cmd.push(kernel.clone().into_os_string());
         }
         if let Some(initramfs) = &self.initramfs {
-            cmd.push("--initramfs".to_string());
-            cmd.push(initramfs.display().to_string());
+            cmd.push("--initramfs".into());
+            cmd.push(initramfs.clone().into_os_string());
         }
         if let Some(cmdline) = &self.cmdline {
-            cmd.push("--cmdline".to_string());
-            cmd.push(cmdline.to_string());
+            cmd.push("--cmdline".into());
+            cmd.push(cmdline.into());
         }
         if let Some(rate_limit_groups) = &self.rate_limit_group {
-            let mut arg = vec![];
-            for rate_limit_group in rate_limit_groups {
-                if let Some(bw_size) = &rate_limit_group.bw_size {
-                    arg.push(format!("bw_size={}", bw_size.0));
-                }
-                if let Some(bw_one_time_burst) = &rate_limit_group.bw_one_time_burst {
-                    arg.push(format!("bw_one_time_burst={}", bw_one_time_burst));
-                }
-                if let Some(bw_refill_time) = &rate_limit_group.bw_refill_time {
-                    arg.push(format!("bw_refill_time={}", bw_refill_time));
-                }
-                if let Some(ops_size) = &rate_limit_group.ops_size {
-                    arg.push(format!("ops_size={}", ops_size));
-                }
-                if let Some(ops_one_time_burst) = &rate_limit_group.ops_one_time_burst {
-                    arg.push(format!("ops_one_time_burst={}", ops_one_time_burst));
-                }
-                if let Some(ops_refill_time) = &rate_limit_group.ops_refill_time {
-                    arg.push(format!("ops_refill_time={}", ops_refill_time));
-                }
-                if let Some(id) = &rate_limit_group.id {
-                    arg.push(format!("id={}", id));
-                }
-                if !arg.is_empty() {
-                    cmd.push("--rate_limit_group".to_string());
-                    cmd.push(arg.join(","));
-                }
-            }
+            push_values(&mut cmd, encoder, rate_limit_groups)?;
         }
         if let Some(disks) = &self.disk {
-            if !disks.is_empty() {
-                let mut added = false;
-
-                for disk in disks {
-                    let mut arg = vec![];
-                    if let Some(path) = &disk.path {
-                        arg.push(format!("path={}", path.display()));
-                    }
-                    if let Some(readonly) = &disk.readonly {
-                        arg.push(format!("readonly={}", readonly));
-                    }
-                    if let Some(direct) = &disk.direct {
-                        arg.push(format!("direct={}", direct));
-                    }
-                    if let Some(iommu) = &disk.iommu {
-                        arg.push(format!("iommu={}", iommu));
-                    }
-                    if let Some(num_queues) = &disk.num_queues {
-                        arg.push(format!("num_queues={}", num_queues));
-                    }
-                    if let Some(queue_size) = &disk.queue_size {
-                        arg.push(format!("queue_size={}", queue_size));
-                    }
-                    if let Some(vhost_user) = &disk.vhost_user {
-                        arg.push(format!("vhost_user={}", vhost_user));
-                    }
-                    if let Some(socket) = &disk.socket {
-                        arg.push(format!("socket={}", socket.display()));
-                    }
-                    if let Some(bw_size) = &disk.bw_size {
-                        arg.push(format!("bw_size={}", bw_size.0));
-                    }
-                    if let Some(bw_one_time_burst) = &disk.bw_one_time_burst {
-                        arg.push(format!("bw_one_time_burst={}", bw_one_time_burst));
-                    }
-                    if let Some(bw_refill_time) = &disk.bw_refill_time {
-                        arg.push(format!("bw_refill_time={}", bw_refill_time));
-                    }
-                    if let Some(ops_size) = &disk.ops_size {
-                        arg.push(format!("ops_size={}", ops_size));
-                    }
-                    if let Some(ops_one_time_burst) = &disk.ops_one_time_burst {
-                        arg.push(format!("ops_one_time_burst={}", ops_one_time_burst));
-                    }
-                    if let Some(ops_refill_time) = &disk.ops_refill_time {
-                        arg.push(format!("ops_refill_time={}", ops_refill_time));
-                    }
-                    if let Some(id) = &disk.id {
-                        arg.push(format!("id={}", id));
-                    }
-                    if let Some(pci_segment) = &disk.pci_segment {
-                        arg.push(format!("pci_segment={}", pci_segment));
-                    }
-                    if let Some(rate_limit_group) = &disk.rate_limit_group {
-                        arg.push(format!("rate_limit_group={}", rate_limit_group));
-                    }
-                    if let Some(queue_affinity) = &disk.queue_affinity {
-                        arg.push(format!("queue_affinity={}", queue_affinity));
-                    }
-                    if !arg.is_empty() {
-                        if !added {
-                            cmd.push("--disk".to_string());
-                            added = true;
-                        }
-                        cmd.push(arg.join(","));
-                    }
-                }
-            }
+            push_values(&mut cmd, encoder, disks)?;
         }
         if let Some(nets) = &self.net {
-            if !nets.is_empty() {
-                let mut added = false;
-
-                for net in nets {
-                    let mut arg = vec![];
-
-                    if let Some(tap) = &net.tap {
-                        arg.push(format!("tap={}", tap));
-                    }
-                    if let Some(ip) = &net.ip {
-                        arg.push(format!("ip={}", ip));
-                    }
-                    if let Some(mask) = &net.mask {
-                        arg.push(format!("mask={}", mask));
-                    }
-                    if let Some(mac) = &net.mac {
-                        arg.push(format!("mac={}", mac));
-                    }
-                    if let Some(fd) = &net.fd {
-                        arg.push(format!(
-                            "fd={}",
-                            fd.iter()
-                                .map(|v| v.to_string())
-                                .collect::<Vec<String>>()
-                                .join(",")
-                        ));
-                    }
-                    if let Some(iommu) = &net.iommu {
-                        arg.push(format!("iommu={}", iommu));
-                    }
-                    if let Some(num_queues) = &net.num_queues {
-                        arg.push(format!("num_queues={}", num_queues));
-                    }
-                    if let Some(queue_size) = &net.queue_size {
-                        arg.push(format!("queue_size={}", queue_size));
-                    }
-                    if let Some(id) = &net.id {
-                        arg.push(format!("id={}", id));
-                    }
-                    if let Some(vhost_user) = &net.vhost_user {
-                        arg.push(format!("vhost_user={}", vhost_user));
-                    }
-                    if let Some(socket) = &net.socket {
-                        arg.push(format!("socket={}", socket.display()));
-                    }
-                    if let Some(vhost_mode) = &net.vhost_mode {
-                        arg.push(format!("vhost_mode={}", vhost_mode));
-                    }
-                    if let Some(bw_size) = &net.bw_size {
-                        arg.push(format!("bw_size={}", bw_size.0));
-                    }
-                    if let Some(bw_one_time_burst) = &net.bw_one_time_burst {
-                        arg.push(format!("bw_one_time_burst={}", bw_one_time_burst));
-                    }
-                    if let Some(bw_refill_time) = &net.bw_refill_time {
-                        arg.push(format!("bw_refill_time={}", bw_refill_time));
-                    }
-                    if let Some(ops_size) = &net.ops_size {
-                        arg.push(format!("ops_size={}", ops_size));
-                    }
-                    if let Some(ops_one_time_burst) = &net.ops_one_time_burst {
-                        arg.push(format!("ops_one_time_burst={}", ops_one_time_burst));
-                    }
-                    if let Some(ops_refill_time) = &net.ops_refill_time {
-                        arg.push(format!("ops_refill_time={}", ops_refill_time));
-                    }
-                    if let Some(pci_segment) = &net.pci_segment {
-                        arg.push(format!("pci_segment={}", pci_segment));
-                    }
-                    if let Some(offload_tso) = &net.offload_tso {
-                        arg.push(format!("offload_tso={}", offload_tso));
-                    }
-                    if let Some(offload_ufo) = &net.offload_ufo {
-                        arg.push(format!("offload_ufo={}", offload_ufo));
-                    }
-                    if let Some(offload_csum) = &net.offload_csum {
-                        arg.push(format!("offload_csum={}", offload_csum));
-                    }
-                    if !arg.is_empty() {
-                        if !added {
-                            cmd.push("--net".to_string());
-                            added = true;
-                        }
-                        cmd.push(arg.join(","));
-                    }
-                }
-            }
+            push_values(&mut cmd, encoder, nets)?;
         }
         if let Some(rng) = &self.rng {
-            cmd.push("--rng".to_string());
-            match rng {
-                Rng::Src(path) => {
-                    cmd.push(format!("src={}", path.display()));
-                }
-                Rng::Iommu(state) => {
-                    cmd.push(format!("iommu={}", state));
-                }
-            }
+            push_value(&mut cmd, encoder, rng)?;
         }
         if let Some(balloon) = &self.balloon {
-            let mut arg = vec![];
-            if let Some(size) = &balloon.size {
-                arg.push(format!("size={}", size.0));
-            }
-            if let Some(deflate_on_oom) = &balloon.deflate_on_oom {
-                arg.push(format!("deflate_on_oom={}", deflate_on_oom));
-            }
-            if let Some(free_page_reporting) = &balloon.free_page_reporting {
-                arg.push(format!("free_page_reporting={}", free_page_reporting));
-            }
-            if !arg.is_empty() {
-                cmd.push("--balloon".to_string());
-                cmd.push(arg.join(","));
-            }
+            push_value(&mut cmd, encoder, balloon)?;
         }
         if let Some(fss) = &self.fs {
-            if !fss.is_empty() {
-                let mut added = false;
-
-                for fs in fss {
-                    let mut arg = vec![];
-                    if let Some(tag) = &fs.tag {
-                        arg.push(format!("tag={}", tag));
-                    }
-                    if let Some(socket) = &fs.socket {
-                        arg.push(format!("socket={}", socket.display()));
-                    }
-                    if let Some(num_queues) = &fs.num_queues {
-                        arg.push(format!("num_queues={}", num_queues));
-                    }
-                    if let Some(queue_size) = &fs.queue_size {
-                        arg.push(format!("queue_size={}", queue_size));
-                    }
-                    if let Some(id) = &fs.id {
-                        arg.push(format!("id={}", id));
-                    }
-                    if let Some(pci_segment) = &fs.pci_segment {
-                        arg.push(format!("pci_segment={}", pci_segment));
-                    }
-                    if !arg.is_empty() {
-                        if !added {
-                            cmd.push("--fs".to_string());
-                            added = true;
-                        }
-                        cmd.push(arg.join(","));
-                    }
-                }
-            }
+            push_values(&mut cmd, encoder, fss)?;
         }
         if let Some(pmems) = &self.pmem {
-            if !pmems.is_empty() {
-                let mut added = false;
-
-                for pmem in pmems {
-                    let mut arg = vec![];
-
-                    if let Some(path) = &pmem.file {
-                        arg.push(format!("file={}", path.display()));
-                    }
-                    if let Some(size) = &pmem.size {
-                        arg.push(format!("size={}", size));
-                    }
-                    if let Some(iommu) = &pmem.iommu {
-                        arg.push(format!("iommu={}", iommu));
-                    }
-                    if let Some(discard_writes) = &pmem.discard_writes {
-                        arg.push(format!("discard_writes={}", discard_writes));
-                    }
-                    if let Some(id) = &pmem.id {
-                        arg.push(format!("id={}", id));
-                    }
-                    if let Some(pci_segment) = &pmem.pci_segment {
-                        arg.push(format!("pci_segment={}", pci_segment));
-                    }
-                    if !arg.is_empty() {
-                        if !added {
-                            cmd.push("--pmem".to_string());
-                            added = true;
-                        }
-                        cmd.push(arg.join(","));
-                    }
-                }
-            }
+            push_values(&mut cmd, encoder, pmems)?;
         }
         if let Some(serial) = &self.serial {
-            cmd.push("--serial".to_string());
-            match serial {
-                Serial::Off => {
-                    cmd.push("off".to_string());
-                }
-                Serial::Null => {
-                    cmd.push("null".to_string());
-                }
-                Serial::Pty => {
-                    cmd.push("pty".to_string());
-                }
-                Serial::Tty => {
-                    cmd.push("tty".to_string());
-                }
-                Serial::File(path) => cmd.push(format!("file={}", path.display())),
-                Serial::Socket(path) => cmd.push(format!("socket={}", path.display())),
-            }
+            push_value(&mut cmd, encoder, serial)?;
         }
         if let Some(console) = &self.console {
-            cmd.push("--console".to_string());
-            match console {
-                Console::Off => {
-                    cmd.push("off".to_string());
-                }
-                Console::Null => {
-                    cmd.push("null".to_string());
-                }
-                Console::Pty => {
-                    cmd.push("pty".to_string());
-                }
-                Console::Tty => {
-                    cmd.push("tty".to_string());
-                }
-                Console::File(path) => cmd.push(format!("file={}", path.display())),
-                Console::Iommu(state) => {
-                    cmd.push(format!("iommu={}", state));
-                }
-            }
+            push_value(&mut cmd, encoder, console)?;
         }
         if let Some(devices) = &self.device {
-            if !devices.is_empty() {
-                let mut added = false;
-
-                for device in devices {
-                    let mut arg = vec![];
-
-                    if let Some(path) = &device.path {
-                        arg.push(format!("path={}", path.display()));
-                    }
-                    if let Some(iommu) = &device.iommu {
-                        arg.push(format!("iommu={}", iommu));
-                    }
-                    if let Some(id) = &device.id {
-                        arg.push(format!("id={}", id));
-                    }
-                    if let Some(pci_segment) = &device.pci_segment {
-                        arg.push(format!("pci_segment={}", pci_segment));
-                    }
-                    if !arg.is_empty() {
-                        if !added {
-                            cmd.push("--device".to_string());
-                            added = true;
-                        }
-                        cmd.push(arg.join(","));
-                    }
-                }
-            }
+            push_values(&mut cmd, encoder, devices)?;
         }
         if let Some(user_devices) = &self.user_device {
-            if !user_devices.is_empty() {
-                let mut added = false;
-
-                for user_device in user_devices {
-                    let mut arg = vec![];
-
-                    if let Some(path) = &user_device.socket {
-                        arg.push(format!("socket={}", path.display()));
-                    }
-                    if let Some(id) = &user_device.id {
-                        arg.push(format!("id={}", id));
-                    }
-                    if let Some(pci_segment) = &user_device.pci_segment {
-                        arg.push(format!("pci_segment={}", pci_segment));
-                    }
-                    if !arg.is_empty() {
-                        if !added {
-                            cmd.push("--user-device".to_string());
-                            added = true;
-                        }
-                        cmd.push(arg.join(","));
-                    }
-                }
-            }
+            push_values(&mut cmd, encoder, user_devices)?;
         }
         if let Some(vsock) = &self.vsock {
-            let mut arg = vec![];
-            if let Some(cid) = &vsock.cid {
-                arg.push(format!("cid={}", cid));
-            }
-            if let Some(path) = &vsock.socket {
-                arg.push(format!("socket={}", path.display()));
-            }
-            if let Some(iommu) = &vsock.iommu {
-                arg.push(format!("iommu={}", iommu));
-            }
-            if let Some(id) = &vsock.id {
-                arg.push(format!("id={}", id));
-            }
-            if let Some(pci_segment) = &vsock.pci_segment {
-                arg.push(format!("pci_segment={}", pci_segment));
-            }
-            if !arg.is_empty() {
-                cmd.push("--vsock".to_string());
-                cmd.push(arg.join(","));
-            }
+            push_value(&mut cmd, encoder, vsock)?;
         }
         if let Some(vdpas) = &self.vdpa {
-            if !vdpas.is_empty() {
-                let mut added = false;
-
-                for vdpa in vdpas {
-                    let mut arg = vec![];
-                    if let Some(path) = &vdpa.path {
-                        arg.push(format!("path={}", path.display()));
-                    }
-                    if let Some(num_queues) = &vdpa.num_queues {
-                        arg.push(format!("num_queues={}", num_queues));
-                    }
-                    if let Some(iommu) = &vdpa.iommu {
-                        arg.push(format!("iommu={}", iommu));
-                    }
-                    if let Some(id) = &vdpa.id {
-                        arg.push(format!("id={}", id));
-                    }
-                    if let Some(pci_segment) = &vdpa.pci_segment {
-                        arg.push(format!("pci_segment={}", pci_segment));
-                    }
-                    if !arg.is_empty() {
-                        if !added {
-                            cmd.push("--vdpa".to_string());
-                            added = true;
-                        }
-                        cmd.push(arg.join(","));
-                    }
-                }
-            }
+            push_values(&mut cmd, encoder, vdpas)?;
         }
         if let Some(pvpanic) = self.pvpanic {
             if pvpanic {
-                cmd.push("--pvpanic".to_string());
+                cmd.push("--pvpanic".into());
             }
         }
         if let Some(numas) = &self.numa {
-            if !numas.is_empty() {
-                let mut added = false;
-
-                for numa in numas {
-                    let mut arg = vec![];
-                    if let Some(guest_numa_id) = &numa.guest_numa_id {
-                        arg.push(format!("guest_numa_id={}", guest_numa_id));
-                    }
-                    if let Some(cpus) = &numa.cpus {
-                        arg.push(format!(
-                            "cpus={}",
-                            cpus.iter()
-                                .map(|v| v.to_string())
-                                .collect::<Vec<String>>()
-                                .join(",")
-                        ));
-                    }
-                    if let Some(distances) = &numa.distances {
-                        arg.push(format!(
-                            "distances={}",
-                            distances
-                                .iter()
-                                .map(|v| v.to_string())
-                                .collect::<Vec<String>>()
-                                .join(",")
-                        ));
-                    }
-                    if let Some(memory_zones) = &numa.memory_zones {
-                        arg.push(format!(
-                            "memory_zones={}",
-                            memory_zones
-                                .iter()
-                                .map(|v| v.to_string())
-                                .collect::<Vec<String>>()
-                                .join(",")
-                        ));
-                    }
-                    if let Some(sgx_epc_sections) = &numa.sgx_epc_sections {
-                        arg.push(format!(
-                            "sgx_epc_sections={}",
-                            sgx_epc_sections
-                                .iter()
-                                .map(|v| v.to_string())
-                                .collect::<Vec<String>>()
-                                .join(",")
-                        ));
-                    }
-                    if let Some(pci_segments) = &numa.pci_segments {
-                        arg.push(format!(
-                            "pci_segments={}",
-                            pci_segments
-                                .iter()
-                                .map(|v| v.to_string())
-                                .collect::<Vec<String>>()
-                                .join(",")
-                        ));
-                    }
-                    if !arg.is_empty() {
-                        if !added {
-                            cmd.push("--numa".to_string());
-                            added = true;
-                        }
-                        cmd.push(arg.join(","));
-                    }
-                }
-            }
+            push_values(&mut cmd, encoder, numas)?;
         }
         if let Some(watchdog) = self.watchdog {
             if watchdog {
-                cmd.push("--watchdog".to_string());
+                cmd.push("--watchdog".into());
             }
         }
         if let Some(log_file) = &self.log_file {
-            cmd.push("--log-file".to_string());
-            cmd.push(log_file.display().to_string());
+            cmd.push("--log-file".into());
+            cmd.push(log_file.clone().into_os_string());
         }
         if let Some(api_socket) = &self.api_socket {
-            cmd.push("--api-socket".to_string());
-            match api_socket {
-                PathOrFileDescriptorOption::Path(path) => {
-                    cmd.push(format!("path={}", path.display()));
-                }
-                PathOrFileDescriptorOption::Fd(fd) => {
-                    cmd.push(format!("fd={}", fd));
-                }
-            }
+            cmd.push("--api-socket".into());
+            cmd.push(api_socket.to_option_value(encoder, "--api-socket")?.into());
         }
         if let Some(event_monitor) = &self.event_monitor {
-            cmd.push("--event-monitor".to_string());
-            match event_monitor {
-                PathOrFileDescriptorOption::Path(path) => {
-                    cmd.push(format!("path={}", path.display()));
-                }
-                PathOrFileDescriptorOption::Fd(fd) => {
-                    cmd.push(format!("fd={}", fd));
-                }
-            }
+            cmd.push("--event-monitor".into());
+            cmd.push(
+                event_monitor
+                    .to_option_value(encoder, "--event-monitor")?
+                    .into(),
+            );
         }
         if let Some(restore) = &self.restore {
-            let mut arg = vec![];
-            if let Some(source_url) = &restore.source_url {
-                arg.push(format!("source_url={}", source_url));
-            }
-            if let Some(prefault) = &restore.prefault {
-                arg.push(format!("prefault={}", prefault));
-            }
-            if !arg.is_empty() {
-                cmd.push("--restore".to_string());
-                cmd.push(arg.join(","));
-            }
+            push_value(&mut cmd, encoder, restore)?;
         }
         if let Some(seccomp) = &self.seccomp {
-            cmd.push("--seccomp".to_string());
-            match seccomp {
-                SecComp::True => {
-                    cmd.push("true".to_string());
-                }
-                SecComp::False => {
-                    cmd.push("false".to_string());
-                }
-                SecComp::Log => {
-                    cmd.push("log".to_string());
-                }
-            }
+            push_value(&mut cmd, encoder, seccomp)?;
         }
         if let Some(tpm) = &self.tpm {
-            cmd.push("--tpm".to_string());
-            cmd.push(tpm.display().to_string());
+            cmd.push("--tpm".into());
+            cmd.push(tpm.clone().into_os_string());
         }
         if let Some(sgx_epcs) = &self.sgx_epc {
-            if !sgx_epcs.is_empty() {
-                let mut added = false;
-
-                for sgx_epc in sgx_epcs {
-                    let mut arg = vec![];
-                    if let Some(id) = &sgx_epc.id {
-                        arg.push(format!("id={}", id));
-                    }
-                    if let Some(size) = &sgx_epc.size {
-                        arg.push(format!("size={}", size));
-                    }
-                    if let Some(prefault) = &sgx_epc.prefault {
-                        arg.push(format!("prefault={}", prefault));
-                    }
-                    if !arg.is_empty() {
-                        if !added {
-                            cmd.push("--sgx-epc".to_string());
-                            added = true;
-                        }
-                        cmd.push(arg.join(","));
-                    }
-                }
-            }
+            push_values(&mut cmd, encoder, sgx_epcs)?;
         }
         if let Some(debug_console) = &self.debug_console {
-            let mut arg = vec![];
-            if let Some(ctype) = &debug_console.console_type {
-                match ctype {
-                    DebugConsoleType::Off => {
-                        arg.push("off".to_string());
-                    }
-                    DebugConsoleType::Pty => arg.push("pty".to_string()),
-                    DebugConsoleType::Tty => arg.push("tty".to_string()),
-                    DebugConsoleType::File(path) => {
-                        arg.push(format!("file={}", path.display()));
-                    }
-                }
-            }
-            if let Some(iobase) = &debug_console.iobase {
-                arg.push(format!("iobase={}", iobase));
-            }
-            if !arg.is_empty() {
-                cmd.push("--debug-console".to_string());
-                cmd.push(arg.join(","));
+            push_value(&mut cmd, encoder, debug_console)?;
+        }
+        if let Some(landlock) = self.landlock {
+            if landlock {
+                encoder.check_supported("--landlock", None)?;
+                cmd.push("--landlock".into());
             }
         }
+        if let Some(landlock_rules) = &self.landlock_rules {
+            push_values(&mut cmd, encoder, landlock_rules)?;
+        }
         if let Some(v) = self.v {
             for _ in 0..v {
-                cmd.push("-v".to_string());
+                cmd.push("-v".into());
             }
         }
-        cmd
+        Ok(cmd)
+    }
+
+    fn check_conflicts(&self) -> Result<(), Error> {
+        let mut ttys = vec![];
+        if let Some(Serial::Tty) = self.serial {
+            ttys.push("--serial");
+        }
+        if let Some(Console::Tty) = self.console {
+            ttys.push("--console");
+        }
+        if let Some(DebugConsole {
+            console_type: Some(DebugConsoleType::Tty),
+            ..
+        }) = self.debug_console
+        {
+            ttys.push("--debug-console");
+        }
+        if let [first, second, ..] = ttys.as_slice() {
+            return Err(Error::Conflict {
+                option: second.to_string(),
+                other: first.to_string(),
+                reason: "only one device can use tty".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl ToCommand for CloudHypervisorInstance {
+    fn to_command(&self) -> Vec<String> {
+        self.render(Encoder::lossy())
+            .map(lossy_command)
+            .unwrap_or_default()
+    }
+}
+
+impl TryToCommand for CloudHypervisorInstance {
+    fn try_to_command(&self) -> Result<Vec<OsString>, Error> {
+        self.render(Encoder::strict())
     }
 }
 
@@ -982,19 +602,19 @@ pub struct Cpus {
     pub features: Option<CpuFeatures>,
 }
 
-impl ToCommand for Cpus {
-    fn to_command(&self) -> Vec<String> {
-        let mut cmd = vec![];
+impl ToOptionValue for Cpus {
+    const OPTION: &'static str = "--cpus";
 
-        let mut arg: Vec<String> = vec![];
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::new(encoder, Self::OPTION);
         if let Some(boot) = self.boot {
-            arg.push(format!("boot={}", boot));
+            arg.push("boot", boot)?;
         }
         if let Some(max) = self.max {
-            arg.push(format!("max={}", max));
+            arg.push("max", max)?;
         }
         if let Some(topology) = &self.topology {
-            arg.push(format!(
+            arg.push_raw(format!(
                 "topology={}:{}:{}:{}",
                 topology.threads_per_core,
                 topology.cores_per_die,
@@ -1003,10 +623,10 @@ impl ToCommand for Cpus {
             ));
         }
         if let Some(kvm_hyperv) = &self.kvm_hyperv {
-            arg.push(format!("kvm_hyperv={}", kvm_hyperv));
+            arg.push("kvm_hyperv", kvm_hyperv)?;
         }
         if let Some(max_phys_bits) = self.max_phys_bits {
-            arg.push(format!("max_phys_bits={}", max_phys_bits));
+            arg.push("max_phys_bits", max_phys_bits)?;
         }
         if let Some(affinity) = &self.affinity {
             if !affinity.is_empty() {
@@ -1022,7 +642,7 @@ impl ToCommand for Cpus {
                             .join(",")
                     ));
                 }
-                arg.push(format!("affinity=[{}]", aarg.join(",")));
+                arg.push_raw(format!("affinity=[{}]", aarg.join(",")));
             }
         }
         if let Some(features) = &self.features {
@@ -1034,15 +654,25 @@ impl ToCommand for Cpus {
                 }
             }
             if !farg.is_empty() {
-                arg.push(format!("features={}", farg.join(",")));
+                arg.push_raw(format!("features={}", farg.join(",")));
             }
         }
-        if !arg.is_empty() {
-            cmd.push("--cpus".to_string());
-            cmd.push(arg.join(","));
-        }
 
-        cmd
+        Ok(arg.finish())
+    }
+}
+
+impl ToCommand for Cpus {
+    fn to_command(&self) -> Vec<String> {
+        option_command(self, Encoder::lossy())
+            .map(lossy_command)
+            .unwrap_or_default()
+    }
+}
+
+impl TryToCommand for Cpus {
+    fn try_to_command(&self) -> Result<Vec<OsString>, Error> {
+        option_command(self, Encoder::strict())
     }
 }
 
@@ -1055,35 +685,44 @@ pub struct Platform {
     pub uuid: Option<String>,
     pub oem_strings: Option<Vec<String>>,
 }
-impl ToCommand for Platform {
-    fn to_command(&self) -> Vec<String> {
-        let mut cmd = vec![];
-        let mut arg: Vec<String> = vec![];
+impl ToOptionValue for Platform {
+    const OPTION: &'static str = "--platform";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::new(encoder, Self::OPTION);
         if let Some(num_pci_segments) = &self.num_pci_segments {
-            arg.push(format!("num_pci_segments={}", num_pci_segments));
+            arg.push("num_pci_segments", num_pci_segments)?;
         }
-
         if let Some(iommu_segments) = &self.iommu_segments {
-            arg.push(format!("iommu_segments={}", iommu_segments));
+            arg.push("iommu_segments", iommu_segments)?;
         }
         if let Some(serial_number) = &self.serial_number {
-            arg.push(format!("serial_number={}", serial_number));
+            arg.push("serial_number", serial_number)?;
         }
         if let Some(uuid) = &self.uuid {
-            arg.push(format!("uuid={}", uuid));
+            arg.push("uuid", uuid)?;
         }
         if let Some(oem_strings) = &self.oem_strings {
             if !oem_strings.is_empty() {
-                arg.push(format!("oem_strings=[{}]", oem_strings.join(",")));
+                arg.push_list("oem_strings", oem_strings)?;
             }
         }
 
-        if !arg.is_empty() {
-            cmd.push("--platform".to_string());
-            cmd.push(arg.join(","));
-        }
+        Ok(arg.finish())
+    }
+}
+
+impl ToCommand for Platform {
+    fn to_command(&self) -> Vec<String> {
+        option_command(self, Encoder::lossy())
+            .map(lossy_command)
+            .unwrap_or_default()
+    }
+}
 
-        cmd
+impl TryToCommand for Platform {
+    fn try_to_command(&self) -> Result<Vec<OsString>, Error> {
+        option_command(self, Encoder::strict())
     }
 }
 
@@ -1120,57 +759,57 @@ pub struct Memory {
     pub thp: Option<OnOff>,
 }
 
-impl ToCommand for Memory {
-    fn to_command(&self) -> Vec<String> {
-        let mut cmd = vec![];
-        let mut arg: Vec<String> = vec![];
+impl ToOptionValue for Memory {
+    const OPTION: &'static str = "--memory";
 
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::new(encoder, Self::OPTION);
         if let Some(size) = &self.size {
-            arg.push(format!("size={}", size.0));
+            arg.push("size", size.0)?;
         }
-
         if let Some(mergeable) = &self.mergeable {
-            arg.push(format!("mergeable={}", mergeable));
+            arg.push("mergeable", mergeable)?;
         }
-
         if let Some(shared) = &self.shared {
-            arg.push(format!("shared={}", shared));
+            arg.push("shared", shared)?;
         }
-
         if let Some(hugepages) = &self.hugepages {
-            arg.push(format!("hugepages={}", hugepages));
+            arg.push("hugepages", hugepages)?;
         }
-
         if let Some(hugepage_size) = &self.hugepage_size {
-            arg.push(format!("hugepage_size={}", hugepage_size.0));
+            arg.push("hugepage_size", hugepage_size.0)?;
         }
-
         if let Some(hotplug_method) = &self.hotplug_method {
-            arg.push(format!("hotplug_method={}", hotplug_method));
+            arg.push("hotplug_method", hotplug_method)?;
         }
-
         if let Some(hotplug_size) = &self.hotplug_size {
-            arg.push(format!("hotplug_size={}", hotplug_size.0));
+            arg.push("hotplug_size", hotplug_size.0)?;
         }
-
         if let Some(hotplugged_size) = &self.hotplugged_size {
-            arg.push(format!("hotplugged_size={}", hotplugged_size.0));
+            arg.push("hotplugged_size", hotplugged_size.0)?;
         }
-
         if let Some(prefault) = &self.prefault {
-            arg.push(format!("prefault={}", prefault));
+            arg.push("prefault", prefault)?;
         }
-
         if let Some(thp) = &self.thp {
-            arg.push(format!("thp={}", thp));
+            arg.push("thp", thp)?;
         }
 
-        if !arg.is_empty() {
-            cmd.push("--memory".to_string());
-            cmd.push(arg.join(","));
-        }
+        Ok(arg.finish())
+    }
+}
 
-        cmd
+impl ToCommand for Memory {
+    fn to_command(&self) -> Vec<String> {
+        option_command(self, Encoder::lossy())
+            .map(lossy_command)
+            .unwrap_or_default()
+    }
+}
+
+impl TryToCommand for Memory {
+    fn try_to_command(&self) -> Result<Vec<OsString>, Error> {
+        option_command(self, Encoder::strict())
     }
 }
 
@@ -1189,57 +828,57 @@ pub struct MemoryZone {
     pub prefault: Option<OnOff>,
 }
 
-impl ToCommand for MemoryZone {
-    fn to_command(&self) -> Vec<String> {
-        let mut cmd = vec![];
-        let mut arg: Vec<String> = vec![];
+impl ToOptionValue for MemoryZone {
+    const OPTION: &'static str = "--memory-zone";
 
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::with_id(encoder, Self::OPTION, self.id.as_ref());
         if let Some(size) = &self.size {
-            arg.push(format!("size={}", size.0));
+            arg.push("size", size.0)?;
         }
-
         if let Some(path) = &self.file {
-            arg.push(format!("file={}", path.display()));
+            arg.push_path("file", path)?;
         }
-
         if let Some(shared) = &self.shared {
-            arg.push(format!("shared={}", shared));
+            arg.push("shared", shared)?;
         }
-
         if let Some(hugepages) = &self.hugepages {
-            arg.push(format!("hugepages={}", hugepages));
+            arg.push("hugepages", hugepages)?;
         }
-
         if let Some(hugepage_size) = &self.hugepage_size {
-            arg.push(format!("hugepage_size={}", hugepage_size.0));
+            arg.push("hugepage_size", hugepage_size.0)?;
         }
-
         if let Some(host_numa_node) = &self.host_numa_node {
-            arg.push(format!("host_numa_node={}", host_numa_node));
+            arg.push("host_numa_node", host_numa_node)?;
         }
-
         if let Some(id) = &self.id {
-            arg.push(format!("id={}", id));
+            arg.push("id", id)?;
         }
-
         if let Some(hotplug_size) = &self.hotplug_size {
-            arg.push(format!("hotplug_size={}", hotplug_size.0));
+            arg.push("hotplug_size", hotplug_size.0)?;
         }
-
         if let Some(hotplugged_size) = &self.hotplugged_size {
-            arg.push(format!("hotplugged_size={}", hotplugged_size.0));
+            arg.push("hotplugged_size", hotplugged_size.0)?;
         }
-
         if let Some(prefault) = &self.prefault {
-            arg.push(format!("prefault={}", prefault));
+            arg.push("prefault", prefault)?;
         }
 
-        if !arg.is_empty() {
-            cmd.push("--memory-zone".to_string());
-            cmd.push(arg.join(","));
-        }
+        Ok(arg.finish())
+    }
+}
 
-        cmd
+impl ToCommand for MemoryZone {
+    fn to_command(&self) -> Vec<String> {
+        option_command(self, Encoder::lossy())
+            .map(lossy_command)
+            .unwrap_or_default()
+    }
+}
+
+impl TryToCommand for MemoryZone {
+    fn try_to_command(&self) -> Result<Vec<OsString>, Error> {
+        option_command(self, Encoder::strict())
     }
 }
 
@@ -1255,6 +894,37 @@ pub struct RateLimitGroup {
     pub id: Option<String>,
 }
 
+impl ToOptionValue for RateLimitGroup {
+    const OPTION: &'static str = "--rate-limit-group";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::with_id(encoder, Self::OPTION, self.id.as_ref());
+        if let Some(bw_size) = &self.bw_size {
+            arg.push("bw_size", bw_size.0)?;
+        }
+        if let Some(bw_one_time_burst) = &self.bw_one_time_burst {
+            arg.push("bw_one_time_burst", bw_one_time_burst.0)?;
+        }
+        if let Some(bw_refill_time) = &self.bw_refill_time {
+            arg.push("bw_refill_time", bw_refill_time)?;
+        }
+        if let Some(ops_size) = &self.ops_size {
+            arg.push("ops_size", ops_size)?;
+        }
+        if let Some(ops_one_time_burst) = &self.ops_one_time_burst {
+            arg.push("ops_one_time_burst", ops_one_time_burst)?;
+        }
+        if let Some(ops_refill_time) = &self.ops_refill_time {
+            arg.push("ops_refill_time", ops_refill_time)?;
+        }
+        if let Some(id) = &self.id {
+            arg.push("id", id)?;
+        }
+
+        Ok(arg.finish())
+    }
+}
+
 #[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[builder(setter(strip_option, into), default)]
 pub struct Disk {
@@ -1278,6 +948,71 @@ pub struct Disk {
     pub queue_affinity: Option<String>,
 }
 
+impl ToOptionValue for Disk {
+    const OPTION: &'static str = "--disk";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::with_id(encoder, Self::OPTION, self.id.as_ref());
+        if let Some(path) = &self.path {
+            arg.push_path("path", path)?;
+        }
+        if let Some(readonly) = &self.readonly {
+            arg.push("readonly", readonly)?;
+        }
+        if let Some(direct) = &self.direct {
+            arg.push("direct", direct)?;
+        }
+        if let Some(iommu) = &self.iommu {
+            arg.push("iommu", iommu)?;
+        }
+        if let Some(num_queues) = &self.num_queues {
+            arg.push("num_queues", num_queues)?;
+        }
+        if let Some(queue_size) = &self.queue_size {
+            arg.push("queue_size", queue_size)?;
+        }
+        if let Some(vhost_user) = &self.vhost_user {
+            arg.push("vhost_user", vhost_user)?;
+        }
+        if let Some(socket) = &self.socket {
+            arg.push_path("socket", socket)?;
+        }
+        if let Some(bw_size) = &self.bw_size {
+            arg.push("bw_size", bw_size.0)?;
+        }
+        if let Some(bw_one_time_burst) = &self.bw_one_time_burst {
+            arg.push("bw_one_time_burst", bw_one_time_burst.0)?;
+        }
+        if let Some(bw_refill_time) = &self.bw_refill_time {
+            arg.push("bw_refill_time", bw_refill_time)?;
+        }
+        if let Some(ops_size) = &self.ops_size {
+            arg.push("ops_size", ops_size)?;
+        }
+        if let Some(ops_one_time_burst) = &self.ops_one_time_burst {
+            arg.push("ops_one_time_burst", ops_one_time_burst)?;
+        }
+        if let Some(ops_refill_time) = &self.ops_refill_time {
+            arg.push("ops_refill_time", ops_refill_time)?;
+        }
+        if let Some(id) = &self.id {
+            arg.push("id", id)?;
+        }
+        if let Some(pci_segment) = &self.pci_segment {
+            arg.push("pci_segment", pci_segment)?;
+        }
+        if let Some(rate_limit_group) = &self.rate_limit_group {
+            arg.push("rate_limit_group", rate_limit_group)?;
+        }
+        if let Some(queue_affinity) = &self.queue_affinity {
+            // Already in cloud-hypervisor's `[queue@[cpus]]` list syntax
+            arg.push_raw(format!("queue_affinity={}", queue_affinity));
+        }
+
+        Ok(arg.finish())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum VhostMode {
     Client,
@@ -1322,12 +1057,105 @@ pub struct Net {
     pub offload_ufo: Option<OnOff>,
     pub offload_csum: Option<OnOff>,
 }
+
+impl ToOptionValue for Net {
+    const OPTION: &'static str = "--net";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::with_id(encoder, Self::OPTION, self.id.as_ref());
+        if let Some(tap) = &self.tap {
+            arg.push("tap", tap)?;
+        }
+        if let Some(ip) = &self.ip {
+            arg.push("ip", ip)?;
+        }
+        if let Some(mask) = &self.mask {
+            arg.push("mask", mask)?;
+        }
+        if let Some(mac) = &self.mac {
+            arg.push("mac", mac)?;
+        }
+        if let Some(fd) = &self.fd {
+            arg.push_list("fd", fd)?;
+        }
+        if let Some(iommu) = &self.iommu {
+            arg.push("iommu", iommu)?;
+        }
+        if let Some(num_queues) = &self.num_queues {
+            arg.push("num_queues", num_queues)?;
+        }
+        if let Some(queue_size) = &self.queue_size {
+            arg.push("queue_size", queue_size)?;
+        }
+        if let Some(id) = &self.id {
+            arg.push("id", id)?;
+        }
+        if let Some(vhost_user) = &self.vhost_user {
+            arg.push("vhost_user", vhost_user)?;
+        }
+        if let Some(socket) = &self.socket {
+            arg.push_path("socket", socket)?;
+        }
+        if let Some(vhost_mode) = &self.vhost_mode {
+            arg.push("vhost_mode", vhost_mode)?;
+        }
+        if let Some(bw_size) = &self.bw_size {
+            arg.push("bw_size", bw_size.0)?;
+        }
+        if let Some(bw_one_time_burst) = &self.bw_one_time_burst {
+            arg.push("bw_one_time_burst", bw_one_time_burst.0)?;
+        }
+        if let Some(bw_refill_time) = &self.bw_refill_time {
+            arg.push("bw_refill_time", bw_refill_time)?;
+        }
+        if let Some(ops_size) = &self.ops_size {
+            arg.push("ops_size", ops_size)?;
+        }
+        if let Some(ops_one_time_burst) = &self.ops_one_time_burst {
+            arg.push("ops_one_time_burst", ops_one_time_burst)?;
+        }
+        if let Some(ops_refill_time) = &self.ops_refill_time {
+            arg.push("ops_refill_time", ops_refill_time)?;
+        }
+        if let Some(pci_segment) = &self.pci_segment {
+            arg.push("pci_segment", pci_segment)?;
+        }
+        if let Some(offload_tso) = &self.offload_tso {
+            arg.push("offload_tso", offload_tso)?;
+        }
+        if let Some(offload_ufo) = &self.offload_ufo {
+            arg.push("offload_ufo", offload_ufo)?;
+        }
+        if let Some(offload_csum) = &self.offload_csum {
+            arg.push("offload_csum", offload_csum)?;
+        }
+
+        Ok(arg.finish())
+    }
+}
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Rng {
     Src(PathBuf),
     Iommu(OnOff),
 }
 
+impl ToOptionValue for Rng {
+    const OPTION: &'static str = "--rng";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::new(encoder, Self::OPTION);
+        match self {
+            Rng::Src(path) => {
+                arg.push_path("src", path)?;
+            }
+            Rng::Iommu(state) => {
+                arg.push("iommu", state)?;
+            }
+        }
+        Ok(arg.finish())
+    }
+}
+
 #[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[builder(setter(strip_option, into), default)]
 pub struct Balloon {
@@ -1336,6 +1164,25 @@ pub struct Balloon {
     pub free_page_reporting: Option<OnOff>,
 }
 
+impl ToOptionValue for Balloon {
+    const OPTION: &'static str = "--balloon";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::new(encoder, Self::OPTION);
+        if let Some(size) = &self.size {
+            arg.push("size", size.0)?;
+        }
+        if let Some(deflate_on_oom) = &self.deflate_on_oom {
+            arg.push("deflate_on_oom", deflate_on_oom)?;
+        }
+        if let Some(free_page_reporting) = &self.free_page_reporting {
+            arg.push("free_page_reporting", free_page_reporting)?;
+        }
+
+        Ok(arg.finish())
+    }
+}
+
 #[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[builder(setter(strip_option, into), default)]
 pub struct Fs {
@@ -1347,6 +1194,34 @@ pub struct Fs {
     pub pci_segment: Option<String>,
 }
 
+impl ToOptionValue for Fs {
+    const OPTION: &'static str = "--fs";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::with_id(encoder, Self::OPTION, self.id.as_ref());
+        if let Some(tag) = &self.tag {
+            arg.push("tag", tag)?;
+        }
+        if let Some(socket) = &self.socket {
+            arg.push_path("socket", socket)?;
+        }
+        if let Some(num_queues) = &self.num_queues {
+            arg.push("num_queues", num_queues)?;
+        }
+        if let Some(queue_size) = &self.queue_size {
+            arg.push("queue_size", queue_size)?;
+        }
+        if let Some(id) = &self.id {
+            arg.push("id", id)?;
+        }
+        if let Some(pci_segment) = &self.pci_segment {
+            arg.push("pci_segment", pci_segment)?;
+        }
+
+        Ok(arg.finish())
+    }
+}
+
 #[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[builder(setter(strip_option, into), default)]
 pub struct Pmem {
@@ -1358,6 +1233,34 @@ pub struct Pmem {
     pub pci_segment: Option<String>,
 }
 
+impl ToOptionValue for Pmem {
+    const OPTION: &'static str = "--pmem";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::with_id(encoder, Self::OPTION, self.id.as_ref());
+        if let Some(file) = &self.file {
+            arg.push_path("file", file)?;
+        }
+        if let Some(size) = &self.size {
+            arg.push("size", size)?;
+        }
+        if let Some(iommu) = &self.iommu {
+            arg.push("iommu", iommu)?;
+        }
+        if let Some(discard_writes) = &self.discard_writes {
+            arg.push("discard_writes", discard_writes)?;
+        }
+        if let Some(id) = &self.id {
+            arg.push("id", id)?;
+        }
+        if let Some(pci_segment) = &self.pci_segment {
+            arg.push("pci_segment", pci_segment)?;
+        }
+
+        Ok(arg.finish())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Serial {
     Off,
@@ -1367,6 +1270,23 @@ pub enum Serial {
     File(PathBuf),
     Socket(PathBuf),
 }
+
+impl ToOptionValue for Serial {
+    const OPTION: &'static str = "--serial";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::new(encoder, Self::OPTION);
+        match self {
+            Serial::Off => arg.push_raw("off"),
+            Serial::Null => arg.push_raw("null"),
+            Serial::Pty => arg.push_raw("pty"),
+            Serial::Tty => arg.push_raw("tty"),
+            Serial::File(path) => arg.push_path("file", path)?,
+            Serial::Socket(path) => arg.push_path("socket", path)?,
+        }
+        Ok(arg.finish())
+    }
+}
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Console {
     Off,
@@ -1377,6 +1297,23 @@ pub enum Console {
     Iommu(OnOff),
 }
 
+impl ToOptionValue for Console {
+    const OPTION: &'static str = "--console";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::new(encoder, Self::OPTION);
+        match self {
+            Console::Off => arg.push_raw("off"),
+            Console::Null => arg.push_raw("null"),
+            Console::Pty => arg.push_raw("pty"),
+            Console::Tty => arg.push_raw("tty"),
+            Console::File(path) => arg.push_path("file", path)?,
+            Console::Iommu(state) => arg.push("iommu", state)?,
+        }
+        Ok(arg.finish())
+    }
+}
+
 #[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[builder(setter(strip_option, into), default)]
 pub struct Device {
@@ -1385,6 +1322,28 @@ pub struct Device {
     pub id: Option<String>,
     pub pci_segment: Option<String>,
 }
+
+impl ToOptionValue for Device {
+    const OPTION: &'static str = "--device";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::with_id(encoder, Self::OPTION, self.id.as_ref());
+        if let Some(path) = &self.path {
+            arg.push_path("path", path)?;
+        }
+        if let Some(iommu) = &self.iommu {
+            arg.push("iommu", iommu)?;
+        }
+        if let Some(id) = &self.id {
+            arg.push("id", id)?;
+        }
+        if let Some(pci_segment) = &self.pci_segment {
+            arg.push("pci_segment", pci_segment)?;
+        }
+
+        Ok(arg.finish())
+    }
+}
 #[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[builder(setter(strip_option, into), default)]
 pub struct UserDevice {
@@ -1393,6 +1352,25 @@ pub struct UserDevice {
     pub pci_segment: Option<String>,
 }
 
+impl ToOptionValue for UserDevice {
+    const OPTION: &'static str = "--user-device";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::with_id(encoder, Self::OPTION, self.id.as_ref());
+        if let Some(socket) = &self.socket {
+            arg.push_path("socket", socket)?;
+        }
+        if let Some(id) = &self.id {
+            arg.push("id", id)?;
+        }
+        if let Some(pci_segment) = &self.pci_segment {
+            arg.push("pci_segment", pci_segment)?;
+        }
+
+        Ok(arg.finish())
+    }
+}
+
 #[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[builder(setter(strip_option, into), default)]
 pub struct Vdpa {
@@ -1402,6 +1380,31 @@ pub struct Vdpa {
     pub id: Option<String>,
     pub pci_segment: Option<String>,
 }
+
+impl ToOptionValue for Vdpa {
+    const OPTION: &'static str = "--vdpa";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::with_id(encoder, Self::OPTION, self.id.as_ref());
+        if let Some(path) = &self.path {
+            arg.push_path("path", path)?;
+        }
+        if let Some(num_queues) = &self.num_queues {
+            arg.push("num_queues", num_queues)?;
+        }
+        if let Some(iommu) = &self.iommu {
+            arg.push("iommu", iommu)?;
+        }
+        if let Some(id) = &self.id {
+            arg.push("id", id)?;
+        }
+        if let Some(pci_segment) = &self.pci_segment {
+            arg.push("pci_segment", pci_segment)?;
+        }
+
+        Ok(arg.finish())
+    }
+}
 #[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[builder(setter(strip_option, into), default)]
 pub struct Vsock {
@@ -1412,23 +1415,104 @@ pub struct Vsock {
     pub pci_segment: Option<String>,
 }
 
+impl ToOptionValue for Vsock {
+    const OPTION: &'static str = "--vsock";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::with_id(encoder, Self::OPTION, self.id.as_ref());
+        if let Some(cid) = &self.cid {
+            arg.push("cid", cid)?;
+        }
+        if let Some(socket) = &self.socket {
+            arg.push_path("socket", socket)?;
+        }
+        if let Some(iommu) = &self.iommu {
+            arg.push("iommu", iommu)?;
+        }
+        if let Some(id) = &self.id {
+            arg.push("id", id)?;
+        }
+        if let Some(pci_segment) = &self.pci_segment {
+            arg.push("pci_segment", pci_segment)?;
+        }
+
+        Ok(arg.finish())
+    }
+}
+
+// `distances=[<destination>@<distance>,...]`
+#[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[builder(setter(strip_option), default)]
+pub struct NumaDistance {
+    pub destination: u32,
+    pub distance: u8,
+}
+
 #[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[builder(setter(strip_option, into), default)]
 pub struct Numa {
     pub guest_numa_id: Option<String>,
     pub cpus: Option<Vec<usize>>,
-    pub distances: Option<Vec<usize>>,
+    pub distances: Option<Vec<NumaDistance>>,
     pub memory_zones: Option<Vec<String>>,
     pub sgx_epc_sections: Option<Vec<String>>,
     pub pci_segments: Option<Vec<String>>,
 }
 
+impl ToOptionValue for Numa {
+    const OPTION: &'static str = "--numa";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::with_id(encoder, Self::OPTION, self.guest_numa_id.as_ref());
+        if let Some(guest_numa_id) = &self.guest_numa_id {
+            arg.push("guest_numa_id", guest_numa_id)?;
+        }
+        if let Some(cpus) = &self.cpus {
+            arg.push_list("cpus", cpus)?;
+        }
+        if let Some(distances) = &self.distances {
+            let distances = distances
+                .iter()
+                .map(|d| format!("{}@{}", d.destination, d.distance))
+                .collect::<Vec<String>>();
+            arg.push_raw(format!("distances=[{}]", distances.join(",")));
+        }
+        if let Some(memory_zones) = &self.memory_zones {
+            arg.push_list("memory_zones", memory_zones)?;
+        }
+        if let Some(sgx_epc_sections) = &self.sgx_epc_sections {
+            arg.push_list("sgx_epc_sections", sgx_epc_sections)?;
+        }
+        if let Some(pci_segments) = &self.pci_segments {
+            arg.push_list("pci_segments", pci_segments)?;
+        }
+
+        Ok(arg.finish())
+    }
+}
+
 #[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[builder(setter(strip_option, into), default)]
 pub struct Restore {
     pub source_url: Option<String>,
     pub prefault: Option<OnOff>,
 }
+
+impl ToOptionValue for Restore {
+    const OPTION: &'static str = "--restore";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::new(encoder, Self::OPTION);
+        if let Some(source_url) = &self.source_url {
+            arg.push("source_url", source_url)?;
+        }
+        if let Some(prefault) = &self.prefault {
+            arg.push("prefault", prefault)?;
+        }
+
+        Ok(arg.finish())
+    }
+}
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum SecComp {
     True,
@@ -1436,6 +1520,20 @@ pub enum SecComp {
     Log,
 }
 
+impl ToOptionValue for SecComp {
+    const OPTION: &'static str = "--seccomp";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::new(encoder, Self::OPTION);
+        match self {
+            SecComp::True => arg.push_raw("true"),
+            SecComp::False => arg.push_raw("false"),
+            SecComp::Log => arg.push_raw("log"),
+        }
+        Ok(arg.finish())
+    }
+}
+
 #[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[builder(setter(strip_option, into), default)]
 pub struct SgxEpc {
@@ -1444,6 +1542,25 @@ pub struct SgxEpc {
     pub prefault: Option<OnOff>,
 }
 
+impl ToOptionValue for SgxEpc {
+    const OPTION: &'static str = "--sgx-epc";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::with_id(encoder, Self::OPTION, self.id.as_ref());
+        if let Some(id) = &self.id {
+            arg.push("id", id)?;
+        }
+        if let Some(size) = &self.size {
+            arg.push("size", size)?;
+        }
+        if let Some(prefault) = &self.prefault {
+            arg.push("prefault", prefault)?;
+        }
+
+        Ok(arg.finish())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum DebugConsoleType {
     Off,
@@ -1458,3 +1575,45 @@ pub struct DebugConsole {
     pub console_type: Option<DebugConsoleType>,
     pub iobase: Option<String>,
 }
+
+impl ToOptionValue for DebugConsole {
+    const OPTION: &'static str = "--debug-console";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::new(encoder, Self::OPTION);
+        if let Some(console_type) = &self.console_type {
+            match console_type {
+                DebugConsoleType::Off => arg.push_raw("off"),
+                DebugConsoleType::Pty => arg.push_raw("pty"),
+                DebugConsoleType::Tty => arg.push_raw("tty"),
+                DebugConsoleType::File(path) => arg.push_path("file", path)?,
+            }
+        }
+        if let Some(iobase) = &self.iobase {
+            arg.push("iobase", iobase)?;
+        }
+        Ok(arg.finish())
+    }
+}
+
+#[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[builder(setter(strip_option, into), default)]
+pub struct LandlockRule {
+    pub path: Option<PathBuf>,
+    pub access: Option<String>,
+}
+
+impl ToOptionValue for LandlockRule {
+    const OPTION: &'static str = "--landlock-rules";
+
+    fn to_option_value(&self, encoder: Encoder) -> Result<Option<String>, Error> {
+        let mut arg = OptionArgs::new(encoder, Self::OPTION);
+        if let Some(path) = &self.path {
+            arg.push_path("path", path)?;
+        }
+        if let Some(access) = &self.access {
+            arg.push("access", access)?;
+        }
+        Ok(arg.finish())
+    }
+}
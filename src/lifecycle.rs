@@ -0,0 +1,189 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::io::Read;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use crate::event_monitor::{Event, EventError, EventReader, EventRecord};
+
+// The state of a VM as seen through its event monitor. Unlike `api::VmState` this includes the
+// transient states cloud-hypervisor only reports as events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VmState {
+    Created,
+    Booting,
+    Running,
+    Paused,
+    Rebooting,
+    Shutdown,
+    // The guest reported a panic through `--pvpanic`.
+    Panicked,
+    // The VMM is gone; no later event changes the state.
+    Exited,
+}
+
+impl VmState {
+    // The state `event` moves to, `None` for events that do not change it.
+    fn after(event: &Event) -> Option<VmState> {
+        match event {
+            Event::VmBooting => Some(VmState::Booting),
+            Event::VmBooted | Event::VmResumed | Event::VmRebooted => Some(VmState::Running),
+            // A restored VM waits to be resumed.
+            Event::VmPaused | Event::VmRestored => Some(VmState::Paused),
+            // Includes resets by the `--watchdog` device.
+            Event::VmRebooting => Some(VmState::Rebooting),
+            Event::VmShutdown => Some(VmState::Shutdown),
+            Event::GuestPanic => Some(VmState::Panicked),
+            Event::VmmShutdown | Event::VmmPanic => Some(VmState::Exited),
+            _ => None,
+        }
+    }
+}
+
+impl Display for VmState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = match self {
+            VmState::Created => "created",
+            VmState::Booting => "booting",
+            VmState::Running => "running",
+            VmState::Paused => "paused",
+            VmState::Rebooting => "rebooting",
+            VmState::Shutdown => "shutdown",
+            VmState::Panicked => "panicked",
+            VmState::Exited => "exited",
+        };
+        write!(f, "{}", state)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transition {
+    pub from: VmState,
+    pub to: VmState,
+    // `None` when the VMM process was seen exiting rather than reporting an event.
+    pub event: Option<Event>,
+    // The event's time since the VMM started.
+    pub timestamp: Option<Duration>,
+    // When the transition was applied.
+    pub at: Instant,
+}
+
+enum Subscriber {
+    Callback(Box<dyn FnMut(&Transition) + Send>),
+    Channel(Sender<Transition>),
+}
+
+// Tracks one VM's state from its monitor events and tells subscribers about every change.
+pub struct Lifecycle {
+    state: VmState,
+    since: Instant,
+    history: Vec<Transition>,
+    subscribers: Vec<Subscriber>,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for Lifecycle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Lifecycle")
+            .field("state", &self.state)
+            .field("since", &self.since)
+            .field("history", &self.history)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Lifecycle {
+            state: VmState::Created,
+            since: Instant::now(),
+            history: vec![],
+            subscribers: vec![],
+        }
+    }
+
+    pub fn state(&self) -> VmState {
+        self.state
+    }
+
+    // When the current state was entered.
+    pub fn since(&self) -> Instant {
+        self.since
+    }
+
+    pub fn history(&self) -> &[Transition] {
+        &self.history
+    }
+
+    // Called on the thread applying the events, for every transition after subscribing.
+    pub fn on_transition(
+        &mut self,
+        callback: impl FnMut(&Transition) + Send + 'static,
+    ) -> &mut Self {
+        self.subscribers
+            .push(Subscriber::Callback(Box::new(callback)));
+        self
+    }
+
+    // Receives every transition after subscribing. Dropping the receiver unsubscribes.
+    pub fn subscribe(&mut self) -> Receiver<Transition> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(Subscriber::Channel(sender));
+        receiver
+    }
+
+    pub fn apply(&mut self, record: &EventRecord) -> Option<Transition> {
+        let to = VmState::after(&record.event)?;
+        self.transition(to, Some(record.event.clone()), Some(record.timestamp))
+    }
+
+    // Applies every event `events` has available, returning how many transitions they caused.
+    pub fn apply_all<R: Read>(&mut self, events: &mut EventReader<R>) -> Result<usize, EventError> {
+        let mut transitions = 0;
+        while let Some(record) = events.next_event()? {
+            if self.apply(&record).is_some() {
+                transitions += 1;
+            }
+        }
+        Ok(transitions)
+    }
+
+    // For a VMM that exited without reporting it, e.g. when killed.
+    pub fn process_exited(&mut self) -> Option<Transition> {
+        self.transition(VmState::Exited, None, None)
+    }
+
+    fn transition(
+        &mut self,
+        to: VmState,
+        event: Option<Event>,
+        timestamp: Option<Duration>,
+    ) -> Option<Transition> {
+        if self.state == to || self.state == VmState::Exited {
+            return None;
+        }
+        let transition = Transition {
+            from: self.state,
+            to,
+            event,
+            timestamp,
+            at: Instant::now(),
+        };
+        self.state = to;
+        self.since = transition.at;
+        self.history.push(transition.clone());
+
+        self.subscribers.retain_mut(|subscriber| match subscriber {
+            Subscriber::Callback(callback) => {
+                callback(&transition);
+                true
+            }
+            Subscriber::Channel(sender) => sender.send(transition.clone()).is_ok(),
+        });
+        Some(transition)
+    }
+}
@@ -0,0 +1,264 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::api::{ApiClient, ApiError};
+use crate::endpoints;
+use crate::process::{InheritedFds, SpawnError, VmExit, VmProcess};
+use crate::{CloudHypervisorInstance, PathOrFileDescriptorOption};
+
+#[derive(Debug)]
+pub enum MigrationError {
+    // The destination needs `--api-socket` with a path to be driven.
+    NoApiSocket,
+    Spawn(SpawnError),
+    Io(io::Error),
+    DestinationExited(VmExit),
+    Timeout(MigrationStage),
+    Receive(ApiError),
+    Send(ApiError),
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::NoApiSocket => {
+                write!(f, "migration: destination has no --api-socket path")
+            }
+            MigrationError::Spawn(err) => write!(f, "migration: {}", err),
+            MigrationError::Io(err) => write!(f, "migration: {}", err),
+            MigrationError::DestinationExited(exit) => {
+                write!(f, "migration: destination exited: {}", exit.reason)
+            }
+            MigrationError::Timeout(stage) => write!(f, "migration: timed out {}", stage),
+            MigrationError::Receive(err) => write!(f, "migration: receiving failed: {}", err),
+            MigrationError::Send(err) => write!(f, "migration: sending failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MigrationError::Spawn(err) => Some(err),
+            MigrationError::Io(err) => Some(err),
+            MigrationError::Receive(err) => Some(err),
+            MigrationError::Send(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<SpawnError> for MigrationError {
+    fn from(err: SpawnError) -> Self {
+        MigrationError::Spawn(err)
+    }
+}
+
+impl From<io::Error> for MigrationError {
+    fn from(err: io::Error) -> Self {
+        MigrationError::Io(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationStage {
+    StartingDestination,
+    Receiving,
+    Sending,
+    Completed,
+}
+
+impl Display for MigrationStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationStage::StartingDestination => write!(f, "starting the destination"),
+            MigrationStage::Receiving => write!(f, "waiting for the destination to receive"),
+            MigrationStage::Sending => write!(f, "sending"),
+            MigrationStage::Completed => write!(f, "completed"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct VmReceiveMigrationData<'a> {
+    pub(crate) receiver_url: &'a str,
+}
+
+#[derive(Serialize)]
+pub(crate) struct VmSendMigrationData<'a> {
+    pub(crate) destination_url: &'a str,
+    pub(crate) local: bool,
+}
+
+impl ApiClient {
+    // Both calls return once the migration is over.
+
+    pub fn vm_receive_migration(&self, receiver_url: &str) -> Result<(), ApiError> {
+        self.send(endpoints::vm_receive_migration(receiver_url)?)
+    }
+
+    pub fn vm_send_migration(&self, destination_url: &str, local: bool) -> Result<(), ApiError> {
+        self.send(endpoints::vm_send_migration(destination_url, local)?)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MigrationOptions {
+    socket: PathBuf,
+    local: bool,
+    timeout: Duration,
+}
+
+impl MigrationOptions {
+    // `socket` is the Unix socket the destination listens on for the migration.
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        MigrationOptions {
+            socket: socket.into(),
+            local: false,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    // Local migration passes guest memory as fds instead of copying it; the source needs
+    // `--memory shared=on`.
+    pub fn local(mut self, local: bool) -> Self {
+        self.local = local;
+        self
+    }
+
+    // How long to wait for the destination to come up and to start listening.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn url(&self) -> String {
+        format!("unix:{}", self.socket.display())
+    }
+}
+
+// Polls `ready` until it returns true, failing when the destination exits or `timeout` passes.
+fn wait_until(
+    destination: &mut VmProcess,
+    stage: MigrationStage,
+    timeout: Duration,
+    mut ready: impl FnMut() -> bool,
+) -> Result<(), MigrationError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if ready() {
+            return Ok(());
+        }
+        if let Some(exit) = destination.try_wait()? {
+            return Err(MigrationError::DestinationExited(exit));
+        }
+        if Instant::now() >= deadline {
+            return Err(MigrationError::Timeout(stage));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn run(
+    source: &ApiClient,
+    destination: &mut VmProcess,
+    api: ApiClient,
+    options: &MigrationOptions,
+    progress: &mut impl FnMut(MigrationStage),
+) -> Result<(), MigrationError> {
+    wait_until(
+        destination,
+        MigrationStage::StartingDestination,
+        options.timeout,
+        || api.vmm_ping().is_ok(),
+    )?;
+
+    progress(MigrationStage::Receiving);
+    let url = options.url();
+    let receiver = {
+        let url = url.clone();
+        thread::spawn(move || api.vm_receive_migration(&url))
+    };
+    let listening = wait_until(
+        destination,
+        MigrationStage::Receiving,
+        options.timeout,
+        || options.socket.exists() || receiver.is_finished(),
+    );
+    if let Err(err) = listening {
+        // A receiver still blocked in the API call returns once the destination is killed.
+        let _ = destination.kill();
+        let _ = receiver.join();
+        return Err(err);
+    }
+    if receiver.is_finished() {
+        return Err(match receiver.join() {
+            Ok(Err(err)) => MigrationError::Receive(err),
+            _ => MigrationError::Timeout(MigrationStage::Receiving),
+        });
+    }
+
+    progress(MigrationStage::Sending);
+    if let Err(err) = source.vm_send_migration(&url, options.local) {
+        let _ = destination.kill();
+        let _ = receiver.join();
+        return Err(MigrationError::Send(err));
+    }
+    match receiver.join() {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => Err(MigrationError::Receive(err)),
+        Err(_) => Err(MigrationError::Timeout(MigrationStage::Receiving)),
+    }
+}
+
+// Starts `destination` as a VMM without a VM, has it receive over `options`' socket while the
+// VMM behind `source` sends, and returns the destination once the VM runs there. `progress` is
+// called as each stage begins. The destination is killed when the migration fails. A stale socket
+// is removed first; anything else at the socket path fails with `AlreadyExists`.
+pub fn migrate(
+    source: &ApiClient,
+    destination: &CloudHypervisorInstance,
+    options: &MigrationOptions,
+    mut progress: impl FnMut(MigrationStage),
+) -> Result<VmProcess, MigrationError> {
+    let destination = destination.vmm_instance();
+    let api = match &destination.api_socket {
+        Some(PathOrFileDescriptorOption::Path(path)) => ApiClient::from_path(path),
+        _ => return Err(MigrationError::NoApiSocket),
+    };
+    remove_stale_socket(&options.socket)?;
+
+    progress(MigrationStage::StartingDestination);
+    let mut process = destination.spawn(InheritedFds::new())?;
+    match run(source, &mut process, api, options, &mut progress) {
+        Ok(()) => {
+            progress(MigrationStage::Completed);
+            Ok(process)
+        }
+        Err(err) => {
+            let _ = process.kill();
+            let _ = process.wait();
+            Err(err)
+        }
+    }
+}
+
+// The socket's existence signals that the destination listens, so one left over from an
+// earlier run has to go. Anything else at that path is left alone.
+fn remove_stale_socket(socket: &Path) -> io::Result<()> {
+    match std::fs::symlink_metadata(socket) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(socket),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", socket.display()),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
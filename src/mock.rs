@@ -0,0 +1,554 @@
+// An in-process stand-in for the cloud-hypervisor API, for testing code built on this crate
+// without a hypervisor. It keeps one VM in memory and walks it through the same states
+// cloud-hypervisor does; migration and coredump are not implemented and answer 404.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::api::{ApiClient, PciDeviceInfo, VmCounters, VmInfo, VmState, VmmPingResponse};
+use crate::vm_config::VmConfig;
+
+const API_PREFIX: &str = "/api/v1/";
+const MOCK_VERSION: &str = "40.0.0";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MockRequest {
+    pub method: String,
+    // Without the `/api/v1/` prefix, e.g. `vm.boot`
+    pub endpoint: String,
+    pub body: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct MockResponse {
+    status: u16,
+    body: String,
+}
+
+impl MockResponse {
+    fn ok(body: String) -> Self {
+        MockResponse { status: 200, body }
+    }
+
+    fn no_content() -> Self {
+        MockResponse {
+            status: 204,
+            body: String::new(),
+        }
+    }
+
+    fn error(status: u16, body: impl Into<String>) -> Self {
+        MockResponse {
+            status,
+            body: body.into(),
+        }
+    }
+
+    fn json<T: serde::Serialize>(value: &T) -> Self {
+        match serde_json::to_string(value) {
+            Ok(body) => MockResponse::ok(body),
+            Err(err) => MockResponse::error(500, err.to_string()),
+        }
+    }
+}
+
+struct Vm {
+    config: VmConfig,
+    state: Option<VmState>,
+    next_slot: u8,
+}
+
+#[derive(Default)]
+struct MockState {
+    vm: Option<Vm>,
+    counters: VmCounters,
+    requests: Vec<MockRequest>,
+    failures: VecDeque<(String, MockResponse)>,
+    latency: Duration,
+}
+
+#[derive(Deserialize)]
+struct IdData {
+    id: String,
+}
+
+// `vm.snapshot` sends a `destination_url`, `vm.restore` a `source_url`.
+#[derive(Deserialize)]
+struct UrlData {
+    #[serde(rename = "source_url", alias = "destination_url")]
+    url: String,
+}
+
+pub struct MockServer {
+    socket: PathBuf,
+    state: Arc<Mutex<MockState>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    // Listens on `socket`, replacing a stale socket file. The server stops when dropped.
+    pub fn start(socket: impl Into<PathBuf>) -> io::Result<MockServer> {
+        let socket = socket.into();
+        match fs::remove_file(&socket) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        let listener = UnixListener::bind(&socket)?;
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let state = state.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        // A client that hangs up early is its own problem.
+                        let _ = serve(stream, &state);
+                    }
+                }
+            })
+        };
+
+        Ok(MockServer {
+            socket,
+            state,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    pub fn client(&self) -> ApiClient {
+        ApiClient::from_path(&self.socket)
+    }
+
+    // Every request received so far, including failed ones.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.lock().requests.clone()
+    }
+
+    // `None` until a VM is created and after it was deleted.
+    pub fn state(&self) -> Option<VmState> {
+        self.lock().vm.as_ref().and_then(|vm| vm.state)
+    }
+
+    pub fn config(&self) -> Option<VmConfig> {
+        self.lock().vm.as_ref().map(|vm| vm.config.clone())
+    }
+
+    // What `vm.counters` returns while a VM exists.
+    pub fn set_counters(&self, counters: VmCounters) {
+        self.lock().counters = counters;
+    }
+
+    // The next request to `endpoint` (e.g. `vm.boot`) fails with `status` and `body` and leaves
+    // the VM as it is. Failures queue up in order.
+    pub fn fail_next(&self, endpoint: &str, status: u16, body: &str) {
+        self.lock()
+            .failures
+            .push_back((endpoint.to_string(), MockResponse::error(status, body)));
+    }
+
+    // Delay before every response.
+    pub fn set_latency(&self, latency: Duration) {
+        self.lock().latency = latency;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        lock(&self.state)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wakes up the accept loop so it sees the stop flag.
+        let _ = UnixStream::connect(&self.socket);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_file(&self.socket);
+    }
+}
+
+// A panicking test thread must not take the other requests down with it.
+fn lock(state: &Mutex<MockState>) -> MutexGuard<'_, MockState> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn serve(stream: UnixStream, state: &Mutex<MockState>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default();
+    let endpoint = path.strip_prefix(API_PREFIX).unwrap_or(path).to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            break;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or_default();
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let request = MockRequest {
+        method,
+        endpoint,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    };
+
+    let (response, latency) = {
+        let mut state = lock(state);
+        state.requests.push(request.clone());
+        let failure = state
+            .failures
+            .iter()
+            .position(|(endpoint, _)| endpoint == &request.endpoint);
+        let response = match failure.and_then(|i| state.failures.remove(i)) {
+            Some((_, response)) => response,
+            None => handle(&mut state, &request).unwrap_or_else(|response| response),
+        };
+        (response, state.latency)
+    };
+    thread::sleep(latency);
+
+    let reason = match response.status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Error",
+    };
+    let stream = reader.get_mut();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\n\r\n",
+        response.status,
+        reason,
+        response.body.len()
+    )?;
+    stream.write_all(response.body.as_bytes())
+}
+
+fn parse<T: DeserializeOwned>(body: &str) -> Result<T, MockResponse> {
+    serde_json::from_str(body).map_err(|err| MockResponse::error(400, err.to_string()))
+}
+
+fn handle(state: &mut MockState, request: &MockRequest) -> Result<MockResponse, MockResponse> {
+    let method = match request.endpoint.as_str() {
+        "vmm.ping" | "vm.info" | "vm.counters" => "GET",
+        _ => "PUT",
+    };
+    if request.method != method {
+        return Err(MockResponse::error(
+            405,
+            format!("{} expects {}", request.endpoint, method),
+        ));
+    }
+
+    let body = request.body.as_str();
+    let response = match request.endpoint.as_str() {
+        "vmm.ping" => MockResponse::json(&VmmPingResponse {
+            build_version: format!("v{}", MOCK_VERSION),
+            version: MOCK_VERSION.to_string(),
+            pid: Some(std::process::id().into()),
+            features: Some(vec!["kvm".to_string()]),
+        }),
+        "vmm.shutdown" => {
+            state.vm = None;
+            MockResponse::no_content()
+        }
+        "vm.create" => {
+            if state.vm.is_some() {
+                return Err(MockResponse::error(500, "VM is already created"));
+            }
+            state.vm = Some(Vm {
+                config: parse(body)?,
+                state: Some(VmState::Created),
+                next_slot: 1,
+            });
+            MockResponse::no_content()
+        }
+        "vm.delete" => {
+            state.vm = None;
+            MockResponse::no_content()
+        }
+        "vm.boot" => transition(
+            state,
+            &[VmState::Created, VmState::Shutdown],
+            VmState::Running,
+        )?,
+        "vm.shutdown" => transition(
+            state,
+            &[VmState::Running, VmState::Paused],
+            VmState::Shutdown,
+        )?,
+        "vm.reboot" => transition(state, &[VmState::Running], VmState::Running)?,
+        "vm.pause" => transition(state, &[VmState::Running], VmState::Paused)?,
+        "vm.resume" => transition(state, &[VmState::Paused], VmState::Running)?,
+        "vm.power-button" => transition(state, &[VmState::Running], VmState::Running)?,
+        "vm.info" => {
+            let vm = vm(state)?;
+            MockResponse::json(&VmInfo {
+                config: vm.config.clone(),
+                state: vm.state.unwrap_or(VmState::Created),
+                memory_actual_size: Some(vm.config.memory.size),
+                device_tree: None,
+            })
+        }
+        "vm.counters" => {
+            vm(state)?;
+            MockResponse::json(&state.counters)
+        }
+        "vm.resize" | "vm.resize-zone" => {
+            parse::<serde_json::Value>(body)?;
+            vm(state)?;
+            MockResponse::no_content()
+        }
+        "vm.add-disk" => {
+            let vm = vm(state)?;
+            add(
+                vm,
+                body,
+                "_disk",
+                |config| &mut config.disks,
+                |disk| &mut disk.id,
+            )?
+        }
+        "vm.add-net" => {
+            let vm = vm(state)?;
+            add(
+                vm,
+                body,
+                "_net",
+                |config| &mut config.net,
+                |net| &mut net.id,
+            )?
+        }
+        "vm.add-fs" => {
+            let vm = vm(state)?;
+            add(vm, body, "_fs", |config| &mut config.fs, |fs| &mut fs.id)?
+        }
+        "vm.add-pmem" => {
+            let vm = vm(state)?;
+            add(
+                vm,
+                body,
+                "_pmem",
+                |config| &mut config.pmem,
+                |pmem| &mut pmem.id,
+            )?
+        }
+        "vm.add-device" => {
+            let vm = vm(state)?;
+            add(
+                vm,
+                body,
+                "_vfio",
+                |config| &mut config.devices,
+                |device| &mut device.id,
+            )?
+        }
+        "vm.add-user-device" => {
+            let vm = vm(state)?;
+            add(
+                vm,
+                body,
+                "_user_device",
+                |config| &mut config.user_devices,
+                |device| &mut device.id,
+            )?
+        }
+        "vm.add-vdpa" => {
+            let vm = vm(state)?;
+            add(
+                vm,
+                body,
+                "_vdpa",
+                |config| &mut config.vdpa,
+                |vdpa| &mut vdpa.id,
+            )?
+        }
+        "vm.add-vsock" => {
+            let vm = vm(state)?;
+            if vm.config.vsock.is_some() {
+                return Err(MockResponse::error(500, "VM already has a vsock device"));
+            }
+            let mut vsock: crate::vm_config::VsockConfig = parse(body)?;
+            let id = vsock
+                .id
+                .get_or_insert_with(|| "_vsock0".to_string())
+                .clone();
+            vm.config.vsock = Some(vsock);
+            MockResponse::json(&pci_device(vm, id))
+        }
+        "vm.remove-device" => {
+            let IdData { id } = parse(body)?;
+            let config = &mut vm(state)?.config;
+            let removed = remove(&mut config.disks, &id, |disk| &disk.id)
+                || remove(&mut config.net, &id, |net| &net.id)
+                || remove(&mut config.fs, &id, |fs| &fs.id)
+                || remove(&mut config.pmem, &id, |pmem| &pmem.id)
+                || remove(&mut config.devices, &id, |device| &device.id)
+                || remove(&mut config.user_devices, &id, |device| &device.id)
+                || remove(&mut config.vdpa, &id, |vdpa| &vdpa.id);
+            if !removed {
+                if config.vsock.as_ref().and_then(|vsock| vsock.id.as_ref()) != Some(&id) {
+                    return Err(MockResponse::error(500, format!("no device `{}`", id)));
+                }
+                config.vsock = None;
+            }
+            MockResponse::no_content()
+        }
+        "vm.snapshot" => {
+            let UrlData { url } = parse(body)?;
+            let vm = vm(state)?;
+            if vm.state != Some(VmState::Paused) {
+                return Err(MockResponse::error(500, "VM is not paused"));
+            }
+            write_snapshot(&vm.config, &url)
+                .map_err(|err| MockResponse::error(500, err.to_string()))?;
+            MockResponse::no_content()
+        }
+        "vm.restore" => {
+            let UrlData { url } = parse(body)?;
+            if state.vm.is_some() {
+                return Err(MockResponse::error(500, "VM is already created"));
+            }
+            let config = VmConfig::read(snapshot_dir(&url)?)
+                .map_err(|err| MockResponse::error(500, err.to_string()))?;
+            // cloud-hypervisor restores a paused VM.
+            state.vm = Some(Vm {
+                config,
+                state: Some(VmState::Paused),
+                next_slot: 1,
+            });
+            MockResponse::no_content()
+        }
+        endpoint => MockResponse::error(404, format!("unknown endpoint {}", endpoint)),
+    };
+    Ok(response)
+}
+
+fn vm(state: &mut MockState) -> Result<&mut Vm, MockResponse> {
+    state
+        .vm
+        .as_mut()
+        .ok_or_else(|| MockResponse::error(500, "VM is not created"))
+}
+
+fn transition(
+    state: &mut MockState,
+    from: &[VmState],
+    to: VmState,
+) -> Result<MockResponse, MockResponse> {
+    let vm = vm(state)?;
+    match vm.state {
+        Some(current) if from.contains(&current) => {
+            vm.state = Some(to);
+            Ok(MockResponse::no_content())
+        }
+        current => Err(MockResponse::error(
+            500,
+            format!("invalid state transition from {:?} to {:?}", current, to),
+        )),
+    }
+}
+
+fn pci_device(vm: &mut Vm, id: String) -> PciDeviceInfo {
+    vm.next_slot += 1;
+    PciDeviceInfo {
+        id,
+        bdf: format!("0000:00:{:02x}.0", vm.next_slot),
+    }
+}
+
+// Adds the device in `body` to its list, naming it `<prefix><n>` like cloud-hypervisor when it
+// has no id.
+fn add<T: DeserializeOwned>(
+    vm: &mut Vm,
+    body: &str,
+    prefix: &str,
+    list: impl Fn(&mut VmConfig) -> &mut Option<Vec<T>>,
+    device_id: impl Fn(&mut T) -> &mut Option<String>,
+) -> Result<MockResponse, MockResponse> {
+    let mut device: T = parse(body)?;
+    let devices = list(&mut vm.config).get_or_insert_with(Vec::new);
+    let id = match device_id(&mut device) {
+        Some(id) => id.clone(),
+        None => {
+            let id = format!("{}{}", prefix, devices.len());
+            *device_id(&mut device) = Some(id.clone());
+            id
+        }
+    };
+    devices.push(device);
+    Ok(MockResponse::json(&pci_device(vm, id)))
+}
+
+fn remove<T>(
+    devices: &mut Option<Vec<T>>,
+    id: &str,
+    device_id: impl Fn(&T) -> &Option<String>,
+) -> bool {
+    let Some(list) = devices else {
+        return false;
+    };
+    let len = list.len();
+    list.retain(|device| device_id(device).as_deref() != Some(id));
+    let removed = list.len() != len;
+    if list.is_empty() {
+        *devices = None;
+    }
+    removed
+}
+
+fn snapshot_dir(url: &str) -> Result<PathBuf, MockResponse> {
+    url.strip_prefix("file://")
+        .map(PathBuf::from)
+        .ok_or_else(|| MockResponse::error(400, format!("unsupported url {}", url)))
+}
+
+// Writes the files `snapshot::Snapshot::open` expects, with an empty state and memory.
+fn write_snapshot(config: &VmConfig, url: &str) -> io::Result<()> {
+    let dir = snapshot_dir(url).map_err(|response| io::Error::other(response.body))?;
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("config.json"), serde_json::to_vec(config)?)?;
+    fs::write(dir.join("state.json"), "{}")?;
+    fs::write(dir.join("memory-ranges"), "")
+}
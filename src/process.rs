@@ -0,0 +1,482 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::api::ApiClient;
+use crate::error::Error;
+use crate::event_monitor::EventReader;
+use crate::to_command::TryToCommand;
+use crate::{CloudHypervisorInstance, Console, PathOrFileDescriptorOption, Serial};
+
+// File descriptors to hand to cloud-hypervisor, keyed by the number the child sees them as (the
+// number rendered in `--net fd=[..]`, `--api-socket fd=..` and `--event-monitor fd=..`).
+#[derive(Debug, Default)]
+pub struct InheritedFds {
+    fds: BTreeMap<RawFd, OwnedFd>,
+}
+
+impl InheritedFds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, target: RawFd, fd: OwnedFd) -> &mut Self {
+        self.fds.insert(target, fd);
+        self
+    }
+
+    // Borrowed fds are duplicated so the returned `Command` does not borrow from the caller.
+    pub fn insert_borrowed(&mut self, target: RawFd, fd: BorrowedFd<'_>) -> io::Result<&mut Self> {
+        Ok(self.insert(target, fd.try_clone_to_owned()?))
+    }
+}
+
+impl CloudHypervisorInstance {
+    fn referenced_fds(&self) -> Vec<(&'static str, RawFd)> {
+        let mut fds = vec![];
+
+        for net in self.net.iter().flatten() {
+            for fd in net.fd.iter().flatten() {
+                fds.push(("--net", *fd as RawFd));
+            }
+        }
+        if let Some(PathOrFileDescriptorOption::Fd(fd)) = &self.api_socket {
+            fds.push(("--api-socket", *fd as RawFd));
+        }
+        if let Some(PathOrFileDescriptorOption::Fd(fd)) = &self.event_monitor {
+            fds.push(("--event-monitor", *fd as RawFd));
+        }
+
+        fds
+    }
+
+    // Every fd referenced on the command line must be provided. In the child, each provided fd is
+    // moved to its target number and every other fd above stderr is closed on exec.
+    pub fn to_process_command(&self, fds: InheritedFds) -> Result<Command, Error> {
+        for (option, fd) in self.referenced_fds() {
+            if !fds.fds.contains_key(&fd) {
+                return Err(Error::MissingFd {
+                    option: option.to_string(),
+                    fd,
+                });
+            }
+        }
+
+        let args = self.try_to_command()?;
+        let mut cmd = Command::new(&args[0]);
+        cmd.args(&args[1..]);
+
+        let mut remap = Remap::new(fds);
+        // SAFETY: `Remap::apply` only makes async-signal-safe syscalls and does not allocate.
+        unsafe {
+            cmd.pre_exec(move || remap.apply());
+        }
+
+        Ok(cmd)
+    }
+
+    // A `tty` console (the default) or serial port reads the guest's input from the VMM's stdin, so
+    // it is inherited for those; everything else gets `/dev/null`.
+    pub(crate) fn spawn_stdin(&self) -> Stdio {
+        let console_tty = matches!(self.console, None | Some(Console::Tty));
+        let serial_tty = matches!(self.serial, Some(Serial::Tty));
+        if console_tty || serial_tty {
+            Stdio::inherit()
+        } else {
+            Stdio::null()
+        }
+    }
+
+    // Like `to_process_command()`, but with `event_monitor_pipe(true)` also creates the pipe, passes
+    // its write end as an fd numbered above every other one and returns the read end.
+    pub(crate) fn to_spawn_command(
+        &self,
+        mut fds: InheritedFds,
+    ) -> Result<(Command, Option<EventReader<File>>), SpawnError> {
+        if self.event_monitor_pipe != Some(true) {
+            return Ok((self.to_process_command(fds)?, None));
+        }
+
+        let (read, write) = pipe()?;
+        let target = self
+            .referenced_fds()
+            .into_iter()
+            .map(|(_, fd)| fd)
+            .chain(fds.fds.keys().copied())
+            .max()
+            .unwrap_or(2)
+            + 1;
+        fds.insert(target, write);
+
+        let mut ch = self.clone();
+        ch.event_monitor = Some(PathOrFileDescriptorOption::Fd(target as usize));
+        Ok((
+            ch.to_process_command(fds)?,
+            Some(EventReader::from_fd(read)),
+        ))
+    }
+}
+
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [-1; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: both fds were just created and are owned by nothing else.
+    unsafe { Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+}
+
+struct Remap {
+    // (target, source), sorted by target
+    fds: Vec<(RawFd, OwnedFd)>,
+    scratch: Vec<RawFd>,
+    floor: RawFd,
+}
+
+impl Remap {
+    fn new(fds: InheritedFds) -> Self {
+        let fds = fds.fds.into_iter().collect::<Vec<(RawFd, OwnedFd)>>();
+        let floor = fds
+            .iter()
+            .flat_map(|(target, fd)| [*target, fd.as_raw_fd()])
+            .max()
+            .unwrap_or(2)
+            + 1;
+
+        Remap {
+            scratch: vec![-1; fds.len()],
+            fds,
+            floor,
+        }
+    }
+
+    // Sources are first copied above every source and target number so that moving one fd into
+    // place never clobbers another source that happens to sit on a target number.
+    fn apply(&mut self) -> io::Result<()> {
+        for (i, (_, fd)) in self.fds.iter().enumerate() {
+            let copy = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, self.floor) };
+            if copy < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.scratch[i] = copy;
+        }
+        for (i, (target, _)) in self.fds.iter().enumerate() {
+            // dup2 clears FD_CLOEXEC on the target
+            if unsafe { libc::dup2(self.scratch[i], *target) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        let mut next = 3;
+        for (target, _) in &self.fds {
+            if *target >= next {
+                cloexec_range(next, *target - 1);
+                next = *target + 1;
+            }
+        }
+        cloexec_range(next, RawFd::MAX);
+
+        Ok(())
+    }
+}
+
+fn cloexec_range(first: RawFd, last: RawFd) {
+    if first > last {
+        return;
+    }
+
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_close_range,
+            first as libc::c_uint,
+            last as libc::c_uint,
+            libc::CLOSE_RANGE_CLOEXEC,
+        )
+    };
+    if ret == 0 {
+        return;
+    }
+
+    // close_range(2) with CLOSE_RANGE_CLOEXEC needs Linux 5.11
+    let max = unsafe { libc::sysconf(libc::_SC_OPEN_MAX) };
+    let last = if max > 0 {
+        last.min(max as RawFd - 1)
+    } else {
+        last.min(65535)
+    };
+    for fd in first..=last {
+        unsafe {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
+}
+
+pub(crate) const STDERR_TAIL: usize = 8192;
+
+#[derive(Debug)]
+pub enum SpawnError {
+    Command(Error),
+    Io(io::Error),
+}
+
+impl Display for SpawnError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpawnError::Command(err) => write!(f, "{}", err),
+            SpawnError::Io(err) => write!(f, "cannot spawn cloud-hypervisor: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SpawnError::Command(err) => Some(err),
+            SpawnError::Io(err) => Some(err),
+        }
+    }
+}
+
+impl From<Error> for SpawnError {
+    fn from(err: Error) -> Self {
+        SpawnError::Command(err)
+    }
+}
+
+impl From<io::Error> for SpawnError {
+    fn from(err: io::Error) -> Self {
+        SpawnError::Io(err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExitReason {
+    Exited,
+    // cloud-hypervisor reports fatal errors on stderr before exiting, `message` is the last line.
+    Failed { code: i32, message: Option<String> },
+    Signaled { signal: i32 },
+}
+
+impl Display for ExitReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitReason::Exited => write!(f, "exited"),
+            ExitReason::Failed { code, message } => {
+                write!(f, "exited with status {}", code)?;
+                if let Some(message) = message {
+                    write!(f, ": {}", message)?;
+                }
+                Ok(())
+            }
+            ExitReason::Signaled { signal } => write!(f, "killed by signal {}", signal),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VmExit {
+    pub status: ExitStatus,
+    pub reason: ExitReason,
+    // The last few KiB cloud-hypervisor wrote to stderr.
+    pub stderr: String,
+}
+
+impl VmExit {
+    pub(crate) fn new(status: ExitStatus, stderr: Vec<u8>) -> Self {
+        let stderr = String::from_utf8_lossy(&stderr).into_owned();
+        let reason = match (status.code(), status.signal()) {
+            (Some(0), _) => ExitReason::Exited,
+            (Some(code), _) => ExitReason::Failed {
+                code,
+                message: stderr
+                    .lines()
+                    .map(str::trim)
+                    .rfind(|line| !line.is_empty())
+                    .map(str::to_string),
+            },
+            (None, signal) => ExitReason::Signaled {
+                signal: signal.unwrap_or_default(),
+            },
+        };
+
+        VmExit {
+            status,
+            reason,
+            stderr,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct VmProcess {
+    child: Child,
+    api_socket: Option<PathBuf>,
+    log_file: Option<PathBuf>,
+    event_monitor: Option<PathBuf>,
+    pub(crate) events: Option<EventReader<File>>,
+    stderr: Option<JoinHandle<Vec<u8>>>,
+    exit: Option<VmExit>,
+}
+
+impl CloudHypervisorInstance {
+    pub fn spawn(&self, fds: InheritedFds) -> Result<VmProcess, SpawnError> {
+        let (mut cmd, events) = self.to_spawn_command(fds)?;
+        let mut child = cmd
+            .stdin(self.spawn_stdin())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // The child has its own copy of the pipe's write end now, dropping ours lets the reader see
+        // the end of the stream when the VMM exits.
+        drop(cmd);
+
+        let stderr = child.stderr.take().map(|stderr| {
+            thread::spawn(move || {
+                let mut tail = vec![];
+                let _ = read_tail(stderr, &mut tail);
+                tail
+            })
+        });
+
+        Ok(VmProcess {
+            child,
+            api_socket: match &self.api_socket {
+                Some(PathOrFileDescriptorOption::Path(path)) => Some(path.clone()),
+                _ => None,
+            },
+            log_file: self.log_file.clone(),
+            event_monitor: match &self.event_monitor {
+                Some(PathOrFileDescriptorOption::Path(path)) => Some(path.clone()),
+                _ => None,
+            },
+            events,
+            stderr,
+            exit: None,
+        })
+    }
+}
+
+fn read_tail(mut stderr: impl Read, tail: &mut Vec<u8>) -> io::Result<()> {
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = stderr.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        tail.extend_from_slice(&buf[..n]);
+        if tail.len() > STDERR_TAIL {
+            tail.drain(..tail.len() - STDERR_TAIL);
+        }
+    }
+}
+
+impl VmProcess {
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    // Only known when `--api-socket` was given a path, not an fd.
+    pub fn api_socket(&self) -> Option<&Path> {
+        self.api_socket.as_deref()
+    }
+
+    pub fn log_file(&self) -> Option<&Path> {
+        self.log_file.as_deref()
+    }
+
+    // Only known when `--event-monitor` was given a path, not an fd.
+    pub fn event_monitor(&self) -> Option<&Path> {
+        self.event_monitor.as_deref()
+    }
+
+    // The read end of the pipe created by `event_monitor_pipe(true)`, once. Reads block until the
+    // VMM writes an event and end when it exits. Events `wait_ready_for()` looked at are still
+    // returned.
+    pub fn take_events(&mut self) -> Option<EventReader<File>> {
+        self.events.take()
+    }
+
+    pub fn api_client(&self) -> Option<ApiClient> {
+        self.api_socket.as_ref().map(ApiClient::from_path)
+    }
+
+    fn exited(&mut self, status: ExitStatus) -> VmExit {
+        if let Some(exit) = &self.exit {
+            return exit.clone();
+        }
+        let stderr = self
+            .stderr
+            .take()
+            .and_then(|stderr| stderr.join().ok())
+            .unwrap_or_default();
+        let exit = VmExit::new(status, stderr);
+        self.exit = Some(exit.clone());
+        exit
+    }
+
+    pub fn wait(&mut self) -> io::Result<VmExit> {
+        let status = self.child.wait()?;
+        Ok(self.exited(status))
+    }
+
+    pub fn try_wait(&mut self) -> io::Result<Option<VmExit>> {
+        Ok(self.child.try_wait()?.map(|status| self.exited(status)))
+    }
+
+    fn wait_timeout(&mut self, timeout: Duration) -> io::Result<Option<VmExit>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(exit) = self.try_wait()? {
+                return Ok(Some(exit));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.child.kill()
+    }
+
+    fn terminate(&mut self) -> io::Result<()> {
+        if self.try_wait()?.is_some() {
+            return Ok(());
+        }
+        if unsafe { libc::kill(self.child.id() as libc::pid_t, libc::SIGTERM) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // Asks the VMM to shut down through the API socket, then falls back to SIGTERM and finally
+    // SIGKILL, waiting up to `timeout` after each of the first two steps.
+    pub fn shutdown(&mut self, timeout: Duration) -> io::Result<VmExit> {
+        if let Some(api_socket) = self.api_socket.clone() {
+            if ApiClient::from_path(api_socket)
+                .timeout(timeout)
+                .vmm_shutdown()
+                .is_ok()
+            {
+                if let Some(exit) = self.wait_timeout(timeout)? {
+                    return Ok(exit);
+                }
+            }
+        }
+
+        self.terminate()?;
+        if let Some(exit) = self.wait_timeout(timeout)? {
+            return Ok(exit);
+        }
+
+        self.kill()?;
+        self.wait()
+    }
+}
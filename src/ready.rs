@@ -0,0 +1,304 @@
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::api::{ApiError, VmState, VmmPingResponse};
+use crate::event_monitor::{EventError, EventReader};
+use crate::process::{VmExit, VmProcess};
+
+// How many lines of `--log-file` a timeout reports.
+const LOG_TAIL_LINES: usize = 20;
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(10);
+const MAX_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+// What has to happen before the VMM counts as ready. The API socket answering `vmm.ping` is always
+// waited for first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ReadyCondition {
+    #[default]
+    Api,
+    // `vm.info` reports the VM as running.
+    VmRunning,
+    // An event with this source and name shows up in the `--event-monitor` file or the pipe
+    // created by `event_monitor_pipe(true)`, e.g. `vm` and `booted`.
+    Event {
+        source: String,
+        event: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadyStage {
+    Socket,
+    Api,
+    VmRunning,
+    Event,
+}
+
+impl Display for ReadyStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadyStage::Socket => write!(f, "the api socket to appear"),
+            ReadyStage::Api => write!(f, "the api to answer"),
+            ReadyStage::VmRunning => write!(f, "the VM to run"),
+            ReadyStage::Event => write!(f, "the event"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadyError {
+    // The process has no `--api-socket` path to connect to.
+    NoApiSocket,
+    // `ReadyCondition::Event` needs `--event-monitor` with a path or `event_monitor_pipe(true)`.
+    NoEventMonitor,
+    Io(io::Error),
+    EventMonitor(EventError),
+    // The VMM exited before it was ready.
+    Exited(VmExit),
+    Timeout {
+        waiting_for: ReadyStage,
+        // Why the last API request failed.
+        last_error: Option<Box<ApiError>>,
+        // The last lines of `--log-file`.
+        log: Vec<String>,
+    },
+}
+
+impl Display for ReadyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadyError::NoApiSocket => write!(f, "cloud-hypervisor has no --api-socket path"),
+            ReadyError::NoEventMonitor => {
+                write!(f, "cloud-hypervisor has no --event-monitor path")
+            }
+            ReadyError::Io(err) => write!(f, "{}", err),
+            ReadyError::EventMonitor(err) => write!(f, "{}", err),
+            ReadyError::Exited(exit) => write!(f, "cloud-hypervisor {}", exit.reason),
+            ReadyError::Timeout {
+                waiting_for,
+                last_error,
+                log,
+            } => {
+                write!(f, "timed out waiting for {}", waiting_for)?;
+                if let Some(err) = last_error {
+                    write!(f, ": {}", err)?;
+                }
+                if let Some(line) = log.last() {
+                    write!(f, " (last log line: {})", line)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReadyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadyError::Io(err) => Some(err),
+            ReadyError::EventMonitor(err) => Some(err),
+            ReadyError::Timeout {
+                last_error: Some(err),
+                ..
+            } => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ReadyError {
+    fn from(err: io::Error) -> Self {
+        ReadyError::Io(err)
+    }
+}
+
+// Retry delays double up to `MAX_RETRY_DELAY`, never sleeping past the deadline.
+pub(crate) struct Backoff {
+    deadline: Instant,
+    delay: Duration,
+}
+
+impl Backoff {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Backoff {
+            deadline: Instant::now() + timeout,
+            delay: FIRST_RETRY_DELAY,
+        }
+    }
+
+    pub(crate) fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    pub(crate) fn next_delay(&mut self) -> Duration {
+        let delay = self.delay.min(self.remaining());
+        self.delay = (self.delay * 2).min(MAX_RETRY_DELAY);
+        delay
+    }
+}
+
+pub(crate) fn log_tail(log_file: Option<&Path>) -> Vec<String> {
+    let Some(contents) = log_file.and_then(|path| fs::read(path).ok()) else {
+        return vec![];
+    };
+    let contents = String::from_utf8_lossy(&contents);
+    let lines = contents.lines().collect::<Vec<&str>>();
+    lines[lines.len().saturating_sub(LOG_TAIL_LINES)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+// Reads what the VMM appended since the last call, opening the file once it exists. A fifo is
+// opened without blocking, so that waiting neither hangs until the VMM opens it for writing nor
+// misses the timeout or the process exiting.
+pub(crate) fn has_event(
+    events: &mut Option<EventReader<File>>,
+    path: &Path,
+    source: &str,
+    event: &str,
+) -> Result<bool, ReadyError> {
+    if events.is_none() {
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path);
+        match file {
+            Ok(file) => *events = Some(EventReader::new(file)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) if err.raw_os_error() == Some(libc::ENXIO) => return Ok(false),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    let Some(events) = events else {
+        return Ok(false);
+    };
+    while let Some(record) = events.next_event().map_err(ReadyError::EventMonitor)? {
+        if record.event.source() == source && record.event.name() == event {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Looks through what the VMM wrote to the pipe created by `event_monitor_pipe(true)` so far. The
+// events stay buffered for whoever takes the pipe's reader afterwards.
+pub(crate) fn pipe_has_event(
+    events: &mut EventReader<File>,
+    source: &str,
+    event: &str,
+) -> Result<bool, ReadyError> {
+    events.fill()?;
+    Ok(events
+        .buffered()
+        .iter()
+        .any(|record| record.event.source() == source && record.event.name() == event))
+}
+
+impl VmProcess {
+    // Waits until the api socket exists and answers `vmm.ping`.
+    pub fn wait_ready(&mut self, timeout: Duration) -> Result<VmmPingResponse, ReadyError> {
+        self.wait_ready_for(timeout, &ReadyCondition::Api)
+    }
+
+    // Retries with a growing delay until `condition` holds, failing as soon as the process exits.
+    pub fn wait_ready_for(
+        &mut self,
+        timeout: Duration,
+        condition: &ReadyCondition,
+    ) -> Result<VmmPingResponse, ReadyError> {
+        let api = self.api_client().ok_or(ReadyError::NoApiSocket)?;
+        // Without the pipe, events are read from the `--event-monitor` file.
+        let event_monitor = match condition {
+            ReadyCondition::Event { .. } if self.events.is_none() => Some(
+                self.event_monitor()
+                    .ok_or(ReadyError::NoEventMonitor)?
+                    .to_path_buf(),
+            ),
+            _ => None,
+        };
+        let mut backoff = Backoff::new(timeout);
+
+        self.poll(&mut backoff, ReadyStage::Socket, |_, _| {
+            Ok(api.socket().exists().then_some(()))
+        })?;
+        let ping = self.poll(
+            &mut backoff,
+            ReadyStage::Api,
+            |remaining, last_error| match api.clone().timeout(remaining).vmm_ping() {
+                Ok(ping) => Ok(Some(ping)),
+                Err(err) => {
+                    *last_error = Some(err);
+                    Ok(None)
+                }
+            },
+        )?;
+
+        match (condition, event_monitor) {
+            (ReadyCondition::VmRunning, _) => self.poll(
+                &mut backoff,
+                ReadyStage::VmRunning,
+                |remaining, last_error| match api.clone().timeout(remaining).vm_info() {
+                    Ok(info) => Ok((info.state == VmState::Running).then_some(())),
+                    Err(err) => {
+                        *last_error = Some(err);
+                        Ok(None)
+                    }
+                },
+            )?,
+            (ReadyCondition::Event { source, event }, Some(path)) => {
+                let mut events = None;
+                self.poll(&mut backoff, ReadyStage::Event, |_, _| {
+                    Ok(has_event(&mut events, &path, source, event)?.then_some(()))
+                })?
+            }
+            (ReadyCondition::Event { source, event }, None) => {
+                // Put back even when waiting fails, so that the events can still be taken.
+                let mut pipe = self.events.take();
+                let found = self.poll(&mut backoff, ReadyStage::Event, |_, _| match &mut pipe {
+                    Some(events) => Ok(pipe_has_event(events, source, event)?.then_some(())),
+                    None => Ok(None),
+                });
+                self.events = pipe;
+                found?
+            }
+            _ => {}
+        }
+
+        Ok(ping)
+    }
+
+    // Calls `attempt` with the time left until it returns a value, the process exits or the time
+    // is up. `attempt` records why it failed in its second argument.
+    fn poll<T>(
+        &mut self,
+        backoff: &mut Backoff,
+        waiting_for: ReadyStage,
+        mut attempt: impl FnMut(Duration, &mut Option<ApiError>) -> Result<Option<T>, ReadyError>,
+    ) -> Result<T, ReadyError> {
+        let mut last_error = None;
+        loop {
+            if let Some(exit) = self.try_wait()? {
+                return Err(ReadyError::Exited(exit));
+            }
+            // An API request without time left would only fail and hide why the previous one did.
+            let remaining = backoff.remaining();
+            if remaining.is_zero() {
+                return Err(ReadyError::Timeout {
+                    waiting_for,
+                    last_error: last_error.map(Box::new),
+                    log: log_tail(self.log_file()),
+                });
+            }
+            if let Some(value) = attempt(remaining, &mut last_error)? {
+                return Ok(value);
+            }
+            thread::sleep(backoff.next_delay());
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::fmt::{Display, Formatter};
+
+use bytesize::ByteSize;
+use derive_builder::Builder;
+use serde::Serialize;
+
+use crate::api::{ApiClient, ApiError};
+use crate::endpoints;
+use crate::error::Error;
+use crate::vm_config::DEFAULT_MEMORY_SIZE;
+use crate::{CloudHypervisorInstance, MemoryHotplugMethod};
+
+#[derive(Builder, Clone, Debug, Default, PartialEq, Eq)]
+#[builder(setter(strip_option, into), default)]
+pub struct VmResize {
+    pub desired_vcpus: Option<u8>,
+    pub desired_ram: Option<ByteSize>,
+    pub desired_balloon: Option<ByteSize>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct VmResizeData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    desired_vcpus: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    desired_ram: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    desired_balloon: Option<u64>,
+}
+
+impl From<&VmResize> for VmResizeData {
+    fn from(resize: &VmResize) -> Self {
+        VmResizeData {
+            desired_vcpus: resize.desired_vcpus,
+            desired_ram: resize.desired_ram.map(|ram| ram.as_u64()),
+            desired_balloon: resize.desired_balloon.map(|balloon| balloon.as_u64()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct VmResizeZoneData<'a> {
+    pub(crate) id: &'a str,
+    pub(crate) desired_ram: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResizeError {
+    Empty,
+    VcpusOutOfRange {
+        desired: u8,
+        max: u8,
+    },
+    // `--memory hotplug_size` is not set
+    MemoryHotplugDisabled,
+    RamOutOfRange {
+        desired: ByteSize,
+        min: ByteSize,
+        max: ByteSize,
+    },
+    NoBalloon,
+    BalloonTooLarge {
+        desired: ByteSize,
+        ram: ByteSize,
+    },
+    UnknownZone(String),
+    // Memory zones can only be resized with `--memory hotplug_method=virtio-mem`.
+    ZoneHotplugRequiresVirtioMem,
+    ZoneHotplugDisabled(String),
+    ZoneRamOutOfRange {
+        id: String,
+        desired: ByteSize,
+        min: ByteSize,
+        max: ByteSize,
+    },
+}
+
+impl Display for ResizeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResizeError::Empty => write!(f, "resize: nothing to resize"),
+            ResizeError::VcpusOutOfRange { desired, max } => write!(
+                f,
+                "resize: desired_vcpus={} is not within 1..={}",
+                desired, max
+            ),
+            ResizeError::MemoryHotplugDisabled => {
+                write!(f, "resize: --memory has no hotplug_size")
+            }
+            ResizeError::RamOutOfRange { desired, min, max } => write!(
+                f,
+                "resize: desired_ram={} is not within {}..={}",
+                desired.as_u64(),
+                min.as_u64(),
+                max.as_u64()
+            ),
+            ResizeError::NoBalloon => write!(f, "resize: no --balloon configured"),
+            ResizeError::BalloonTooLarge { desired, ram } => write!(
+                f,
+                "resize: desired_balloon={} exceeds the guest memory of {}",
+                desired.as_u64(),
+                ram.as_u64()
+            ),
+            ResizeError::UnknownZone(id) => write!(f, "resize-zone: unknown memory zone `{}`", id),
+            ResizeError::ZoneHotplugRequiresVirtioMem => write!(
+                f,
+                "resize-zone: memory zones need --memory hotplug_method=virtio-mem"
+            ),
+            ResizeError::ZoneHotplugDisabled(id) => {
+                write!(f, "resize-zone: memory zone `{}` has no hotplug_size", id)
+            }
+            ResizeError::ZoneRamOutOfRange {
+                id,
+                desired,
+                min,
+                max,
+            } => write!(
+                f,
+                "resize-zone: desired_ram={} for memory zone `{}` is not within {}..={}",
+                desired.as_u64(),
+                id,
+                min.as_u64(),
+                max.as_u64()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ResizeError {}
+
+impl ApiClient {
+    pub fn vm_resize(&self, resize: &VmResize) -> Result<(), ApiError> {
+        self.send(endpoints::vm_resize(resize)?)
+    }
+
+    pub fn vm_resize_zone(&self, id: &str, desired_ram: ByteSize) -> Result<(), ApiError> {
+        self.send(endpoints::vm_resize_zone(id, desired_ram)?)
+    }
+}
+
+impl CloudHypervisorInstance {
+    // The checks are made against the boot configuration. cloud-hypervisor can still refuse a
+    // request that fits, e.g. shrinking ACPI hotplugged memory.
+    pub fn check_resize(&self, resize: &VmResize) -> Result<(), Error> {
+        if resize == &VmResize::default() {
+            return Err(Error::Resize(ResizeError::Empty));
+        }
+
+        if let Some(desired) = resize.desired_vcpus {
+            let cpus = self.cpus.as_ref();
+            let boot = cpus.and_then(|cpus| cpus.boot).unwrap_or(1);
+            let max = cpus.and_then(|cpus| cpus.max).unwrap_or(boot);
+            if desired == 0 || desired > max {
+                return Err(Error::Resize(ResizeError::VcpusOutOfRange { desired, max }));
+            }
+        }
+
+        let memory = self.memory.as_ref();
+        let size = memory
+            .and_then(|memory| memory.size)
+            .unwrap_or(ByteSize(DEFAULT_MEMORY_SIZE));
+        let hotplug_size = memory.and_then(|memory| memory.hotplug_size);
+        if let Some(desired) = resize.desired_ram {
+            let Some(hotplug_size) = hotplug_size else {
+                return Err(Error::Resize(ResizeError::MemoryHotplugDisabled));
+            };
+            let max = size + hotplug_size;
+            if desired < size || desired > max {
+                return Err(Error::Resize(ResizeError::RamOutOfRange {
+                    desired,
+                    min: size,
+                    max,
+                }));
+            }
+        }
+
+        if let Some(desired) = resize.desired_balloon {
+            if self.balloon.is_none() {
+                return Err(Error::Resize(ResizeError::NoBalloon));
+            }
+            let ram = resize
+                .desired_ram
+                .unwrap_or_else(|| size + hotplug_size.unwrap_or_default());
+            if desired > ram {
+                return Err(Error::Resize(ResizeError::BalloonTooLarge { desired, ram }));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn check_resize_zone(&self, id: &str, desired_ram: ByteSize) -> Result<(), Error> {
+        let zone = self
+            .memory_zone
+            .as_ref()
+            .filter(|zone| zone.id.as_deref() == Some(id))
+            .ok_or_else(|| Error::Resize(ResizeError::UnknownZone(id.to_string())))?;
+
+        let hotplug_method = self
+            .memory
+            .as_ref()
+            .and_then(|memory| memory.hotplug_method.as_ref());
+        if hotplug_method != Some(&MemoryHotplugMethod::VirtioMem) {
+            return Err(Error::Resize(ResizeError::ZoneHotplugRequiresVirtioMem));
+        }
+
+        let Some(hotplug_size) = zone.hotplug_size else {
+            return Err(Error::Resize(ResizeError::ZoneHotplugDisabled(
+                id.to_string(),
+            )));
+        };
+        let size = zone.size.unwrap_or_default();
+        let max = size + hotplug_size;
+        if desired_ram < size || desired_ram > max {
+            return Err(Error::Resize(ResizeError::ZoneRamOutOfRange {
+                id: id.to_string(),
+                desired: desired_ram,
+                min: size,
+                max,
+            }));
+        }
+
+        Ok(())
+    }
+
+    pub fn resize(&self, api: &ApiClient, resize: &VmResize) -> Result<(), ApiError> {
+        self.check_resize(resize)?;
+        api.vm_resize(resize)
+    }
+
+    pub fn resize_zone(
+        &self,
+        api: &ApiClient,
+        id: &str,
+        desired_ram: ByteSize,
+    ) -> Result<(), ApiError> {
+        self.check_resize_zone(id, desired_ram)?;
+        api.vm_resize_zone(id, desired_ram)
+    }
+}
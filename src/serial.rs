@@ -0,0 +1,218 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use regex::bytes::Regex;
+
+use crate::{CloudHypervisorInstance, Serial};
+
+// How much unconsumed output is kept by default; older output is dropped first.
+const DEFAULT_CAPACITY: usize = 64 * 1024;
+const READ_CHUNK: usize = 4096;
+
+#[derive(Debug)]
+pub enum SerialError {
+    // The instance has no `--serial socket=..`.
+    NoSocket,
+    Io(io::Error),
+    Pattern(regex::Error),
+    // The VMM closed the socket before the pattern showed up.
+    Closed { pattern: String, output: String },
+    Timeout { pattern: String, output: String },
+}
+
+impl Display for SerialError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SerialError::NoSocket => write!(f, "serial: no --serial socket"),
+            SerialError::Io(err) => write!(f, "serial: {}", err),
+            SerialError::Pattern(err) => write!(f, "serial: {}", err),
+            SerialError::Closed { pattern, output } => write!(
+                f,
+                "serial: closed while waiting for {:?}, last output: {:?}",
+                pattern,
+                last_line(output)
+            ),
+            SerialError::Timeout { pattern, output } => write!(
+                f,
+                "serial: timed out waiting for {:?}, last output: {:?}",
+                pattern,
+                last_line(output)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SerialError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SerialError::Io(err) => Some(err),
+            SerialError::Pattern(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SerialError {
+    fn from(err: io::Error) -> Self {
+        SerialError::Io(err)
+    }
+}
+
+fn last_line(output: &str) -> &str {
+    output.trim_end().rsplit('\n').next().unwrap_or_default()
+}
+
+// What `expect()` matched. The output up to the end of the match is consumed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expected {
+    // Output before the match.
+    pub before: String,
+    pub matched: String,
+    // Capture groups 1.., `None` for groups that did not participate.
+    pub groups: Vec<Option<String>>,
+}
+
+// Talks to the guest serial port exposed by `--serial socket=..`.
+#[derive(Debug)]
+pub struct SerialClient {
+    stream: UnixStream,
+    output: VecDeque<u8>,
+    capacity: usize,
+    tee: Option<File>,
+}
+
+impl SerialClient {
+    pub fn connect(socket: &Path) -> io::Result<Self> {
+        Ok(SerialClient::from_stream(UnixStream::connect(socket)?))
+    }
+
+    pub fn from_stream(stream: UnixStream) -> Self {
+        SerialClient {
+            stream,
+            output: VecDeque::new(),
+            capacity: DEFAULT_CAPACITY,
+            tee: None,
+        }
+    }
+
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    // Appends everything read from the guest to `path`, including output dropped from the buffer.
+    pub fn tee(mut self, path: &Path) -> io::Result<Self> {
+        self.tee = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        Ok(self)
+    }
+
+    // Unconsumed output, lossily decoded.
+    pub fn output(&self) -> String {
+        let (front, back) = self.output.as_slices();
+        String::from_utf8_lossy(&[front, back].concat()).into_owned()
+    }
+
+    pub fn clear(&mut self) {
+        self.output.clear();
+    }
+
+    pub fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.stream.write_all(data)?;
+        self.stream.flush()
+    }
+
+    pub fn send_line(&mut self, line: &str) -> io::Result<()> {
+        self.send(format!("{}\n", line).as_bytes())
+    }
+
+    // Reads until `pattern` matches the unconsumed output.
+    pub fn expect(&mut self, pattern: &str, timeout: Duration) -> Result<Expected, SerialError> {
+        let regex = Regex::new(pattern).map_err(SerialError::Pattern)?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(expected) = self.find(&regex) {
+                return Ok(expected);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(SerialError::Timeout {
+                    pattern: pattern.to_string(),
+                    output: self.output(),
+                });
+            }
+            if !self.read(remaining)? {
+                return Err(SerialError::Closed {
+                    pattern: pattern.to_string(),
+                    output: self.output(),
+                });
+            }
+        }
+    }
+
+    fn find(&mut self, regex: &Regex) -> Option<Expected> {
+        let output = self.output.make_contiguous();
+        let captures = regex.captures(output)?;
+        let matched = captures.get(0)?;
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+        let expected = Expected {
+            before: text(&output[..matched.start()]),
+            matched: text(matched.as_bytes()),
+            groups: captures
+                .iter()
+                .skip(1)
+                .map(|group| group.map(|group| text(group.as_bytes())))
+                .collect(),
+        };
+        let end = matched.end();
+        self.output.drain(..end);
+        Some(expected)
+    }
+
+    // Waits up to `timeout` for output, `false` once the socket is closed.
+    fn read(&mut self, timeout: Duration) -> io::Result<bool> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        let mut chunk = [0; READ_CHUNK];
+        let n = match self.stream.read(&mut chunk) {
+            Ok(n) => n,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::Interrupted
+                ) =>
+            {
+                return Ok(true)
+            }
+            Err(err) => return Err(err),
+        };
+        if n == 0 {
+            return Ok(false);
+        }
+
+        if let Some(tee) = &mut self.tee {
+            tee.write_all(&chunk[..n])?;
+        }
+        self.output.extend(&chunk[..n]);
+        if self.output.len() > self.capacity {
+            self.output.drain(..self.output.len() - self.capacity);
+        }
+        Ok(true)
+    }
+}
+
+impl CloudHypervisorInstance {
+    // Connects to the socket given with `--serial socket=..`, which exists once the VM is created.
+    pub fn serial_client(&self) -> Result<SerialClient, SerialError> {
+        match &self.serial {
+            Some(Serial::Socket(path)) => Ok(SerialClient::connect(path)?),
+            _ => Err(SerialError::NoSocket),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::api::{ApiClient, ApiError};
+use crate::endpoints;
+use crate::vm_config::VmConfig;
+use crate::{CloudHypervisorInstance, OnOff, Restore};
+
+const CONFIG_FILE: &str = "config.json";
+const STATE_FILE: &str = "state.json";
+// Guest memory is saved as `memory-ranges`; older releases wrote one `memory-region-*` file per
+// region.
+const MEMORY_FILE_PREFIX: &str = "memory-";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Api(ApiError),
+    Io(io::Error),
+    Missing { dir: PathBuf, file: String },
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Api(err) => write!(f, "{}", err),
+            SnapshotError::Io(err) => write!(f, "snapshot: {}", err),
+            SnapshotError::Missing { dir, file } => {
+                write!(f, "snapshot {} has no {}", dir.display(), file)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Api(err) => Some(err),
+            SnapshotError::Io(err) => Some(err),
+            SnapshotError::Missing { .. } => None,
+        }
+    }
+}
+
+impl From<ApiError> for SnapshotError {
+    fn from(err: ApiError) -> Self {
+        SnapshotError::Api(err)
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+// What to do with the VM once the snapshot has been written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AfterSnapshot {
+    #[default]
+    Resume,
+    KeepPaused,
+    Shutdown,
+}
+
+#[derive(Serialize)]
+pub(crate) struct VmSnapshotConfig<'a> {
+    pub(crate) destination_url: &'a str,
+}
+
+#[derive(Serialize)]
+pub(crate) struct RestoreConfig<'a> {
+    pub(crate) source_url: &'a str,
+    pub(crate) prefault: bool,
+}
+
+impl ApiClient {
+    pub fn vm_snapshot(&self, destination_url: &str) -> Result<(), ApiError> {
+        self.send(endpoints::vm_snapshot(destination_url)?)
+    }
+
+    // Restores into a VMM started without a VM.
+    pub fn vm_restore(&self, source_url: &str, prefault: bool) -> Result<(), ApiError> {
+        self.send(endpoints::vm_restore(source_url, prefault)?)
+    }
+}
+
+fn file_url(dir: &Path) -> String {
+    format!("file://{}", dir.display())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    dir: PathBuf,
+}
+
+impl Snapshot {
+    // Pauses the VM, snapshots it into `dir` (created if needed) and then resumes, keeps paused
+    // or shuts down the VM. A VM whose snapshot failed is resumed before the error is returned.
+    pub fn take(
+        api: &ApiClient,
+        dir: impl AsRef<Path>,
+        after: AfterSnapshot,
+    ) -> Result<Snapshot, SnapshotError> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let dir = fs::canonicalize(dir)?;
+
+        api.vm_pause()?;
+        if let Err(err) = api.vm_snapshot(&file_url(&dir)) {
+            let _ = api.vm_resume();
+            return Err(err.into());
+        }
+        match after {
+            AfterSnapshot::Resume => api.vm_resume()?,
+            AfterSnapshot::KeepPaused => {}
+            AfterSnapshot::Shutdown => api.vm_shutdown()?,
+        }
+
+        Snapshot::open(dir)
+    }
+
+    // Checks that `dir` holds a complete snapshot.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Snapshot, SnapshotError> {
+        let dir = fs::canonicalize(dir)?;
+        let missing = |file: &str| SnapshotError::Missing {
+            dir: dir.clone(),
+            file: file.to_string(),
+        };
+
+        for file in [CONFIG_FILE, STATE_FILE] {
+            if !dir.join(file).is_file() {
+                return Err(missing(file));
+            }
+        }
+        let mut has_memory = false;
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(MEMORY_FILE_PREFIX)
+                && entry.file_type()?.is_file()
+            {
+                has_memory = true;
+            }
+        }
+        if !has_memory {
+            return Err(missing("memory file"));
+        }
+
+        Ok(Snapshot { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn source_url(&self) -> String {
+        file_url(&self.dir)
+    }
+
+    pub fn config(&self) -> io::Result<VmConfig> {
+        VmConfig::read(self.dir.join(CONFIG_FILE))
+    }
+
+    // An instance that restores this snapshot. The VM configuration comes from the snapshot, so
+    // only the VMM level options of `base` are kept.
+    pub fn restore_instance(
+        &self,
+        base: &CloudHypervisorInstance,
+        prefault: Option<OnOff>,
+    ) -> CloudHypervisorInstance {
+        let mut instance = base.vmm_instance();
+        instance.restore(Restore {
+            source_url: Some(self.source_url()),
+            prefault,
+        });
+        instance
+    }
+}
@@ -1,3 +1,7 @@
+use std::ffi::OsString;
+
+use crate::error::Error;
+
 pub trait ToCommand {
     fn to_command(&self) -> Vec<String>;
 
@@ -5,3 +9,7 @@ pub trait ToCommand {
         self.to_command().join(" ")
     }
 }
+
+pub trait TryToCommand {
+    fn try_to_command(&self) -> Result<Vec<OsString>, Error>;
+}
@@ -0,0 +1,318 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use crate::error::Error;
+use crate::CloudHypervisorInstance;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    UnknownRateLimitGroup {
+        disk: Option<String>,
+        rate_limit_group: String,
+    },
+    UnknownMemoryZone {
+        guest_numa_id: Option<String>,
+        memory_zone: String,
+    },
+    UnknownSgxEpc {
+        guest_numa_id: Option<String>,
+        sgx_epc: String,
+    },
+    InvalidPciSegment {
+        option: String,
+        id: Option<String>,
+        pci_segment: String,
+    },
+    PciSegmentOutOfRange {
+        option: String,
+        id: Option<String>,
+        pci_segment: u16,
+        num_pci_segments: u16,
+    },
+    DuplicateId {
+        id: String,
+        options: Vec<String>,
+    },
+}
+
+fn fmt_id(f: &mut Formatter<'_>, option: &str, id: &Option<String>) -> std::fmt::Result {
+    match id {
+        None => write!(f, "{}", option),
+        Some(id) => write!(f, "{} (id={})", option, id),
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::UnknownRateLimitGroup {
+                disk,
+                rate_limit_group,
+            } => {
+                fmt_id(f, "--disk", disk)?;
+                write!(f, ": unknown rate_limit_group `{}`", rate_limit_group)
+            }
+            ValidationError::UnknownMemoryZone {
+                guest_numa_id,
+                memory_zone,
+            } => {
+                fmt_id(f, "--numa", guest_numa_id)?;
+                write!(f, ": unknown memory zone `{}`", memory_zone)
+            }
+            ValidationError::UnknownSgxEpc {
+                guest_numa_id,
+                sgx_epc,
+            } => {
+                fmt_id(f, "--numa", guest_numa_id)?;
+                write!(f, ": unknown sgx_epc section `{}`", sgx_epc)
+            }
+            ValidationError::InvalidPciSegment {
+                option,
+                id,
+                pci_segment,
+            } => {
+                fmt_id(f, option, id)?;
+                write!(f, ": invalid pci segment `{}`", pci_segment)
+            }
+            ValidationError::PciSegmentOutOfRange {
+                option,
+                id,
+                pci_segment,
+                num_pci_segments,
+            } => {
+                fmt_id(f, option, id)?;
+                write!(
+                    f,
+                    ": pci segment {} is not below num_pci_segments={}",
+                    pci_segment, num_pci_segments
+                )
+            }
+            ValidationError::DuplicateId { id, options } => {
+                write!(f, "id `{}` is used by {}", id, options.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl CloudHypervisorInstance {
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut errors = vec![];
+
+        self.validate_rate_limit_groups(&mut errors);
+        self.validate_numa(&mut errors);
+        self.validate_pci_segments(&mut errors);
+        self.validate_device_ids(&mut errors);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(errors))
+        }
+    }
+
+    fn validate_rate_limit_groups(&self, errors: &mut Vec<ValidationError>) {
+        let groups = self
+            .rate_limit_group
+            .iter()
+            .flatten()
+            .filter_map(|group| group.id.as_deref())
+            .collect::<Vec<&str>>();
+
+        for disk in self.disk.iter().flatten() {
+            if let Some(rate_limit_group) = &disk.rate_limit_group {
+                if !groups.contains(&rate_limit_group.as_str()) {
+                    errors.push(ValidationError::UnknownRateLimitGroup {
+                        disk: disk.id.clone(),
+                        rate_limit_group: rate_limit_group.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn validate_numa(&self, errors: &mut Vec<ValidationError>) {
+        let memory_zones = self
+            .memory_zone
+            .iter()
+            .filter_map(|zone| zone.id.as_deref())
+            .collect::<Vec<&str>>();
+        let sgx_epcs = self
+            .sgx_epc
+            .iter()
+            .flatten()
+            .filter_map(|epc| epc.id.as_deref())
+            .collect::<Vec<&str>>();
+
+        for numa in self.numa.iter().flatten() {
+            for memory_zone in numa.memory_zones.iter().flatten() {
+                if !memory_zones.contains(&memory_zone.as_str()) {
+                    errors.push(ValidationError::UnknownMemoryZone {
+                        guest_numa_id: numa.guest_numa_id.clone(),
+                        memory_zone: memory_zone.clone(),
+                    });
+                }
+            }
+            for sgx_epc in numa.sgx_epc_sections.iter().flatten() {
+                if !sgx_epcs.contains(&sgx_epc.as_str()) {
+                    errors.push(ValidationError::UnknownSgxEpc {
+                        guest_numa_id: numa.guest_numa_id.clone(),
+                        sgx_epc: sgx_epc.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn pci_segments(&self) -> Vec<(&'static str, Option<&String>, &String)> {
+        let mut segments = vec![];
+
+        for numa in self.numa.iter().flatten() {
+            for pci_segment in numa.pci_segments.iter().flatten() {
+                segments.push(("--numa", numa.guest_numa_id.as_ref(), pci_segment));
+            }
+        }
+        for disk in self.disk.iter().flatten() {
+            if let Some(pci_segment) = &disk.pci_segment {
+                segments.push(("--disk", disk.id.as_ref(), pci_segment));
+            }
+        }
+        for net in self.net.iter().flatten() {
+            if let Some(pci_segment) = &net.pci_segment {
+                segments.push(("--net", net.id.as_ref(), pci_segment));
+            }
+        }
+        for fs in self.fs.iter().flatten() {
+            if let Some(pci_segment) = &fs.pci_segment {
+                segments.push(("--fs", fs.id.as_ref(), pci_segment));
+            }
+        }
+        for pmem in self.pmem.iter().flatten() {
+            if let Some(pci_segment) = &pmem.pci_segment {
+                segments.push(("--pmem", pmem.id.as_ref(), pci_segment));
+            }
+        }
+        for device in self.device.iter().flatten() {
+            if let Some(pci_segment) = &device.pci_segment {
+                segments.push(("--device", device.id.as_ref(), pci_segment));
+            }
+        }
+        for user_device in self.user_device.iter().flatten() {
+            if let Some(pci_segment) = &user_device.pci_segment {
+                segments.push(("--user-device", user_device.id.as_ref(), pci_segment));
+            }
+        }
+        for vdpa in self.vdpa.iter().flatten() {
+            if let Some(pci_segment) = &vdpa.pci_segment {
+                segments.push(("--vdpa", vdpa.id.as_ref(), pci_segment));
+            }
+        }
+        if let Some(vsock) = &self.vsock {
+            if let Some(pci_segment) = &vsock.pci_segment {
+                segments.push(("--vsock", vsock.id.as_ref(), pci_segment));
+            }
+        }
+
+        segments
+    }
+
+    fn validate_pci_segments(&self, errors: &mut Vec<ValidationError>) {
+        // cloud-hypervisor always creates segment 0
+        let num_pci_segments = self
+            .platform
+            .as_ref()
+            .and_then(|platform| platform.num_pci_segments)
+            .map(u16::from)
+            .unwrap_or(1);
+
+        for (option, id, pci_segment) in self.pci_segments() {
+            match pci_segment.parse::<u16>() {
+                Err(_) => errors.push(ValidationError::InvalidPciSegment {
+                    option: option.to_string(),
+                    id: id.cloned(),
+                    pci_segment: pci_segment.clone(),
+                }),
+                Ok(segment) if segment >= num_pci_segments => {
+                    errors.push(ValidationError::PciSegmentOutOfRange {
+                        option: option.to_string(),
+                        id: id.cloned(),
+                        pci_segment: segment,
+                        num_pci_segments,
+                    })
+                }
+                Ok(_) => {}
+            }
+        }
+    }
+
+    fn device_ids(&self) -> Vec<(&'static str, &String)> {
+        let mut ids = vec![];
+
+        ids.extend(
+            self.disk
+                .iter()
+                .flatten()
+                .filter_map(|d| d.id.as_ref().map(|id| ("--disk", id))),
+        );
+        ids.extend(
+            self.net
+                .iter()
+                .flatten()
+                .filter_map(|d| d.id.as_ref().map(|id| ("--net", id))),
+        );
+        ids.extend(
+            self.fs
+                .iter()
+                .flatten()
+                .filter_map(|d| d.id.as_ref().map(|id| ("--fs", id))),
+        );
+        ids.extend(
+            self.pmem
+                .iter()
+                .flatten()
+                .filter_map(|d| d.id.as_ref().map(|id| ("--pmem", id))),
+        );
+        ids.extend(
+            self.device
+                .iter()
+                .flatten()
+                .filter_map(|d| d.id.as_ref().map(|id| ("--device", id))),
+        );
+        ids.extend(
+            self.vdpa
+                .iter()
+                .flatten()
+                .filter_map(|d| d.id.as_ref().map(|id| ("--vdpa", id))),
+        );
+        ids.extend(
+            self.user_device
+                .iter()
+                .flatten()
+                .filter_map(|d| d.id.as_ref().map(|id| ("--user-device", id))),
+        );
+        ids.extend(
+            self.vsock
+                .iter()
+                .filter_map(|d| d.id.as_ref().map(|id| ("--vsock", id))),
+        );
+
+        ids
+    }
+
+    fn validate_device_ids(&self, errors: &mut Vec<ValidationError>) {
+        let mut seen: BTreeMap<&String, Vec<String>> = BTreeMap::new();
+        for (option, id) in self.device_ids() {
+            seen.entry(id).or_default().push(option.to_string());
+        }
+
+        for (id, options) in seen {
+            if options.len() > 1 {
+                errors.push(ValidationError::DuplicateId {
+                    id: id.clone(),
+                    options,
+                });
+            }
+        }
+    }
+}
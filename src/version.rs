@@ -0,0 +1,134 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct ChVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl ChVersion {
+    pub const V38: ChVersion = ChVersion::new(38, 0, 0);
+    pub const V39: ChVersion = ChVersion::new(39, 0, 0);
+    pub const V40: ChVersion = ChVersion::new(40, 0, 0);
+    pub const V41: ChVersion = ChVersion::new(41, 0, 0);
+
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        ChVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl Display for ChVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidVersion(pub String);
+
+impl Display for InvalidVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid cloud-hypervisor version `{}`", self.0)
+    }
+}
+
+impl std::error::Error for InvalidVersion {}
+
+// Accepts `v40.0`, `40.0.1` and the `git describe` style `v40.0-12-gdeadbeef` that development
+// builds report; missing components are zero.
+impl FromStr for ChVersion {
+    type Err = InvalidVersion;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidVersion(s.to_string());
+
+        let version = s.trim();
+        let version = version.strip_prefix('v').unwrap_or(version);
+        let version = version.split('-').next().unwrap_or_default();
+
+        let mut parts = version.split('.');
+        let mut next = |required: bool| match parts.next() {
+            None if !required => Ok(0),
+            None => Err(invalid()),
+            Some(part) => part.parse::<u32>().map_err(|_| invalid()),
+        };
+        let parsed = ChVersion::new(next(true)?, next(false)?, next(false)?);
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(parsed)
+    }
+}
+
+struct Change {
+    option: &'static str,
+    key: Option<&'static str>,
+    added: Option<ChVersion>,
+    removed: Option<ChVersion>,
+}
+
+// Only changes confirmed against the upstream release notes are listed. Everything the crate
+// models that is not listed here is assumed to be accepted by every release from v38 on.
+const CHANGES: &[Change] = &[
+    Change {
+        option: "--sgx-epc",
+        key: None,
+        added: None,
+        removed: Some(ChVersion::V40),
+    },
+    Change {
+        option: "--numa",
+        key: Some("sgx_epc_sections"),
+        added: None,
+        removed: Some(ChVersion::V40),
+    },
+    Change {
+        option: "--landlock",
+        key: None,
+        added: Some(ChVersion::V40),
+        removed: None,
+    },
+    Change {
+        option: "--landlock-rules",
+        key: None,
+        added: Some(ChVersion::V40),
+        removed: None,
+    },
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Availability {
+    pub(crate) added: Option<ChVersion>,
+    pub(crate) removed: Option<ChVersion>,
+}
+
+impl Availability {
+    fn supports(&self, version: ChVersion) -> bool {
+        self.added.map_or(true, |added| version >= added)
+            && self.removed.map_or(true, |removed| version < removed)
+    }
+}
+
+pub(crate) fn unavailable(
+    version: ChVersion,
+    option: &str,
+    key: Option<&str>,
+) -> Option<Availability> {
+    CHANGES
+        .iter()
+        .filter(|change| change.option == option && change.key == key)
+        .map(|change| Availability {
+            added: change.added,
+            removed: change.removed,
+        })
+        .find(|availability| !availability.supports(version))
+}
@@ -0,0 +1,1729 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bytesize::ByteSize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::encode::ToOptionValue;
+use crate::error::Error;
+use crate::from_command::{
+    parse_affinity, parse_byte_size, prefix_len, ParseError, ParseErrorKind,
+};
+use crate::{
+    Balloon, CloudHypervisorInstance, Console, CpuAffinity, CpuFeatures, CpuTopology, Cpus,
+    DebugConsole, DebugConsoleType, Device, Disk, Fs, LandlockRule, Memory, MemoryHotplugMethod,
+    MemoryZone, Net, Numa, NumaDistance, OnOff, Platform, Pmem, RateLimitGroup, Rng, Serial,
+    SgxEpc, UserDevice, Vdpa, VhostMode, Vsock,
+};
+
+// The JSON `VmConfig` accepted by `PUT /api/v1/vm.create` and returned by `GET /api/v1/vm.info`.
+// Fields cloud-hypervisor has but the crate does not model are kept in `other` so a config read
+// from the API can be sent back unchanged.
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VmConfig {
+    pub cpus: CpusConfig,
+    pub memory: MemoryConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<PayloadConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit_groups: Option<Vec<RateLimiterGroupConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disks: Option<Vec<DiskConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub net: Option<Vec<NetConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rng: Option<RngConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balloon: Option<BalloonConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fs: Option<Vec<FsConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pmem: Option<Vec<PmemConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial: Option<ConsoleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub console: Option<ConsoleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug_console: Option<DebugConsoleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub devices: Option<Vec<DeviceConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_devices: Option<Vec<UserDeviceConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vdpa: Option<Vec<VdpaConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vsock: Option<VsockConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pvpanic: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sgx_epc: Option<Vec<SgxEpcConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub numa: Option<Vec<NumaConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watchdog: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<PlatformConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tpm: Option<TpmConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub landlock_enable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub landlock_rules: Option<Vec<LandlockConfig>>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CpusConfig {
+    pub boot_vcpus: u8,
+    pub max_vcpus: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topology: Option<CpuTopology>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kvm_hyperv: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_phys_bits: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub affinity: Option<Vec<CpuAffinity>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub features: Option<CpuFeaturesConfig>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CpuFeaturesConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amx: Option<bool>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PlatformConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_pci_segments: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iommu_segments: Option<Vec<u16>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oem_strings: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MemoryConfig {
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mergeable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hotplug_method: Option<MemoryHotplugMethod>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hotplug_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hotplugged_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hugepages: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hugepage_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefault: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zones: Option<Vec<MemoryZoneConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thp: Option<bool>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MemoryZoneConfig {
+    pub id: String,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hugepages: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hugepage_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_numa_node: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hotplug_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hotplugged_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefault: Option<bool>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PayloadConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub firmware: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cmdline: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initramfs: Option<PathBuf>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TokenBucketConfig {
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub one_time_burst: Option<u64>,
+    pub refill_time: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RateLimiterConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<TokenBucketConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ops: Option<TokenBucketConfig>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RateLimiterGroupConfig {
+    pub id: String,
+    pub rate_limiter_config: RateLimiterConfig,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VirtQueueAffinity {
+    pub queue_index: u16,
+    pub host_cpus: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DiskConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub readonly: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direct: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iommu: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_queues: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_size: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vhost_user: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vhost_socket: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit_group: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limiter_config: Option<RateLimiterConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pci_segment: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_affinity: Option<Vec<VirtQueueAffinity>>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NetConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tap: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mask: Option<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iommu: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_queues: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_size: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vhost_user: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vhost_socket: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vhost_mode: Option<VhostMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fds: Option<Vec<i32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limiter_config: Option<RateLimiterConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pci_segment: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offload_tso: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offload_ufo: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offload_csum: Option<bool>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RngConfig {
+    pub src: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iommu: Option<bool>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BalloonConfig {
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deflate_on_oom: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_page_reporting: Option<bool>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FsConfig {
+    pub tag: String,
+    pub socket: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_queues: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_size: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pci_segment: Option<u16>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PmemConfig {
+    pub file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iommu: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discard_writes: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pci_segment: Option<u16>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ConsoleOutputMode {
+    Off,
+    Pty,
+    Tty,
+    File,
+    Socket,
+    #[default]
+    Null,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ConsoleConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    pub mode: ConsoleOutputMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iommu: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket: Option<PathBuf>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DebugConsoleConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    pub mode: ConsoleOutputMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iobase: Option<u16>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DeviceConfig {
+    pub path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iommu: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pci_segment: Option<u16>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UserDeviceConfig {
+    pub socket: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pci_segment: Option<u16>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VdpaConfig {
+    pub path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_queues: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iommu: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pci_segment: Option<u16>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VsockConfig {
+    pub cid: u32,
+    pub socket: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iommu: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pci_segment: Option<u16>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SgxEpcConfig {
+    pub id: String,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefault: Option<bool>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NumaConfig {
+    pub guest_numa_id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<Vec<u8>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distances: Option<Vec<NumaDistance>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_zones: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sgx_epc_sections: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pci_segments: Option<Vec<u16>>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TpmConfig {
+    pub socket: PathBuf,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LandlockConfig {
+    pub path: PathBuf,
+    pub access: String,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, Value>,
+}
+
+fn invalid(option: &str, key: &str, value: impl Display) -> Error {
+    Error::Parse(ParseError {
+        flag: option.to_string(),
+        key: Some(key.to_string()),
+        kind: ParseErrorKind::InvalidValue(value.to_string()),
+    })
+}
+
+fn missing(option: &str, key: &str) -> Error {
+    Error::Parse(ParseError {
+        flag: option.to_string(),
+        key: Some(key.to_string()),
+        kind: ParseErrorKind::MissingValue,
+    })
+}
+
+fn required<T: Clone>(option: &str, key: &str, value: &Option<T>) -> Result<T, Error> {
+    value.clone().ok_or_else(|| missing(option, key))
+}
+
+fn parse<T: FromStr>(option: &str, key: &str, value: &str) -> Result<T, Error> {
+    value.parse().map_err(|_| invalid(option, key, value))
+}
+
+fn parse_opt<T: FromStr>(
+    option: &str,
+    key: &str,
+    value: &Option<String>,
+) -> Result<Option<T>, Error> {
+    value
+        .as_deref()
+        .map(|value| parse(option, key, value))
+        .transpose()
+}
+
+fn convert<T: TryFrom<usize>>(option: &str, key: &str, value: usize) -> Result<T, Error> {
+    T::try_from(value).map_err(|_| invalid(option, key, value))
+}
+
+fn on(value: &Option<OnOff>) -> Option<bool> {
+    value.as_ref().map(|value| *value == OnOff::On)
+}
+
+fn bytes(value: &Option<ByteSize>) -> Option<u64> {
+    value.map(|value| value.as_u64())
+}
+
+// Like cloud-hypervisor's own parser, a bucket is only created when both its size and refill time
+// are set.
+fn token_bucket(
+    size: Option<u64>,
+    one_time_burst: Option<u64>,
+    refill_time: Option<usize>,
+) -> Option<TokenBucketConfig> {
+    match (size, refill_time) {
+        (Some(size), Some(refill_time)) if size != 0 && refill_time != 0 => {
+            Some(TokenBucketConfig {
+                size,
+                one_time_burst,
+                refill_time: refill_time as u64,
+            })
+        }
+        _ => None,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn rate_limiter(
+    bw_size: &Option<ByteSize>,
+    bw_one_time_burst: &Option<ByteSize>,
+    bw_refill_time: &Option<usize>,
+    ops_size: &Option<usize>,
+    ops_one_time_burst: &Option<usize>,
+    ops_refill_time: &Option<usize>,
+) -> Option<RateLimiterConfig> {
+    let bandwidth = token_bucket(bytes(bw_size), bytes(bw_one_time_burst), *bw_refill_time);
+    let ops = token_bucket(
+        ops_size.map(|v| v as u64),
+        ops_one_time_burst.map(|v| v as u64),
+        *ops_refill_time,
+    );
+    if bandwidth.is_none() && ops.is_none() {
+        None
+    } else {
+        Some(RateLimiterConfig { bandwidth, ops })
+    }
+}
+
+impl TryFrom<&Cpus> for CpusConfig {
+    type Error = Error;
+
+    fn try_from(cpus: &Cpus) -> Result<Self, Self::Error> {
+        let boot_vcpus = cpus.boot.unwrap_or(1);
+        Ok(CpusConfig {
+            boot_vcpus,
+            max_vcpus: cpus.max.unwrap_or(boot_vcpus),
+            topology: cpus.topology.clone(),
+            kvm_hyperv: on(&cpus.kvm_hyperv),
+            max_phys_bits: cpus.max_phys_bits,
+            affinity: cpus.affinity.clone(),
+            features: cpus.features.as_ref().map(|features| CpuFeaturesConfig {
+                amx: features.amx,
+                other: BTreeMap::new(),
+            }),
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&Platform> for PlatformConfig {
+    type Error = Error;
+
+    fn try_from(platform: &Platform) -> Result<Self, Self::Error> {
+        Ok(PlatformConfig {
+            num_pci_segments: platform.num_pci_segments.map(u16::from),
+            iommu_segments: platform
+                .iommu_segments
+                .map(|segment| vec![u16::from(segment)]),
+            serial_number: platform.serial_number.clone(),
+            uuid: platform.uuid.clone(),
+            oem_strings: platform.oem_strings.clone(),
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+// cloud-hypervisor defaults to 512 MiB of guest memory.
+pub(crate) const DEFAULT_MEMORY_SIZE: u64 = 512 << 20;
+
+impl TryFrom<&Memory> for MemoryConfig {
+    type Error = Error;
+
+    fn try_from(memory: &Memory) -> Result<Self, Self::Error> {
+        Ok(MemoryConfig {
+            size: bytes(&memory.size).unwrap_or(DEFAULT_MEMORY_SIZE),
+            mergeable: on(&memory.mergeable),
+            hotplug_method: memory.hotplug_method.clone(),
+            hotplug_size: bytes(&memory.hotplug_size),
+            hotplugged_size: bytes(&memory.hotplugged_size),
+            shared: on(&memory.shared),
+            hugepages: on(&memory.hugepages),
+            hugepage_size: bytes(&memory.hugepage_size),
+            prefault: on(&memory.prefault),
+            zones: None,
+            thp: on(&memory.thp),
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&MemoryZone> for MemoryZoneConfig {
+    type Error = Error;
+
+    fn try_from(zone: &MemoryZone) -> Result<Self, Self::Error> {
+        let option = MemoryZone::OPTION;
+        Ok(MemoryZoneConfig {
+            id: required(option, "id", &zone.id)?,
+            size: required(option, "size", &bytes(&zone.size))?,
+            file: zone.file.clone(),
+            shared: on(&zone.shared),
+            hugepages: on(&zone.hugepages),
+            hugepage_size: bytes(&zone.hugepage_size),
+            host_numa_node: zone
+                .host_numa_node
+                .map(|node| convert(option, "host_numa_node", node))
+                .transpose()?,
+            hotplug_size: bytes(&zone.hotplug_size),
+            hotplugged_size: bytes(&zone.hotplugged_size),
+            prefault: on(&zone.prefault),
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&RateLimitGroup> for RateLimiterGroupConfig {
+    type Error = Error;
+
+    fn try_from(group: &RateLimitGroup) -> Result<Self, Self::Error> {
+        Ok(RateLimiterGroupConfig {
+            id: required(RateLimitGroup::OPTION, "id", &group.id)?,
+            rate_limiter_config: rate_limiter(
+                &group.bw_size,
+                &group.bw_one_time_burst,
+                &group.bw_refill_time,
+                &group.ops_size,
+                &group.ops_one_time_burst,
+                &group.ops_refill_time,
+            )
+            .unwrap_or_default(),
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&Disk> for DiskConfig {
+    type Error = Error;
+
+    fn try_from(disk: &Disk) -> Result<Self, Self::Error> {
+        let option = Disk::OPTION;
+        Ok(DiskConfig {
+            path: disk.path.clone(),
+            readonly: on(&disk.readonly),
+            direct: on(&disk.direct),
+            iommu: on(&disk.iommu),
+            num_queues: disk.num_queues,
+            queue_size: disk
+                .queue_size
+                .map(|size| convert(option, "queue_size", size))
+                .transpose()?,
+            vhost_user: on(&disk.vhost_user),
+            vhost_socket: disk.socket.clone(),
+            rate_limit_group: disk.rate_limit_group.clone(),
+            rate_limiter_config: rate_limiter(
+                &disk.bw_size,
+                &disk.bw_one_time_burst,
+                &disk.bw_refill_time,
+                &disk.ops_size,
+                &disk.ops_one_time_burst,
+                &disk.ops_refill_time,
+            ),
+            id: disk.id.clone(),
+            pci_segment: parse_opt(option, "pci_segment", &disk.pci_segment)?,
+            queue_affinity: disk
+                .queue_affinity
+                .as_deref()
+                .map(|value| parse_affinity(option, "queue_affinity", value))
+                .transpose()?
+                .map(|affinity| {
+                    affinity
+                        .into_iter()
+                        .map(|(queue_index, host_cpus)| VirtQueueAffinity {
+                            queue_index,
+                            host_cpus,
+                        })
+                        .collect()
+                }),
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+// The crate models the netmask as a prefix length, the API expects an address.
+fn netmask(prefix: u8) -> Option<IpAddr> {
+    if prefix > 32 {
+        return None;
+    }
+    let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+    Some(IpAddr::V4(Ipv4Addr::from(mask)))
+}
+
+impl TryFrom<&Net> for NetConfig {
+    type Error = Error;
+
+    fn try_from(net: &Net) -> Result<Self, Self::Error> {
+        let option = Net::OPTION;
+        Ok(NetConfig {
+            tap: net.tap.clone(),
+            ip: net.ip,
+            mask: net
+                .mask
+                .map(|mask| netmask(mask).ok_or_else(|| invalid(option, "mask", mask)))
+                .transpose()?,
+            mac: net.mac.clone(),
+            iommu: on(&net.iommu),
+            num_queues: net.num_queues,
+            queue_size: net
+                .queue_size
+                .map(|size| convert(option, "queue_size", size))
+                .transpose()?,
+            vhost_user: on(&net.vhost_user),
+            vhost_socket: net.socket.clone(),
+            vhost_mode: net.vhost_mode.clone(),
+            id: net.id.clone(),
+            fds: net
+                .fd
+                .as_ref()
+                .map(|fds| {
+                    fds.iter()
+                        .map(|fd| convert(option, "fd", *fd))
+                        .collect::<Result<Vec<i32>, Error>>()
+                })
+                .transpose()?,
+            rate_limiter_config: rate_limiter(
+                &net.bw_size,
+                &net.bw_one_time_burst,
+                &net.bw_refill_time,
+                &net.ops_size,
+                &net.ops_one_time_burst,
+                &net.ops_refill_time,
+            ),
+            pci_segment: parse_opt(option, "pci_segment", &net.pci_segment)?,
+            offload_tso: on(&net.offload_tso),
+            offload_ufo: on(&net.offload_ufo),
+            offload_csum: on(&net.offload_csum),
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+const DEFAULT_RNG_SOURCE: &str = "/dev/urandom";
+
+impl TryFrom<&Rng> for RngConfig {
+    type Error = Error;
+
+    fn try_from(rng: &Rng) -> Result<Self, Self::Error> {
+        Ok(match rng {
+            Rng::Src(src) => RngConfig {
+                src: src.clone(),
+                iommu: None,
+                other: BTreeMap::new(),
+            },
+            Rng::Iommu(iommu) => RngConfig {
+                src: PathBuf::from(DEFAULT_RNG_SOURCE),
+                iommu: on(&Some(iommu.clone())),
+                other: BTreeMap::new(),
+            },
+        })
+    }
+}
+
+impl TryFrom<&Balloon> for BalloonConfig {
+    type Error = Error;
+
+    fn try_from(balloon: &Balloon) -> Result<Self, Self::Error> {
+        Ok(BalloonConfig {
+            size: required(Balloon::OPTION, "size", &bytes(&balloon.size))?,
+            deflate_on_oom: on(&balloon.deflate_on_oom),
+            free_page_reporting: on(&balloon.free_page_reporting),
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&Fs> for FsConfig {
+    type Error = Error;
+
+    fn try_from(fs: &Fs) -> Result<Self, Self::Error> {
+        let option = Fs::OPTION;
+        Ok(FsConfig {
+            tag: required(option, "tag", &fs.tag)?,
+            socket: required(option, "socket", &fs.socket)?,
+            num_queues: fs.num_queues,
+            queue_size: fs
+                .queue_size
+                .map(|size| convert(option, "queue_size", size))
+                .transpose()?,
+            id: fs.id.clone(),
+            pci_segment: parse_opt(option, "pci_segment", &fs.pci_segment)?,
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&Pmem> for PmemConfig {
+    type Error = Error;
+
+    fn try_from(pmem: &Pmem) -> Result<Self, Self::Error> {
+        let option = Pmem::OPTION;
+        Ok(PmemConfig {
+            file: required(option, "file", &pmem.file)?,
+            size: pmem.size.map(|size| size as u64),
+            iommu: on(&pmem.iommu),
+            discard_writes: on(&pmem.discard_writes),
+            id: pmem.id.clone(),
+            pci_segment: parse_opt(option, "pci_segment", &pmem.pci_segment)?,
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+fn console_config(mode: ConsoleOutputMode) -> ConsoleConfig {
+    ConsoleConfig {
+        mode,
+        ..ConsoleConfig::default()
+    }
+}
+
+impl TryFrom<&Serial> for ConsoleConfig {
+    type Error = Error;
+
+    fn try_from(serial: &Serial) -> Result<Self, Self::Error> {
+        Ok(match serial {
+            Serial::Off => console_config(ConsoleOutputMode::Off),
+            Serial::Null => console_config(ConsoleOutputMode::Null),
+            Serial::Pty => console_config(ConsoleOutputMode::Pty),
+            Serial::Tty => console_config(ConsoleOutputMode::Tty),
+            Serial::File(file) => ConsoleConfig {
+                file: Some(file.clone()),
+                ..console_config(ConsoleOutputMode::File)
+            },
+            Serial::Socket(socket) => ConsoleConfig {
+                socket: Some(socket.clone()),
+                ..console_config(ConsoleOutputMode::Socket)
+            },
+        })
+    }
+}
+
+impl TryFrom<&Console> for ConsoleConfig {
+    type Error = Error;
+
+    fn try_from(console: &Console) -> Result<Self, Self::Error> {
+        Ok(match console {
+            Console::Off => console_config(ConsoleOutputMode::Off),
+            Console::Null => console_config(ConsoleOutputMode::Null),
+            Console::Pty => console_config(ConsoleOutputMode::Pty),
+            Console::Tty => console_config(ConsoleOutputMode::Tty),
+            Console::File(file) => ConsoleConfig {
+                file: Some(file.clone()),
+                ..console_config(ConsoleOutputMode::File)
+            },
+            // `--console iommu=on` keeps the default tty output
+            Console::Iommu(iommu) => ConsoleConfig {
+                iommu: on(&Some(iommu.clone())),
+                ..console_config(ConsoleOutputMode::Tty)
+            },
+        })
+    }
+}
+
+impl TryFrom<&DebugConsole> for DebugConsoleConfig {
+    type Error = Error;
+
+    fn try_from(debug_console: &DebugConsole) -> Result<Self, Self::Error> {
+        let (mode, file) = match &debug_console.console_type {
+            None | Some(DebugConsoleType::Off) => (ConsoleOutputMode::Off, None),
+            Some(DebugConsoleType::Pty) => (ConsoleOutputMode::Pty, None),
+            Some(DebugConsoleType::Tty) => (ConsoleOutputMode::Tty, None),
+            Some(DebugConsoleType::File(file)) => (ConsoleOutputMode::File, Some(file.clone())),
+        };
+        // cloud-hypervisor always reads the io port as hex
+        let iobase = debug_console
+            .iobase
+            .as_deref()
+            .map(|iobase| {
+                let hex = iobase.strip_prefix("0x").unwrap_or(iobase);
+                u16::from_str_radix(hex, 16)
+                    .map_err(|_| invalid(DebugConsole::OPTION, "iobase", iobase))
+            })
+            .transpose()?;
+
+        Ok(DebugConsoleConfig {
+            file,
+            mode,
+            iobase,
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&Device> for DeviceConfig {
+    type Error = Error;
+
+    fn try_from(device: &Device) -> Result<Self, Self::Error> {
+        let option = Device::OPTION;
+        Ok(DeviceConfig {
+            path: required(option, "path", &device.path)?,
+            iommu: on(&device.iommu),
+            id: device.id.clone(),
+            pci_segment: parse_opt(option, "pci_segment", &device.pci_segment)?,
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&UserDevice> for UserDeviceConfig {
+    type Error = Error;
+
+    fn try_from(user_device: &UserDevice) -> Result<Self, Self::Error> {
+        let option = UserDevice::OPTION;
+        Ok(UserDeviceConfig {
+            socket: required(option, "socket", &user_device.socket)?,
+            id: user_device.id.clone(),
+            pci_segment: parse_opt(option, "pci_segment", &user_device.pci_segment)?,
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&Vdpa> for VdpaConfig {
+    type Error = Error;
+
+    fn try_from(vdpa: &Vdpa) -> Result<Self, Self::Error> {
+        let option = Vdpa::OPTION;
+        Ok(VdpaConfig {
+            path: required(option, "path", &vdpa.path)?,
+            num_queues: vdpa.num_queues,
+            iommu: on(&vdpa.iommu),
+            id: vdpa.id.clone(),
+            pci_segment: parse_opt(option, "pci_segment", &vdpa.pci_segment)?,
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&Vsock> for VsockConfig {
+    type Error = Error;
+
+    fn try_from(vsock: &Vsock) -> Result<Self, Self::Error> {
+        let option = Vsock::OPTION;
+        Ok(VsockConfig {
+            cid: parse(option, "cid", &required(option, "cid", &vsock.cid)?)?,
+            socket: required(option, "socket", &vsock.socket)?,
+            iommu: on(&vsock.iommu),
+            id: vsock.id.clone(),
+            pci_segment: parse_opt(option, "pci_segment", &vsock.pci_segment)?,
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&SgxEpc> for SgxEpcConfig {
+    type Error = Error;
+
+    fn try_from(sgx_epc: &SgxEpc) -> Result<Self, Self::Error> {
+        let option = SgxEpc::OPTION;
+        let size = required(option, "size", &sgx_epc.size)?;
+        Ok(SgxEpcConfig {
+            id: required(option, "id", &sgx_epc.id)?,
+            size: parse_byte_size(&size)
+                .ok_or_else(|| invalid(option, "size", &size))?
+                .as_u64(),
+            prefault: on(&sgx_epc.prefault),
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&Numa> for NumaConfig {
+    type Error = Error;
+
+    fn try_from(numa: &Numa) -> Result<Self, Self::Error> {
+        let option = Numa::OPTION;
+        Ok(NumaConfig {
+            guest_numa_id: parse(
+                option,
+                "guest_numa_id",
+                &required(option, "guest_numa_id", &numa.guest_numa_id)?,
+            )?,
+            cpus: numa
+                .cpus
+                .as_ref()
+                .map(|cpus| {
+                    cpus.iter()
+                        .map(|cpu| convert(option, "cpus", *cpu))
+                        .collect::<Result<Vec<u8>, Error>>()
+                })
+                .transpose()?,
+            distances: numa.distances.clone(),
+            memory_zones: numa.memory_zones.clone(),
+            sgx_epc_sections: numa.sgx_epc_sections.clone(),
+            pci_segments: numa
+                .pci_segments
+                .as_ref()
+                .map(|segments| {
+                    segments
+                        .iter()
+                        .map(|segment| parse(option, "pci_segments", segment))
+                        .collect::<Result<Vec<u16>, Error>>()
+                })
+                .transpose()?,
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl TryFrom<&LandlockRule> for LandlockConfig {
+    type Error = Error;
+
+    fn try_from(rule: &LandlockRule) -> Result<Self, Self::Error> {
+        let option = LandlockRule::OPTION;
+        Ok(LandlockConfig {
+            path: required(option, "path", &rule.path)?,
+            access: required(option, "access", &rule.access)?,
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+fn convert_all<'a, T: 'a, C>(items: &'a Option<Vec<T>>) -> Result<Option<Vec<C>>, Error>
+where
+    C: TryFrom<&'a T, Error = Error>,
+{
+    items
+        .as_ref()
+        .map(|items| items.iter().map(C::try_from).collect())
+        .transpose()
+}
+
+fn convert_one<'a, T: 'a, C>(item: &'a Option<T>) -> Result<Option<C>, Error>
+where
+    C: TryFrom<&'a T, Error = Error>,
+{
+    item.as_ref().map(C::try_from).transpose()
+}
+
+impl CloudHypervisorInstance {
+    // VMM level options (`--api-socket`, `--event-monitor`, `--log-file`, `--seccomp`,
+    // `--restore`, `-v`) have no place in a `VmConfig` and are left out.
+    pub fn to_vm_config(&self) -> Result<VmConfig, Error> {
+        let mut memory = match &self.memory {
+            Some(memory) => MemoryConfig::try_from(memory)?,
+            None => MemoryConfig {
+                size: DEFAULT_MEMORY_SIZE,
+                ..MemoryConfig::default()
+            },
+        };
+        memory.zones = convert_one(&self.memory_zone)?.map(|zone| vec![zone]);
+
+        let payload = PayloadConfig {
+            firmware: self.firmware.clone(),
+            kernel: self.kernel.clone(),
+            cmdline: self.cmdline.clone(),
+            initramfs: self.initramfs.clone(),
+            other: BTreeMap::new(),
+        };
+
+        Ok(VmConfig {
+            cpus: match &self.cpus {
+                Some(cpus) => CpusConfig::try_from(cpus)?,
+                None => CpusConfig::try_from(&Cpus::default())?,
+            },
+            memory,
+            payload: if payload == PayloadConfig::default() {
+                None
+            } else {
+                Some(payload)
+            },
+            rate_limit_groups: convert_all(&self.rate_limit_group)?,
+            disks: convert_all(&self.disk)?,
+            net: convert_all(&self.net)?,
+            rng: convert_one(&self.rng)?,
+            balloon: convert_one(&self.balloon)?,
+            fs: convert_all(&self.fs)?,
+            pmem: convert_all(&self.pmem)?,
+            serial: convert_one(&self.serial)?,
+            console: convert_one(&self.console)?,
+            debug_console: convert_one(&self.debug_console)?,
+            devices: convert_all(&self.device)?,
+            user_devices: convert_all(&self.user_device)?,
+            vdpa: convert_all(&self.vdpa)?,
+            vsock: convert_one(&self.vsock)?,
+            pvpanic: self.pvpanic,
+            sgx_epc: convert_all(&self.sgx_epc)?,
+            numa: convert_all(&self.numa)?,
+            watchdog: self.watchdog,
+            platform: convert_one(&self.platform)?,
+            tpm: self.tpm.as_ref().map(|socket| TpmConfig {
+                socket: socket.clone(),
+                other: BTreeMap::new(),
+            }),
+            landlock_enable: self.landlock,
+            landlock_rules: convert_all(&self.landlock_rules)?,
+            other: BTreeMap::new(),
+        })
+    }
+}
+
+impl VmConfig {
+    // Reads a `VmConfig` from a JSON file, or from the `config.json` of a snapshot directory.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<VmConfig> {
+        let path = path.as_ref();
+        let path = if path.is_dir() {
+            path.join(SNAPSHOT_CONFIG_FILE)
+        } else {
+            path.to_path_buf()
+        };
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+}
+
+const SNAPSHOT_CONFIG_FILE: &str = "config.json";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportedVmConfig {
+    pub instance: CloudHypervisorInstance,
+    // Fields that could not be carried over, as paths into the JSON such as `disks[0].serial`.
+    pub unrepresentable: Vec<String>,
+}
+
+#[derive(Default)]
+struct Importer {
+    unrepresentable: Vec<String>,
+}
+
+impl Importer {
+    fn record_dropped(&mut self, path: impl Display) {
+        self.unrepresentable.push(path.to_string());
+    }
+
+    fn other(&mut self, path: &str, other: &BTreeMap<String, Value>) {
+        for key in other.keys() {
+            if path.is_empty() {
+                self.record_dropped(key);
+            } else {
+                self.record_dropped(format!("{}.{}", path, key));
+            }
+        }
+    }
+
+    fn int<T: TryFrom<u64>>(&mut self, path: impl Display, value: Option<u64>) -> Option<T> {
+        let converted = value.map(T::try_from)?;
+        if converted.is_err() {
+            self.record_dropped(path);
+        }
+        converted.ok()
+    }
+
+    // The crate models rate limiters as flat `bw_*` and `ops_*` keys, the same ones a
+    // `RateLimitGroup` has.
+    fn rate_limiter(&mut self, path: &str, config: &Option<RateLimiterConfig>) -> RateLimitGroup {
+        let config = config.clone().unwrap_or_default();
+        let bandwidth = config.bandwidth.as_ref();
+        let ops = config.ops.as_ref();
+        RateLimitGroup {
+            bw_size: bandwidth.map(|bucket| ByteSize(bucket.size)),
+            bw_one_time_burst: bandwidth.and_then(|bucket| bucket.one_time_burst.map(ByteSize)),
+            bw_refill_time: self.int(
+                format!("{}.bandwidth.refill_time", path),
+                bandwidth.map(|bucket| bucket.refill_time),
+            ),
+            ops_size: self.int(format!("{}.ops.size", path), ops.map(|bucket| bucket.size)),
+            ops_one_time_burst: self.int(
+                format!("{}.ops.one_time_burst", path),
+                ops.and_then(|bucket| bucket.one_time_burst),
+            ),
+            ops_refill_time: self.int(
+                format!("{}.ops.refill_time", path),
+                ops.map(|bucket| bucket.refill_time),
+            ),
+            id: None,
+        }
+    }
+}
+
+fn on_off(value: Option<bool>) -> Option<OnOff> {
+    value.map(|value| if value { OnOff::On } else { OnOff::Off })
+}
+
+fn byte_size(value: Option<u64>) -> Option<ByteSize> {
+    value.map(ByteSize)
+}
+
+fn to_string(value: Option<impl Display>) -> Option<String> {
+    value.map(|value| value.to_string())
+}
+
+fn queue_affinity(affinity: &[VirtQueueAffinity]) -> String {
+    let entries = affinity
+        .iter()
+        .map(|entry| {
+            let host_cpus = entry
+                .host_cpus
+                .iter()
+                .map(|cpu| cpu.to_string())
+                .collect::<Vec<String>>();
+            format!("{}@[{}]", entry.queue_index, host_cpus.join(","))
+        })
+        .collect::<Vec<String>>();
+    format!("[{}]", entries.join(","))
+}
+
+impl Importer {
+    fn cpus(&mut self, config: &CpusConfig) -> Cpus {
+        self.other("cpus", &config.other);
+        if let Some(features) = &config.features {
+            self.other("cpus.features", &features.other);
+        }
+        Cpus {
+            boot: Some(config.boot_vcpus),
+            max: Some(config.max_vcpus),
+            topology: config.topology.clone(),
+            kvm_hyperv: on_off(config.kvm_hyperv),
+            max_phys_bits: config.max_phys_bits,
+            affinity: config.affinity.clone(),
+            features: config
+                .features
+                .as_ref()
+                .map(|features| CpuFeatures { amx: features.amx }),
+        }
+    }
+
+    fn memory(&mut self, config: &MemoryConfig) -> Memory {
+        self.other("memory", &config.other);
+        Memory {
+            size: Some(ByteSize(config.size)),
+            mergeable: on_off(config.mergeable),
+            shared: on_off(config.shared),
+            hugepages: on_off(config.hugepages),
+            hugepage_size: byte_size(config.hugepage_size),
+            hotplug_method: config.hotplug_method.clone(),
+            hotplug_size: byte_size(config.hotplug_size),
+            hotplugged_size: byte_size(config.hotplugged_size),
+            prefault: on_off(config.prefault),
+            thp: on_off(config.thp),
+        }
+    }
+
+    fn memory_zone(&mut self, path: &str, config: &MemoryZoneConfig) -> MemoryZone {
+        self.other(path, &config.other);
+        MemoryZone {
+            size: Some(ByteSize(config.size)),
+            file: config.file.clone(),
+            shared: on_off(config.shared),
+            hugepages: on_off(config.hugepages),
+            hugepage_size: byte_size(config.hugepage_size),
+            host_numa_node: self.int(
+                format!("{}.host_numa_node", path),
+                config.host_numa_node.map(u64::from),
+            ),
+            id: Some(config.id.clone()),
+            hotplug_size: byte_size(config.hotplug_size),
+            hotplugged_size: byte_size(config.hotplugged_size),
+            prefault: on_off(config.prefault),
+        }
+    }
+
+    fn rate_limit_group(&mut self, path: &str, config: &RateLimiterGroupConfig) -> RateLimitGroup {
+        self.other(path, &config.other);
+        let limiter = self.rate_limiter(
+            &format!("{}.rate_limiter_config", path),
+            &Some(config.rate_limiter_config.clone()),
+        );
+        RateLimitGroup {
+            id: Some(config.id.clone()),
+            ..limiter
+        }
+    }
+
+    fn disk(&mut self, path: &str, config: &DiskConfig) -> Disk {
+        self.other(path, &config.other);
+        let limiter = self.rate_limiter(
+            &format!("{}.rate_limiter_config", path),
+            &config.rate_limiter_config,
+        );
+        Disk {
+            path: config.path.clone(),
+            readonly: on_off(config.readonly),
+            direct: on_off(config.direct),
+            iommu: on_off(config.iommu),
+            num_queues: config.num_queues,
+            queue_size: config.queue_size.map(usize::from),
+            vhost_user: on_off(config.vhost_user),
+            socket: config.vhost_socket.clone(),
+            bw_size: limiter.bw_size,
+            bw_one_time_burst: limiter.bw_one_time_burst,
+            bw_refill_time: limiter.bw_refill_time,
+            ops_size: limiter.ops_size,
+            ops_one_time_burst: limiter.ops_one_time_burst,
+            ops_refill_time: limiter.ops_refill_time,
+            id: config.id.clone(),
+            pci_segment: to_string(config.pci_segment),
+            rate_limit_group: config.rate_limit_group.clone(),
+            queue_affinity: config.queue_affinity.as_deref().map(queue_affinity),
+        }
+    }
+
+    fn net(&mut self, path: &str, config: &NetConfig) -> Net {
+        self.other(path, &config.other);
+        let limiter = self.rate_limiter(
+            &format!("{}.rate_limiter_config", path),
+            &config.rate_limiter_config,
+        );
+        let mask = config.mask.and_then(|mask| {
+            let prefix = prefix_len(mask);
+            if prefix.is_none() {
+                self.record_dropped(format!("{}.mask", path));
+            }
+            prefix
+        });
+        let fd = config.fds.as_ref().map(|fds| {
+            fds.iter()
+                .enumerate()
+                .filter_map(|(i, fd)| {
+                    let fd = usize::try_from(*fd).ok();
+                    if fd.is_none() {
+                        self.record_dropped(format!("{}.fds[{}]", path, i));
+                    }
+                    fd
+                })
+                .collect()
+        });
+        Net {
+            tap: config.tap.clone(),
+            ip: config.ip,
+            mask,
+            mac: config.mac.clone(),
+            fd,
+            iommu: on_off(config.iommu),
+            num_queues: config.num_queues,
+            queue_size: config.queue_size.map(usize::from),
+            id: config.id.clone(),
+            vhost_user: on_off(config.vhost_user),
+            socket: config.vhost_socket.clone(),
+            vhost_mode: config.vhost_mode.clone(),
+            bw_size: limiter.bw_size,
+            bw_one_time_burst: limiter.bw_one_time_burst,
+            bw_refill_time: limiter.bw_refill_time,
+            ops_size: limiter.ops_size,
+            ops_one_time_burst: limiter.ops_one_time_burst,
+            ops_refill_time: limiter.ops_refill_time,
+            pci_segment: to_string(config.pci_segment),
+            offload_tso: on_off(config.offload_tso),
+            offload_ufo: on_off(config.offload_ufo),
+            offload_csum: on_off(config.offload_csum),
+        }
+    }
+
+    // `--rng` takes either a source or the iommu flag; a non default source wins.
+    fn rng(&mut self, config: &RngConfig) -> Rng {
+        self.other("rng", &config.other);
+        match config.iommu {
+            Some(iommu) if config.src == Path::new(DEFAULT_RNG_SOURCE) => {
+                Rng::Iommu(if iommu { OnOff::On } else { OnOff::Off })
+            }
+            iommu => {
+                if iommu.is_some() {
+                    self.record_dropped("rng.iommu");
+                }
+                Rng::Src(config.src.clone())
+            }
+        }
+    }
+
+    fn balloon(&mut self, config: &BalloonConfig) -> Balloon {
+        self.other("balloon", &config.other);
+        Balloon {
+            size: Some(ByteSize(config.size)),
+            deflate_on_oom: on_off(config.deflate_on_oom),
+            free_page_reporting: on_off(config.free_page_reporting),
+        }
+    }
+
+    fn fs(&mut self, path: &str, config: &FsConfig) -> Fs {
+        self.other(path, &config.other);
+        Fs {
+            tag: Some(config.tag.clone()),
+            socket: Some(config.socket.clone()),
+            num_queues: config.num_queues,
+            queue_size: config.queue_size.map(usize::from),
+            id: config.id.clone(),
+            pci_segment: to_string(config.pci_segment),
+        }
+    }
+
+    fn pmem(&mut self, path: &str, config: &PmemConfig) -> Pmem {
+        self.other(path, &config.other);
+        Pmem {
+            file: Some(config.file.clone()),
+            size: self.int(format!("{}.size", path), config.size),
+            iommu: on_off(config.iommu),
+            discard_writes: on_off(config.discard_writes),
+            id: config.id.clone(),
+            pci_segment: to_string(config.pci_segment),
+        }
+    }
+
+    fn serial(&mut self, config: &ConsoleConfig) -> Option<Serial> {
+        self.other("serial", &config.other);
+        if config.iommu.is_some() {
+            self.record_dropped("serial.iommu");
+        }
+        let serial = match (config.mode, &config.file, &config.socket) {
+            (ConsoleOutputMode::Off, _, _) => Serial::Off,
+            (ConsoleOutputMode::Null, _, _) => Serial::Null,
+            (ConsoleOutputMode::Pty, _, _) => Serial::Pty,
+            (ConsoleOutputMode::Tty, _, _) => Serial::Tty,
+            (ConsoleOutputMode::File, Some(file), _) => Serial::File(file.clone()),
+            (ConsoleOutputMode::Socket, _, Some(socket)) => Serial::Socket(socket.clone()),
+            _ => {
+                self.record_dropped("serial.mode");
+                return None;
+            }
+        };
+        Some(serial)
+    }
+
+    // `--console` takes either an output mode or the iommu flag; iommu is only kept on the
+    // default tty output, which is what `--console iommu=on` selects.
+    fn console(&mut self, config: &ConsoleConfig) -> Option<Console> {
+        self.other("console", &config.other);
+        if config.socket.is_some() {
+            self.record_dropped("console.socket");
+        }
+        let console = match (config.mode, &config.file, config.iommu) {
+            (ConsoleOutputMode::Tty, _, Some(iommu)) => {
+                Console::Iommu(if iommu { OnOff::On } else { OnOff::Off })
+            }
+            (_, _, Some(_)) => {
+                self.record_dropped("console.iommu");
+                return self.console(&ConsoleConfig {
+                    iommu: None,
+                    socket: None,
+                    other: BTreeMap::new(),
+                    ..config.clone()
+                });
+            }
+            (ConsoleOutputMode::Off, _, None) => Console::Off,
+            (ConsoleOutputMode::Null, _, None) => Console::Null,
+            (ConsoleOutputMode::Pty, _, None) => Console::Pty,
+            (ConsoleOutputMode::Tty, _, None) => Console::Tty,
+            (ConsoleOutputMode::File, Some(file), None) => Console::File(file.clone()),
+            _ => {
+                self.record_dropped("console.mode");
+                return None;
+            }
+        };
+        Some(console)
+    }
+
+    fn debug_console(&mut self, config: &DebugConsoleConfig) -> DebugConsole {
+        self.other("debug_console", &config.other);
+        let console_type = match (config.mode, &config.file) {
+            (ConsoleOutputMode::Off, _) => Some(DebugConsoleType::Off),
+            (ConsoleOutputMode::Pty, _) => Some(DebugConsoleType::Pty),
+            (ConsoleOutputMode::Tty, _) => Some(DebugConsoleType::Tty),
+            (ConsoleOutputMode::File, Some(file)) => Some(DebugConsoleType::File(file.clone())),
+            _ => {
+                self.record_dropped("debug_console.mode");
+                None
+            }
+        };
+        DebugConsole {
+            console_type,
+            iobase: config.iobase.map(|iobase| format!("{:#x}", iobase)),
+        }
+    }
+
+    fn device(&mut self, path: &str, config: &DeviceConfig) -> Device {
+        self.other(path, &config.other);
+        Device {
+            path: Some(config.path.clone()),
+            iommu: on_off(config.iommu),
+            id: config.id.clone(),
+            pci_segment: to_string(config.pci_segment),
+        }
+    }
+
+    fn user_device(&mut self, path: &str, config: &UserDeviceConfig) -> UserDevice {
+        self.other(path, &config.other);
+        UserDevice {
+            socket: Some(config.socket.clone()),
+            id: config.id.clone(),
+            pci_segment: to_string(config.pci_segment),
+        }
+    }
+
+    fn vdpa(&mut self, path: &str, config: &VdpaConfig) -> Vdpa {
+        self.other(path, &config.other);
+        Vdpa {
+            path: Some(config.path.clone()),
+            num_queues: config.num_queues,
+            iommu: on_off(config.iommu),
+            id: config.id.clone(),
+            pci_segment: to_string(config.pci_segment),
+        }
+    }
+
+    fn vsock(&mut self, config: &VsockConfig) -> Vsock {
+        self.other("vsock", &config.other);
+        Vsock {
+            cid: Some(config.cid.to_string()),
+            socket: Some(config.socket.clone()),
+            iommu: on_off(config.iommu),
+            id: config.id.clone(),
+            pci_segment: to_string(config.pci_segment),
+        }
+    }
+
+    fn sgx_epc(&mut self, path: &str, config: &SgxEpcConfig) -> SgxEpc {
+        self.other(path, &config.other);
+        SgxEpc {
+            id: Some(config.id.clone()),
+            size: Some(config.size.to_string()),
+            prefault: on_off(config.prefault),
+        }
+    }
+
+    fn numa(&mut self, path: &str, config: &NumaConfig) -> Numa {
+        self.other(path, &config.other);
+        Numa {
+            guest_numa_id: Some(config.guest_numa_id.to_string()),
+            cpus: config
+                .cpus
+                .as_ref()
+                .map(|cpus| cpus.iter().map(|cpu| usize::from(*cpu)).collect()),
+            distances: config.distances.clone(),
+            memory_zones: config.memory_zones.clone(),
+            sgx_epc_sections: config.sgx_epc_sections.clone(),
+            pci_segments: config
+                .pci_segments
+                .as_ref()
+                .map(|segments| segments.iter().map(|s| s.to_string()).collect()),
+        }
+    }
+
+    fn platform(&mut self, config: &PlatformConfig) -> Platform {
+        self.other("platform", &config.other);
+        let iommu_segments = match config.iommu_segments.as_deref() {
+            None | Some([]) => None,
+            Some([segment]) => self.int("platform.iommu_segments", Some(u64::from(*segment))),
+            Some(_) => {
+                self.record_dropped("platform.iommu_segments");
+                None
+            }
+        };
+        Platform {
+            num_pci_segments: self.int(
+                "platform.num_pci_segments",
+                config.num_pci_segments.map(u64::from),
+            ),
+            iommu_segments,
+            serial_number: config.serial_number.clone(),
+            uuid: config.uuid.clone(),
+            oem_strings: config.oem_strings.clone(),
+        }
+    }
+
+    fn landlock_rule(&mut self, path: &str, config: &LandlockConfig) -> LandlockRule {
+        self.other(path, &config.other);
+        LandlockRule {
+            path: Some(config.path.clone()),
+            access: Some(config.access.clone()),
+        }
+    }
+
+    fn all<C, T>(
+        &mut self,
+        name: &str,
+        configs: &Option<Vec<C>>,
+        import: impl Fn(&mut Self, &str, &C) -> T,
+    ) -> Option<Vec<T>> {
+        configs.as_ref().map(|configs| {
+            configs
+                .iter()
+                .enumerate()
+                .map(|(i, config)| import(self, &format!("{}[{}]", name, i), config))
+                .collect()
+        })
+    }
+}
+
+impl CloudHypervisorInstance {
+    // The reverse of `to_vm_config()`. Everything the crate cannot model is reported in
+    // `ImportedVmConfig::unrepresentable` instead of failing the import.
+    pub fn from_vm_config(bin_path: PathBuf, config: &VmConfig) -> ImportedVmConfig {
+        let mut importer = Importer::default();
+        let mut instance = CloudHypervisorInstance::new(bin_path);
+        importer.other("", &config.other);
+
+        instance.cpus = Some(importer.cpus(&config.cpus));
+        instance.memory = Some(importer.memory(&config.memory));
+        // Only a single `--memory-zone` is modelled.
+        if let Some(zones) = &config.memory.zones {
+            let mut zones = zones.iter().enumerate();
+            if let Some((i, zone)) = zones.next() {
+                instance.memory_zone =
+                    Some(importer.memory_zone(&format!("memory.zones[{}]", i), zone));
+            }
+            for (i, _) in zones {
+                importer.record_dropped(format!("memory.zones[{}]", i));
+            }
+        }
+
+        if let Some(payload) = &config.payload {
+            importer.other("payload", &payload.other);
+            instance.firmware = payload.firmware.clone();
+            instance.kernel = payload.kernel.clone();
+            instance.cmdline = payload.cmdline.clone();
+            instance.initramfs = payload.initramfs.clone();
+        }
+
+        instance.rate_limit_group = importer.all(
+            "rate_limit_groups",
+            &config.rate_limit_groups,
+            Importer::rate_limit_group,
+        );
+        instance.disk = importer.all("disks", &config.disks, Importer::disk);
+        instance.net = importer.all("net", &config.net, Importer::net);
+        instance.rng = config.rng.as_ref().map(|rng| importer.rng(rng));
+        instance.balloon = config
+            .balloon
+            .as_ref()
+            .map(|balloon| importer.balloon(balloon));
+        instance.fs = importer.all("fs", &config.fs, Importer::fs);
+        instance.pmem = importer.all("pmem", &config.pmem, Importer::pmem);
+        instance.serial = config
+            .serial
+            .as_ref()
+            .and_then(|serial| importer.serial(serial));
+        instance.console = config
+            .console
+            .as_ref()
+            .and_then(|console| importer.console(console));
+        instance.debug_console = config
+            .debug_console
+            .as_ref()
+            .map(|debug_console| importer.debug_console(debug_console));
+        instance.device = importer.all("devices", &config.devices, Importer::device);
+        instance.user_device =
+            importer.all("user_devices", &config.user_devices, Importer::user_device);
+        instance.vdpa = importer.all("vdpa", &config.vdpa, Importer::vdpa);
+        instance.vsock = config.vsock.as_ref().map(|vsock| importer.vsock(vsock));
+        instance.pvpanic = config.pvpanic;
+        instance.sgx_epc = importer.all("sgx_epc", &config.sgx_epc, Importer::sgx_epc);
+        instance.numa = importer.all("numa", &config.numa, Importer::numa);
+        instance.watchdog = config.watchdog;
+        instance.platform = config
+            .platform
+            .as_ref()
+            .map(|platform| importer.platform(platform));
+        if let Some(tpm) = &config.tpm {
+            importer.other("tpm", &tpm.other);
+            instance.tpm = Some(tpm.socket.clone());
+        }
+        instance.landlock = config.landlock_enable;
+        instance.landlock_rules = importer.all(
+            "landlock_rules",
+            &config.landlock_rules,
+            Importer::landlock_rule,
+        );
+
+        ImportedVmConfig {
+            instance,
+            unrepresentable: importer.unrepresentable,
+        }
+    }
+}
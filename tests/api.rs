@@ -0,0 +1,315 @@
+mod common;
+
+use std::fs;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+
+use bytesize::ByteSize;
+use serde_json::json;
+
+use cloud_hypervisor_command_builder::api::{
+    ApiClient, ApiError, PciDeviceInfo, VmState, VmmPingResponse,
+};
+use cloud_hypervisor_command_builder::error::Error;
+use cloud_hypervisor_command_builder::resize::VmResizeBuilder;
+use cloud_hypervisor_command_builder::snapshot::{AfterSnapshot, Snapshot, SnapshotError};
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, CpusBuilder, DiskBuilder, NetBuilder, OnOff,
+    PathOrFileDescriptorOption, Vsock,
+};
+
+use common::{read_request, respond, tmp};
+
+// Serves one canned response per connection and returns the requests it received.
+fn fake_api(
+    name: &str,
+    responses: Vec<(&'static str, &'static str)>,
+) -> (PathBuf, JoinHandle<Vec<String>>) {
+    let socket = tmp(&format!("{}.sock", name));
+    let listener = UnixListener::bind(&socket).unwrap();
+
+    let server = thread::spawn(move || {
+        responses
+            .into_iter()
+            .map(|(status, body)| {
+                let (stream, _) = listener.accept().unwrap();
+                let request = read_request(&stream).unwrap();
+                respond(&stream, status, body);
+                request.to_string()
+            })
+            .collect()
+    });
+
+    (socket, server)
+}
+
+#[test]
+fn typed_responses() {
+    let (socket, server) = fake_api(
+        "typed",
+        vec![
+            (
+                "200 OK",
+                r#"{"build_version":"v40.0.0","version":"40.0.0","pid":42,"features":["kvm"]}"#,
+            ),
+            (
+                "200 OK",
+                r#"{"config":{"cpus":{"boot_vcpus":1}},"state":"Running","memory_actual_size":536870912}"#,
+            ),
+            ("200 OK", r#"{"_disk0":{"read_bytes":512,"write_ops":3}}"#),
+        ],
+    );
+    let api = ApiClient::from_path(&socket);
+
+    assert_eq!(
+        api.vmm_ping().unwrap(),
+        VmmPingResponse {
+            build_version: "v40.0.0".to_string(),
+            version: "40.0.0".to_string(),
+            pid: Some(42),
+            features: Some(vec!["kvm".to_string()]),
+        }
+    );
+
+    let info = api.vm_info().unwrap();
+    assert_eq!(info.state, VmState::Running);
+    assert_eq!(info.memory_actual_size, Some(536870912));
+    assert_eq!(info.config.cpus.boot_vcpus, 1);
+
+    let counters = api.vm_counters().unwrap();
+    assert_eq!(counters["_disk0"]["write_ops"], 3);
+
+    assert_eq!(
+        server.join().unwrap(),
+        vec![
+            "GET /api/v1/vmm.ping HTTP/1.1 ",
+            "GET /api/v1/vm.info HTTP/1.1 ",
+            "GET /api/v1/vm.counters HTTP/1.1 ",
+        ]
+    );
+}
+
+#[test]
+fn actions() {
+    let (socket, server) = fake_api("actions", vec![("204 No Content", ""); 9]);
+    let api = ApiClient::from_path(&socket);
+
+    api.vm_create(&json!({"payload": {"kernel": "/vmlinux"}}))
+        .unwrap();
+    api.vm_boot().unwrap();
+    api.vm_pause().unwrap();
+    api.vm_resume().unwrap();
+    api.vm_reboot().unwrap();
+    api.vm_power_button().unwrap();
+    api.vm_shutdown().unwrap();
+    api.vm_delete().unwrap();
+    api.vmm_shutdown().unwrap();
+
+    assert_eq!(
+        server.join().unwrap(),
+        vec![
+            r#"PUT /api/v1/vm.create HTTP/1.1 {"payload":{"kernel":"/vmlinux"}}"#,
+            "PUT /api/v1/vm.boot HTTP/1.1 ",
+            "PUT /api/v1/vm.pause HTTP/1.1 ",
+            "PUT /api/v1/vm.resume HTTP/1.1 ",
+            "PUT /api/v1/vm.reboot HTTP/1.1 ",
+            "PUT /api/v1/vm.power-button HTTP/1.1 ",
+            "PUT /api/v1/vm.shutdown HTTP/1.1 ",
+            "PUT /api/v1/vm.delete HTTP/1.1 ",
+            "PUT /api/v1/vmm.shutdown HTTP/1.1 ",
+        ]
+    );
+}
+
+#[test]
+fn hotplug() {
+    let (socket, server) = fake_api(
+        "hotplug",
+        vec![
+            ("200 OK", r#"{"id":"_disk1","bdf":"0000:00:06.0"}"#),
+            ("200 OK", r#"{"id":"net1","bdf":"0000:00:07.0"}"#),
+            ("204 No Content", ""),
+        ],
+    );
+    let api = ApiClient::from_path(&socket);
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+
+    let disk = DiskBuilder::default()
+        .path(PathBuf::from("/data.raw"))
+        .readonly(OnOff::On)
+        .build()
+        .unwrap();
+    assert_eq!(
+        ch.hotplug_disk(&api, disk.clone()).unwrap(),
+        PciDeviceInfo {
+            id: "_disk1".to_string(),
+            bdf: "0000:00:06.0".to_string(),
+        }
+    );
+    let net = NetBuilder::default()
+        .tap("tap1")
+        .id("net1")
+        .build()
+        .unwrap();
+    api.vm_add_net(&net).unwrap();
+
+    let mut expected = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    expected.disk(
+        DiskBuilder::default()
+            .path(PathBuf::from("/data.raw"))
+            .readonly(OnOff::On)
+            .id("_disk1")
+            .build()
+            .unwrap(),
+    );
+    assert_eq!(ch, expected);
+
+    assert!(ch.unplug(&api, "_disk1").unwrap());
+    assert_eq!(
+        ch,
+        CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"))
+    );
+
+    assert!(matches!(
+        api.vm_add_vsock(&Vsock::default()),
+        Err(ApiError::Config(_))
+    ));
+
+    assert_eq!(
+        server.join().unwrap(),
+        vec![
+            r#"PUT /api/v1/vm.add-disk HTTP/1.1 {"path":"/data.raw","readonly":true}"#,
+            r#"PUT /api/v1/vm.add-net HTTP/1.1 {"tap":"tap1","id":"net1"}"#,
+            r#"PUT /api/v1/vm.remove-device HTTP/1.1 {"id":"_disk1"}"#,
+        ]
+    );
+}
+
+#[test]
+fn resize() {
+    let (socket, server) = fake_api("resize", vec![("204 No Content", ""); 2]);
+    let api = ApiClient::from_path(&socket);
+
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.cpus(CpusBuilder::default().boot(1).max(4).build().unwrap());
+    ch.resize(
+        &api,
+        &VmResizeBuilder::default().desired_vcpus(4).build().unwrap(),
+    )
+    .unwrap();
+    assert!(matches!(
+        ch.resize(
+            &api,
+            &VmResizeBuilder::default().desired_vcpus(5).build().unwrap()
+        ),
+        Err(ApiError::Config(Error::Resize(_)))
+    ));
+    api.vm_resize_zone("mem0", ByteSize::gib(2)).unwrap();
+
+    assert_eq!(
+        server.join().unwrap(),
+        vec![
+            r#"PUT /api/v1/vm.resize HTTP/1.1 {"desired_vcpus":4}"#,
+            r#"PUT /api/v1/vm.resize-zone HTTP/1.1 {"id":"mem0","desired_ram":2147483648}"#,
+        ]
+    );
+}
+
+#[test]
+fn snapshot() {
+    let (socket, server) = fake_api(
+        "snapshot",
+        vec![
+            ("204 No Content", ""),
+            ("204 No Content", ""),
+            ("204 No Content", ""),
+            ("204 No Content", ""),
+            ("204 No Content", ""),
+            ("500 Internal Server Error", "snapshot failed"),
+            ("204 No Content", ""),
+        ],
+    );
+    let api = ApiClient::from_path(&socket);
+
+    // the fake api does not write anything, so the snapshot is put in place beforehand
+    let dir = tmp("snapshot");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for file in ["config.json", "state.json", "memory-ranges"] {
+        fs::write(dir.join(file), "{}").unwrap();
+    }
+
+    let snapshot = Snapshot::take(&api, &dir, AfterSnapshot::Shutdown).unwrap();
+    assert_eq!(snapshot.dir(), dir);
+    api.vm_restore(&snapshot.source_url(), true).unwrap();
+    assert!(matches!(
+        Snapshot::take(&api, &dir, AfterSnapshot::KeepPaused),
+        Err(SnapshotError::Api(ApiError::Http { status: 500, .. }))
+    ));
+
+    let url = format!("file://{}", dir.display());
+    assert_eq!(
+        server.join().unwrap(),
+        vec![
+            "PUT /api/v1/vm.pause HTTP/1.1 ".to_string(),
+            format!(
+                r#"PUT /api/v1/vm.snapshot HTTP/1.1 {{"destination_url":"{}"}}"#,
+                url
+            ),
+            "PUT /api/v1/vm.shutdown HTTP/1.1 ".to_string(),
+            format!(
+                r#"PUT /api/v1/vm.restore HTTP/1.1 {{"source_url":"{}","prefault":true}}"#,
+                url
+            ),
+            "PUT /api/v1/vm.pause HTTP/1.1 ".to_string(),
+            format!(
+                r#"PUT /api/v1/vm.snapshot HTTP/1.1 {{"destination_url":"{}"}}"#,
+                url
+            ),
+            "PUT /api/v1/vm.resume HTTP/1.1 ".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn errors() {
+    let (socket, server) = fake_api(
+        "errors",
+        vec![
+            ("500 Internal Server Error", "VM is not booted"),
+            ("200 OK", "not json"),
+        ],
+    );
+    let api = ApiClient::from_path(&socket);
+
+    let err = api.vm_pause().unwrap_err();
+    assert!(matches!(err, ApiError::Http { status: 500, .. }));
+    assert_eq!(
+        err.to_string(),
+        "api request failed: 500 Internal Server Error: VM is not booted"
+    );
+    assert!(matches!(api.vm_info(), Err(ApiError::Json(_))));
+    server.join().unwrap();
+
+    assert!(matches!(api.vmm_ping(), Err(ApiError::Io(_))));
+}
+
+#[test]
+fn connects_through_fd() {
+    let socket = tmp("fd.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+
+    let api = ApiClient::new(&PathOrFileDescriptorOption::Fd(
+        listener.as_raw_fd() as usize
+    ))
+    .unwrap();
+    assert_eq!(api.socket(), socket);
+
+    let file = fs::File::open("/dev/null").unwrap();
+    assert!(matches!(
+        ApiClient::new(&PathOrFileDescriptorOption::Fd(file.as_raw_fd() as usize)),
+        Err(ApiError::UnresolvedSocket(_))
+    ));
+}
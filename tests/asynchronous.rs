@@ -0,0 +1,209 @@
+mod common;
+
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use cloud_hypervisor_command_builder::api::{ApiError, VmState};
+use cloud_hypervisor_command_builder::asynchronous::AsyncApiClient;
+use cloud_hypervisor_command_builder::mock::MockServer;
+use cloud_hypervisor_command_builder::process::{ExitReason, InheritedFds};
+use cloud_hypervisor_command_builder::ready::{ReadyCondition, ReadyError, ReadyStage};
+use cloud_hypervisor_command_builder::resize::VmResizeBuilder;
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, CpusBuilder, DiskBuilder, PathOrFileDescriptorOption,
+};
+
+use common::{script, tmp};
+
+#[tokio::test]
+async fn api() {
+    let server = MockServer::start(tmp("api.sock")).unwrap();
+    let api = AsyncApiClient::from_path(server.socket()).timeout(Duration::from_secs(5));
+
+    assert_eq!(api.vmm_ping().await.unwrap().version, "40.0.0");
+
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.cpus(CpusBuilder::default().boot(1).max(2).build().unwrap());
+    api.vm_create(&ch.to_vm_config().unwrap()).await.unwrap();
+    api.vm_boot().await.unwrap();
+    assert_eq!(api.vm_info().await.unwrap().state, VmState::Running);
+
+    let disk = api
+        .vm_add_disk(&DiskBuilder::default().path("/data.raw").build().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(disk.id, "_disk0");
+    api.vm_remove_device(&disk.id).await.unwrap();
+    api.vm_resize(&VmResizeBuilder::default().desired_vcpus(2).build().unwrap())
+        .await
+        .unwrap();
+
+    api.vm_pause().await.unwrap();
+    assert!(matches!(
+        api.vm_pause().await,
+        Err(ApiError::Http { status: 500, .. })
+    ));
+    assert_eq!(server.state(), Some(VmState::Paused));
+
+    let requests = server.requests();
+    assert_eq!(requests[2].endpoint, "vm.boot");
+    assert_eq!(requests[6].body, r#"{"desired_vcpus":2}"#);
+}
+
+#[tokio::test]
+async fn process() {
+    let server = MockServer::start(tmp("process.sock")).unwrap();
+    let bin_path = script(
+        "process",
+        "trap 'exit 0' TERM\nwhile true; do sleep 0.01; done",
+    );
+
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    ch.api_socket(PathOrFileDescriptorOption::Path(
+        server.socket().to_path_buf(),
+    ));
+    let mut process = ch.spawn_async(InheritedFds::new()).unwrap();
+    assert_eq!(process.api_socket(), Some(server.socket()));
+
+    let ping = process.wait_ready(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(ping.version, "40.0.0");
+    assert!(process.try_wait().await.unwrap().is_none());
+
+    // The mock answers vmm.shutdown but the script only stops on SIGTERM.
+    let exit = process.shutdown(Duration::from_millis(100)).await.unwrap();
+    assert_eq!(exit.reason, ExitReason::Exited);
+    assert_eq!(server.requests().last().unwrap().endpoint, "vmm.shutdown");
+}
+
+#[tokio::test]
+async fn ready_for() {
+    let server = MockServer::start(tmp("ready_for.sock")).unwrap();
+    let events = tmp("ready_for.json");
+    let bin_path = script(
+        "ready_for",
+        &format!(
+            "trap 'exit 0' TERM\nsleep 0.1\necho '{}' > {}\nwhile true; do sleep 0.01; done",
+            r#"{"timestamp":{"secs":0,"nanos":0},"source":"vm","event":"booted","properties":null}"#,
+            events.display()
+        ),
+    );
+
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    ch.api_socket(PathOrFileDescriptorOption::Path(
+        server.socket().to_path_buf(),
+    ));
+    ch.event_monitor(PathOrFileDescriptorOption::Path(events.clone()));
+    let mut process = ch.spawn_async(InheritedFds::new()).unwrap();
+    assert_eq!(process.event_monitor(), Some(events.as_path()));
+
+    let booted = ReadyCondition::Event {
+        source: "vm".to_string(),
+        event: "booted".to_string(),
+    };
+    process
+        .wait_ready_for(Duration::from_secs(5), &booted)
+        .await
+        .unwrap();
+
+    // The mock has no VM until one is created and booted.
+    let api = process.api_client().unwrap();
+    match process
+        .wait_ready_for(Duration::from_millis(100), &ReadyCondition::VmRunning)
+        .await
+    {
+        Err(ReadyError::Timeout { waiting_for, .. }) => {
+            assert_eq!(waiting_for, ReadyStage::VmRunning)
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    let mut vm = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    vm.cpus(CpusBuilder::default().boot(1).build().unwrap());
+    api.vm_create(&vm.to_vm_config().unwrap()).await.unwrap();
+    api.vm_boot().await.unwrap();
+    process
+        .wait_ready_for(Duration::from_secs(5), &ReadyCondition::VmRunning)
+        .await
+        .unwrap();
+
+    process.kill().unwrap();
+    process.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn event_fifo() {
+    let server = MockServer::start(tmp("event_fifo.sock")).unwrap();
+    let events = tmp("event_fifo.fifo");
+    let path = CString::new(events.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o600) }, 0);
+    // Opening the fifo for writing waits for a reader.
+    let bin_path = script(
+        "event_fifo",
+        &format!(
+            "trap 'exit 0' TERM\nsleep 0.3\necho '{}' > {}\nwhile true; do sleep 0.01; done",
+            r#"{"timestamp":{"secs":0,"nanos":0},"source":"vm","event":"booted","properties":null}"#,
+            events.display()
+        ),
+    );
+
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    ch.api_socket(PathOrFileDescriptorOption::Path(
+        server.socket().to_path_buf(),
+    ));
+    ch.event_monitor(PathOrFileDescriptorOption::Path(events));
+    let mut process = ch.spawn_async(InheritedFds::new()).unwrap();
+
+    let booted = ReadyCondition::Event {
+        source: "vm".to_string(),
+        event: "booted".to_string(),
+    };
+    // The fifo is not written to yet, which must neither stall the runtime nor the timeout.
+    match process
+        .wait_ready_for(Duration::from_millis(100), &booted)
+        .await
+    {
+        Err(ReadyError::Timeout { waiting_for, .. }) => assert_eq!(waiting_for, ReadyStage::Event),
+        other => panic!("unexpected {:?}", other),
+    }
+    process
+        .wait_ready_for(Duration::from_secs(5), &booted)
+        .await
+        .unwrap();
+
+    process.kill().unwrap();
+    process.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn not_ready() {
+    let bin_path = script("not_ready", "echo 'Error booting VM' >&2\nexit 1");
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    ch.api_socket(PathOrFileDescriptorOption::Path(tmp("not_ready.sock")));
+
+    let mut process = ch.spawn_async(InheritedFds::new()).unwrap();
+    match process.wait_ready(Duration::from_secs(5)).await {
+        Err(ReadyError::Exited(exit)) => assert_eq!(
+            exit.reason,
+            ExitReason::Failed {
+                code: 1,
+                message: Some("Error booting VM".to_string())
+            }
+        ),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let bin_path = script("silent", "while true; do sleep 0.01; done");
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    ch.api_socket(PathOrFileDescriptorOption::Path(tmp("silent.sock")));
+    let mut process = ch.spawn_async(InheritedFds::new()).unwrap();
+    match process.wait_ready(Duration::from_millis(100)).await {
+        Err(ReadyError::Timeout {
+            last_error: Some(err),
+            ..
+        }) => assert!(matches!(*err, ApiError::Io(_))),
+        other => panic!("unexpected {:?}", other),
+    }
+    process.kill().unwrap();
+    process.wait().await.unwrap();
+}
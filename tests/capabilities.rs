@@ -0,0 +1,126 @@
+mod common;
+
+use std::path::PathBuf;
+
+use cloud_hypervisor_command_builder::capabilities::{Capabilities, ProbeError};
+use cloud_hypervisor_command_builder::error::Error;
+use cloud_hypervisor_command_builder::version::ChVersion;
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, CpusBuilder, DiskBuilder, LandlockRuleBuilder,
+};
+
+use common::script;
+
+const HELP: &str = r#"Launch a cloud-hypervisor VMM.
+
+Usage: cloud-hypervisor [OPTIONS]
+
+Options:
+      --cpus <cpus>
+          boot=<boot_vcpus>,max=<max_vcpus>,kvm_hyperv=on|off [default: boot=1,max_phys_bits=46]
+      --kernel <kernel>
+          Path to kernel or firmware that supports a PVH entry point
+      --cmdline <cmdline>
+          Kernel command line
+      --disk <disk>...
+          path=<disk_image_path>,readonly=on|off,id=<device_id>
+      --serial <serial>
+          Control serial port: off|null|pty|tty|file=</path/to/a/file>|socket=</path/to/a/file> [default: null]
+  -v...
+          Sets the level of debugging output
+  -h, --help
+          Print help
+  -V, --version
+          Print version
+"#;
+
+fn fake_binary(name: &str, version: &str) -> PathBuf {
+    script(
+        name,
+        &format!(
+            "case \"$1\" in\n--version) echo \"{}\" ;;\n--help) cat <<'EOF'\n{}EOF\n;;\n*) exit 1 ;;\nesac",
+            version, HELP
+        ),
+    )
+}
+
+#[test]
+fn probe() {
+    let bin_path = fake_binary("ch-v39", "cloud-hypervisor v39.0.0-dirty");
+    let ch = CloudHypervisorInstance::new(bin_path);
+    let caps = ch.probe_capabilities().unwrap();
+
+    assert_eq!(caps.version, ChVersion::V39);
+    assert_eq!(
+        caps.options["--cpus"],
+        vec!["boot", "max", "kvm_hyperv", "max_phys_bits"]
+    );
+    assert_eq!(caps.options["--serial"], vec!["file", "socket"]);
+    assert!(caps.options["--kernel"].is_empty());
+    assert!(caps.supports("-v"));
+    assert!(caps.supports("--version"));
+    assert!(!caps.supports("--landlock"));
+    assert!(caps.takes_value.contains("--cmdline"));
+    assert!(!caps.takes_value.contains("-v"));
+}
+
+#[test]
+fn check() {
+    let bin_path = fake_binary("ch-v39-check", "cloud-hypervisor v39.0.0");
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    ch.cpus(CpusBuilder::default().boot(2).build().unwrap());
+    ch.kernel(PathBuf::from("/vmlinux"));
+    ch.cmdline("console=ttyS0".to_string());
+    ch.disk(
+        DiskBuilder::default()
+            .path(PathBuf::from("/a,b.raw"))
+            .id("disk0")
+            .build()
+            .unwrap(),
+    );
+    ch.v();
+
+    let caps = ch.probe_capabilities().unwrap();
+    assert_eq!(caps.check(&ch), Ok(()));
+
+    // A value starting with `-` is not a flag.
+    ch.cmdline("-b console=ttyS0".to_string());
+    assert_eq!(caps.check(&ch), Ok(()));
+
+    ch.disk(
+        DiskBuilder::default()
+            .path(PathBuf::from("/c.raw"))
+            .num_queues(2usize)
+            .build()
+            .unwrap(),
+    );
+    assert_eq!(
+        caps.check(&ch).unwrap_err().to_string(),
+        "--disk num_queues is not supported by cloud-hypervisor v39.0.0"
+    );
+
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.landlock_rules(LandlockRuleBuilder::default().access("r").build().unwrap());
+    assert_eq!(
+        caps.check(&ch),
+        Err(Error::Unsupported {
+            option: "--landlock-rules".to_string(),
+            key: None,
+            version: ChVersion::V39,
+            added: None,
+            removed: None,
+        })
+    );
+}
+
+#[test]
+fn probe_errors() {
+    let ch = CloudHypervisorInstance::new(PathBuf::from("/nonexistent/cloud-hypervisor"));
+    assert!(matches!(ch.probe_capabilities(), Err(ProbeError::Spawn(_))));
+
+    let bin_path = fake_binary("ch-bad-version", "cloud-hypervisor unknown");
+    assert_eq!(
+        Capabilities::probe(&bin_path).unwrap_err().to_string(),
+        "invalid cloud-hypervisor version `unknown`"
+    );
+}
@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+
+use bytesize::ByteSize;
+use cloud_hypervisor_command_builder::ch_remote::{ChRemote, ChRemoteCommand};
+use cloud_hypervisor_command_builder::error::Error;
+use cloud_hypervisor_command_builder::resize::{ResizeError, VmResize, VmResizeBuilder};
+use cloud_hypervisor_command_builder::to_command::{ToCommand, TryToCommand};
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, DiskBuilder, NetBuilder, OnOff, PathOrFileDescriptorOption, Restore,
+};
+
+fn ch_remote(command: ChRemoteCommand) -> ChRemote {
+    ChRemote::new("/ch-remote", "/run/ch.sock", command)
+}
+
+#[test]
+fn commands() {
+    assert_eq!(
+        ch_remote(ChRemoteCommand::Pause).to_single_command(),
+        "/ch-remote --api-socket /run/ch.sock pause"
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::ShutdownVmm).to_single_command(),
+        "/ch-remote --api-socket /run/ch.sock shutdown-vmm"
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::AddDisk(
+            DiskBuilder::default()
+                .path("/images/data.raw")
+                .readonly(OnOff::On)
+                .id("data")
+                .build()
+                .unwrap()
+        ))
+        .to_single_command(),
+        "/ch-remote --api-socket /run/ch.sock add-disk path=/images/data.raw,readonly=on,id=data"
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::AddNet(
+            NetBuilder::default().tap("tap1").build().unwrap()
+        ))
+        .to_single_command(),
+        "/ch-remote --api-socket /run/ch.sock add-net tap=tap1"
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::RemoveDevice("data".to_string())).to_single_command(),
+        "/ch-remote --api-socket /run/ch.sock remove-device data"
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::Resize(
+            VmResizeBuilder::default()
+                .desired_vcpus(4)
+                .desired_ram(ByteSize::gib(2))
+                .build()
+                .unwrap()
+        ))
+        .to_single_command(),
+        "/ch-remote --api-socket /run/ch.sock resize --cpus 4 --memory 2147483648"
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::ResizeZone {
+            id: "mem0".to_string(),
+            size: ByteSize::gib(1),
+        })
+        .to_single_command(),
+        "/ch-remote --api-socket /run/ch.sock resize-zone --id mem0 --size 1073741824"
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::Snapshot(
+            "file:///snapshots/vm".to_string()
+        ))
+        .to_single_command(),
+        "/ch-remote --api-socket /run/ch.sock snapshot file:///snapshots/vm"
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::Restore(Restore {
+            source_url: Some("file:///snapshots/vm".to_string()),
+            prefault: Some(OnOff::On),
+        }))
+        .to_single_command(),
+        "/ch-remote --api-socket /run/ch.sock restore source_url=file:///snapshots/vm,prefault=on"
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::SendMigration {
+            destination_url: "unix:/run/migration.sock".to_string(),
+            local: true,
+        })
+        .to_single_command(),
+        "/ch-remote --api-socket /run/ch.sock send-migration --local unix:/run/migration.sock"
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::Create(PathBuf::from("/vm.json"))).to_single_command(),
+        "/ch-remote --api-socket /run/ch.sock create /vm.json"
+    );
+}
+
+#[test]
+fn strict() {
+    assert_eq!(
+        ch_remote(ChRemoteCommand::AddDisk(Default::default())).try_to_command(),
+        Err(Error::EmptyOption {
+            option: "add-disk".to_string()
+        })
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::AddDisk(Default::default())).to_single_command(),
+        "/ch-remote --api-socket /run/ch.sock add-disk"
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::Resize(VmResize::default())).try_to_command(),
+        Err(Error::Resize(ResizeError::Empty))
+    );
+    assert_eq!(
+        ch_remote(ChRemoteCommand::AddDisk(
+            DiskBuilder::default().path("/a,b").build().unwrap()
+        ))
+        .try_to_command()
+        .unwrap()[4],
+        "path=\"/a,b\""
+    );
+    assert!(matches!(
+        ch_remote(ChRemoteCommand::AddDisk(
+            DiskBuilder::default().path("").build().unwrap()
+        ))
+        .try_to_command(),
+        Err(Error::Encode(_))
+    ));
+}
+
+#[test]
+fn from_instance() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    assert_eq!(ch.ch_remote("/ch-remote", ChRemoteCommand::Info), None);
+
+    ch.api_socket(PathOrFileDescriptorOption::Fd(3));
+    assert_eq!(ch.ch_remote("/ch-remote", ChRemoteCommand::Info), None);
+
+    ch.api_socket(PathOrFileDescriptorOption::Path(PathBuf::from(
+        "/run/ch.sock",
+    )));
+    assert_eq!(
+        ch.ch_remote("/ch-remote", ChRemoteCommand::Info)
+            .unwrap()
+            .to_command(),
+        ["/ch-remote", "--api-socket", "/run/ch.sock", "info"]
+    );
+}
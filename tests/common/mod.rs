@@ -0,0 +1,107 @@
+// Helpers shared by the integration tests; every test binary only uses some of them.
+#![allow(dead_code)]
+
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// A path in a directory of its own for each test binary, with whatever a previous run left there
+// removed.
+pub fn tmp(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(env!("CARGO_CRATE_NAME"));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+// An executable shell script standing in for cloud-hypervisor.
+pub fn script(name: &str, body: &str) -> PathBuf {
+    let path = tmp(name);
+    fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+pub struct Request {
+    // `PUT /api/v1/vm.boot HTTP/1.1`
+    pub line: String,
+    // `vm.boot`
+    pub endpoint: String,
+    pub body: String,
+}
+
+// How the fake API servers record requests.
+impl Display for Request {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.line, self.body)
+    }
+}
+
+// Reads one API request, `None` when the client hung up without sending one.
+pub fn read_request(stream: &UnixStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    if reader.read_line(&mut line).ok()? == 0 {
+        return None;
+    }
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        if header == "\r\n" {
+            break;
+        }
+        if let Some(length) = header.strip_prefix("Content-Length: ") {
+            content_length = length.trim().parse().unwrap();
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+
+    let line = line.trim_end().to_string();
+    let endpoint = line.split(' ').nth(1).unwrap_or_default();
+    Some(Request {
+        endpoint: endpoint.trim_start_matches("/api/v1/").to_string(),
+        line,
+        body: String::from_utf8(body).unwrap(),
+    })
+}
+
+pub fn respond(mut stream: &UnixStream, status: &str, body: &str) {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+    .unwrap();
+}
+
+// Answers API requests on `socket` with `handler` until the test exits and records them.
+pub fn serve(
+    socket: &Path,
+    mut handler: impl FnMut(&Request) -> (&'static str, String) + Send + 'static,
+) -> Arc<Mutex<Vec<String>>> {
+    let listener = UnixListener::bind(socket).unwrap();
+    let requests = Arc::new(Mutex::new(vec![]));
+    let recorded = requests.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let Some(request) = read_request(&stream) else {
+                continue;
+            };
+            recorded.lock().unwrap().push(request.to_string());
+            let (status, body) = handler(&request);
+            respond(&stream, status, &body);
+        }
+    });
+    requests
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use cloud_hypervisor_command_builder::api::VmCounters;
+use cloud_hypervisor_command_builder::counters::{
+    to_prometheus, BlockCounters, CounterSample, DeviceCounters, NetCounters,
+};
+
+fn vm_counters(devices: &[(&str, &[(&str, u64)])]) -> VmCounters {
+    devices
+        .iter()
+        .map(|(id, counters)| {
+            let counters = counters
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect();
+            (id.to_string(), counters)
+        })
+        .collect()
+}
+
+#[test]
+fn sample() {
+    let counters = vm_counters(&[
+        (
+            "root",
+            &[
+                ("read_bytes", 4096),
+                ("write_bytes", 512),
+                ("read_ops", 8),
+                ("write_ops", 1),
+                ("read_latency_avg", 120),
+            ],
+        ),
+        (
+            "tap0",
+            &[
+                ("rx_bytes", 1500),
+                ("rx_frames", 1),
+                ("tx_bytes", 3000),
+                ("tx_frames", 2),
+            ],
+        ),
+        ("_rng", &[("entropy_bytes", 64)]),
+    ]);
+    let sample = CounterSample::new(&counters, Instant::now());
+
+    assert_eq!(
+        sample.disk("root"),
+        Some(&BlockCounters {
+            read_bytes: 4096,
+            write_bytes: 512,
+            read_ops: 8,
+            write_ops: 1,
+        })
+    );
+    assert_eq!(
+        sample.net("tap0"),
+        Some(&NetCounters {
+            rx_bytes: 1500,
+            rx_frames: 1,
+            tx_bytes: 3000,
+            tx_frames: 2,
+        })
+    );
+    assert_eq!(sample.net("root"), None);
+    assert_eq!(
+        sample.devices["_rng"],
+        DeviceCounters::Other(BTreeMap::from([("entropy_bytes".to_string(), 64)]))
+    );
+}
+
+#[test]
+fn delta() {
+    let start = Instant::now();
+    let earlier = CounterSample::new(
+        &vm_counters(&[
+            ("root", &[("read_bytes", 4096), ("write_ops", 1)]),
+            ("tap0", &[("rx_bytes", 1500), ("tx_bytes", 3000)]),
+        ]),
+        start,
+    );
+    // tap0 was re-added in between and counts from 0 again.
+    let later = CounterSample::new(
+        &vm_counters(&[
+            ("root", &[("read_bytes", 12288), ("write_ops", 5)]),
+            ("tap0", &[("rx_bytes", 500), ("tx_bytes", 100)]),
+            ("data", &[("read_bytes", 2048)]),
+        ]),
+        start + Duration::from_secs(2),
+    );
+
+    let delta = later.since(&earlier);
+    assert_eq!(delta.elapsed, Duration::from_secs(2));
+    assert_eq!(
+        delta.disk("root"),
+        Some(&BlockCounters {
+            read_bytes: 8192,
+            write_ops: 4,
+            ..Default::default()
+        })
+    );
+    assert_eq!(delta.net("tap0").unwrap().rx_bytes, 500);
+    assert_eq!(delta.disk("data").unwrap().read_bytes, 2048);
+
+    let rates = delta.rates();
+    assert_eq!(rates["root"]["read_bytes"], 4096.0);
+    assert_eq!(rates["root"]["write_ops"], 2.0);
+    assert_eq!(rates["tap0"]["tx_bytes"], 50.0);
+
+    assert_eq!(earlier.since(&earlier).rate(100), 0.0);
+}
+
+#[test]
+fn prometheus() {
+    let now = Instant::now();
+    let web = CounterSample::new(
+        &vm_counters(&[
+            ("root", &[("read_bytes", 4096), ("write_bytes", 512)]),
+            ("tap0", &[("rx_bytes", 1500)]),
+        ]),
+        now,
+    );
+    let db = CounterSample::new(
+        &vm_counters(&[
+            ("root", &[("read_bytes", 8192)]),
+            ("_rng", &[("entropy_bytes", 64)]),
+        ]),
+        now,
+    );
+
+    assert_eq!(
+        to_prometheus([("web", &web), ("db \"1\"", &db)]),
+        r#"# TYPE cloud_hypervisor_block_read_bytes_total counter
+cloud_hypervisor_block_read_bytes_total{vm="web",device="root"} 4096
+cloud_hypervisor_block_read_bytes_total{vm="db \"1\"",device="root"} 8192
+# TYPE cloud_hypervisor_block_read_ops_total counter
+cloud_hypervisor_block_read_ops_total{vm="web",device="root"} 0
+cloud_hypervisor_block_read_ops_total{vm="db \"1\"",device="root"} 0
+# TYPE cloud_hypervisor_block_write_bytes_total counter
+cloud_hypervisor_block_write_bytes_total{vm="web",device="root"} 512
+cloud_hypervisor_block_write_bytes_total{vm="db \"1\"",device="root"} 0
+# TYPE cloud_hypervisor_block_write_ops_total counter
+cloud_hypervisor_block_write_ops_total{vm="web",device="root"} 0
+cloud_hypervisor_block_write_ops_total{vm="db \"1\"",device="root"} 0
+# TYPE cloud_hypervisor_device_counter untyped
+cloud_hypervisor_device_counter{vm="db \"1\"",device="_rng",counter="entropy_bytes"} 64
+# TYPE cloud_hypervisor_net_rx_bytes_total counter
+cloud_hypervisor_net_rx_bytes_total{vm="web",device="tap0"} 1500
+# TYPE cloud_hypervisor_net_rx_frames_total counter
+cloud_hypervisor_net_rx_frames_total{vm="web",device="tap0"} 0
+# TYPE cloud_hypervisor_net_tx_bytes_total counter
+cloud_hypervisor_net_tx_bytes_total{vm="web",device="tap0"} 0
+# TYPE cloud_hypervisor_net_tx_frames_total counter
+cloud_hypervisor_net_tx_frames_total{vm="web",device="tap0"} 0
+"#
+    );
+    assert!(web
+        .to_prometheus("web")
+        .starts_with("# TYPE cloud_hypervisor_block_read_bytes_total counter\n"));
+}
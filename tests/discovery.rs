@@ -0,0 +1,128 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use cloud_hypervisor_command_builder::discovery::{running_instances_in, DiscoveryError};
+use cloud_hypervisor_command_builder::from_command::ParseErrorKind;
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, DiskBuilder, PathOrFileDescriptorOption,
+};
+
+fn fake_process<A: AsRef<[u8]>>(proc_root: &Path, pid: &str, args: &[A]) {
+    let dir = proc_root.join(pid);
+    fs::create_dir_all(&dir).unwrap();
+    let mut cmdline = vec![];
+    for arg in args {
+        cmdline.extend_from_slice(arg.as_ref());
+        cmdline.push(0);
+    }
+    fs::write(dir.join("cmdline"), cmdline).unwrap();
+}
+
+#[test]
+fn discover() {
+    let proc_root = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("discovery-proc");
+    let _ = fs::remove_dir_all(&proc_root);
+    fs::create_dir_all(proc_root.join("self")).unwrap();
+
+    fake_process(&proc_root, "1", &["/sbin/init"]);
+    fake_process::<&str>(&proc_root, "2", &[]);
+    fake_process(
+        &proc_root,
+        "200",
+        &[
+            "/usr/bin/cloud-hypervisor",
+            "--disk",
+            "path=/vm1.raw",
+            "--api-socket",
+            "path=/run/vm1.sock",
+            "--log-file",
+            "/var/log/vm1.log",
+        ],
+    );
+    fake_process(
+        &proc_root,
+        "100",
+        &["cloud-hypervisor", "--api-socket", "fd=3"],
+    );
+    fake_process(
+        &proc_root,
+        "300",
+        &["/opt/cloud-hypervisor", "--pvmemcontrol"],
+    );
+    // Paths do not have to be UTF-8, other values do.
+    fake_process(
+        &proc_root,
+        "400",
+        &[&b"cloud-hypervisor"[..], b"--disk", b"path=/vm\xff.raw"],
+    );
+    fake_process(
+        &proc_root,
+        "500",
+        &[
+            &b"cloud-hypervisor"[..],
+            b"--disk",
+            b"path=/vm5.raw,id=disk\xff",
+        ],
+    );
+
+    let instances = running_instances_in(&proc_root).unwrap();
+    assert_eq!(instances.len(), 5);
+
+    let vm = instances[0].as_ref().unwrap();
+    assert_eq!(vm.pid, 100);
+    assert_eq!(vm.api_socket, Some(PathOrFileDescriptorOption::Fd(3)));
+    assert_eq!(vm.log_file, None);
+
+    let vm = instances[1].as_ref().unwrap();
+    let mut expected = CloudHypervisorInstance::new(PathBuf::from("/usr/bin/cloud-hypervisor"));
+    expected
+        .disk(
+            DiskBuilder::default()
+                .path(PathBuf::from("/vm1.raw"))
+                .build()
+                .unwrap(),
+        )
+        .api_socket(PathOrFileDescriptorOption::Path(PathBuf::from(
+            "/run/vm1.sock",
+        )))
+        .log_file(PathBuf::from("/var/log/vm1.log"));
+    assert_eq!(vm.pid, 200);
+    assert_eq!(vm.instance, expected);
+    assert_eq!(
+        vm.api_socket,
+        Some(PathOrFileDescriptorOption::Path(PathBuf::from(
+            "/run/vm1.sock"
+        )))
+    );
+    assert_eq!(vm.log_file, Some(PathBuf::from("/var/log/vm1.log")));
+
+    match &instances[2] {
+        Err(DiscoveryError::Parse { pid, args, error }) => {
+            assert_eq!(*pid, 300);
+            assert_eq!(args[1], "--pvmemcontrol");
+            assert_eq!(error.flag, "--pvmemcontrol");
+            assert_eq!(error.kind, ParseErrorKind::UnknownFlag);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let vm = instances[3].as_ref().unwrap();
+    let mut expected = CloudHypervisorInstance::new(PathBuf::from("cloud-hypervisor"));
+    expected.disk(
+        DiskBuilder::default()
+            .path(PathBuf::from(OsStr::from_bytes(b"/vm\xff.raw")))
+            .build()
+            .unwrap(),
+    );
+    assert_eq!(vm.instance, expected);
+
+    match &instances[4] {
+        Err(DiscoveryError::Parse { pid, error, .. }) => {
+            assert_eq!(*pid, 500);
+            assert_eq!(error.key.as_deref(), Some("id"));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
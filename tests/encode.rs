@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use cloud_hypervisor_command_builder::encode::{EncodeError, EncodeErrorReason};
+use cloud_hypervisor_command_builder::error::Error;
+use cloud_hypervisor_command_builder::from_command::FromCommand;
+use cloud_hypervisor_command_builder::to_command::{ToCommand, TryToCommand};
+use cloud_hypervisor_command_builder::{CloudHypervisorInstance, DiskBuilder, PlatformBuilder};
+
+#[test]
+fn quotes_values() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.disk(
+        DiskBuilder::default()
+            .path(PathBuf::from("/images/a,b.raw"))
+            .id("disk=0")
+            .build()
+            .unwrap(),
+    );
+    ch.platform(
+        PlatformBuilder::default()
+            .serial_number(" padded ")
+            .oem_strings(vec!["a".to_string(), "b".to_string()])
+            .build()
+            .unwrap(),
+    );
+
+    let expected = [
+        "/cloud-hypervisor",
+        "--platform",
+        "serial_number=\" padded \",oem_strings=[a,b]",
+        "--disk",
+        "path=\"/images/a,b.raw\",id=\"disk=0\"",
+    ];
+
+    assert_eq!(ch.try_to_command().unwrap(), expected);
+    assert_eq!(ch.to_command(), expected);
+    assert_eq!(
+        CloudHypervisorInstance::from_command(&ch.to_command()).unwrap(),
+        ch
+    );
+}
+
+#[test]
+fn rejects_unrepresentable_values() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.disk(
+        DiskBuilder::default()
+            .path(PathBuf::from("/images/\"quoted\".raw"))
+            .id("disk0")
+            .build()
+            .unwrap(),
+    );
+
+    assert_eq!(
+        ch.try_to_command(),
+        Err(Error::Encode(EncodeError {
+            option: "--disk".to_string(),
+            id: Some("disk0".to_string()),
+            key: "path".to_string(),
+            value: "/images/\"quoted\".raw".to_string(),
+            reason: EncodeErrorReason::ContainsQuote,
+        }))
+    );
+
+    let platform = PlatformBuilder::default()
+        .oem_strings(vec!["a,b".to_string(), "c]".to_string()])
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        platform.try_to_command().unwrap_err().to_string(),
+        "--platform oem_strings: cannot encode `[a,b,c]]`: list items cannot contain `,`"
+    );
+}
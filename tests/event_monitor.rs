@@ -0,0 +1,137 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::Duration;
+
+use cloud_hypervisor_command_builder::event_monitor::{
+    Event, EventError, EventReader, EventRecord,
+};
+
+use common::tmp;
+
+// As written by cloud-hypervisor: pretty printed objects separated by a blank line.
+const EVENTS: &str = r#"{
+  "timestamp": {
+    "secs": 0,
+    "nanos": 29228
+  },
+  "source": "vmm",
+  "event": "starting",
+  "properties": null
+}
+
+{
+  "timestamp": {
+    "secs": 0,
+    "nanos": 1102913
+  },
+  "source": "vm",
+  "event": "booting",
+  "properties": null
+}
+{"timestamp":{"secs":1,"nanos":5},"source":"vm","event":"device-removed","properties":{"id":"_disk0"}}
+{"timestamp":{"secs":2,"nanos":0},"source":"balloon","event":"inflated","properties":{"size":"1024"}}
+"#;
+
+#[test]
+fn events() {
+    let records = EventReader::new(EVENTS.as_bytes())
+        .collect::<Result<Vec<EventRecord>, EventError>>()
+        .unwrap();
+
+    assert_eq!(
+        records
+            .iter()
+            .map(|record| record.event.clone())
+            .collect::<Vec<Event>>(),
+        vec![
+            Event::VmmStarting,
+            Event::VmBooting,
+            Event::DeviceRemoved {
+                id: "_disk0".to_string()
+            },
+            Event::Unknown {
+                source: "balloon".to_string(),
+                event: "inflated".to_string()
+            },
+        ]
+    );
+    assert_eq!(records[0].timestamp, Duration::from_nanos(29228));
+    assert_eq!(
+        records[3].properties,
+        BTreeMap::from([("size".to_string(), "1024".to_string())])
+    );
+
+    assert_eq!(records[2].event.to_string(), "vm device-removed _disk0");
+    for record in &records {
+        assert_eq!(
+            Event::new(
+                record.event.source(),
+                record.event.name(),
+                &record.properties
+            ),
+            record.event
+        );
+    }
+}
+
+#[test]
+fn partial_writes() {
+    let path = tmp("events.json");
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .unwrap();
+    let (first, rest) = EVENTS.split_at(EVENTS.find("booting").unwrap());
+    file.write_all(first.as_bytes()).unwrap();
+
+    let mut events = EventReader::open(&path).unwrap();
+    assert_eq!(
+        events.next_event().unwrap().unwrap().event,
+        Event::VmmStarting
+    );
+    assert!(events.next_event().unwrap().is_none());
+
+    file.write_all(rest.as_bytes()).unwrap();
+    assert_eq!(
+        events.next_event().unwrap().unwrap().event,
+        Event::VmBooting
+    );
+    assert_eq!(events.count(), 2);
+}
+
+#[test]
+fn fd() {
+    let (mut writer, reader) = UnixStream::pair().unwrap();
+    let events = EventReader::from_fd(OwnedFd::from(reader));
+
+    let writing = thread::spawn(move || {
+        for chunk in EVENTS.as_bytes().chunks(7) {
+            writer.write_all(chunk).unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+
+    assert_eq!(events.map(Result::unwrap).count(), 4);
+    writing.join().unwrap();
+}
+
+#[test]
+fn invalid() {
+    let input =
+        r#"{"source":"vm"} {"timestamp":{"secs":0,"nanos":0},"source":"vm","event":"booted"}"#;
+    let mut events = EventReader::new(input.as_bytes());
+
+    assert!(matches!(events.next_event(), Err(EventError::Json(_))));
+
+    // What was buffered is dropped, later data is read again.
+    let mut events = EventReader::new(input.as_bytes().chain(EVENTS.as_bytes()));
+    assert!(events.next().unwrap().is_err());
+    assert_eq!(events.next().unwrap().unwrap().event, Event::VmmStarting);
+}
@@ -0,0 +1,449 @@
+use bytesize::ByteSize;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+
+use cloud_hypervisor_command_builder::from_command::{FromCommand, ParseError, ParseErrorKind};
+use cloud_hypervisor_command_builder::to_command::ToCommand;
+use cloud_hypervisor_command_builder::{
+    BalloonBuilder, CloudHypervisorInstance, Console, CpuAffinity, CpuFeatures, CpuTopology,
+    CpusBuilder, DebugConsole, DebugConsoleType, DeviceBuilder, DiskBuilder, FsBuilder,
+    LandlockRuleBuilder, MemoryBuilder, MemoryHotplugMethod, MemoryZoneBuilder, NetBuilder,
+    NumaBuilder, NumaDistance, OnOff, PathOrFileDescriptorOption, PlatformBuilder, PmemBuilder,
+    RateLimitGroupBuilder, Restore, Rng, SecComp, Serial, SgxEpc, UserDeviceBuilder, VdpaBuilder,
+    VhostMode, Vsock,
+};
+
+fn full_instance() -> CloudHypervisorInstance {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+
+    ch.cpus(
+        CpusBuilder::default()
+            .boot(2)
+            .max(4)
+            .topology(CpuTopology {
+                threads_per_core: 1,
+                cores_per_die: 2,
+                dies_per_package: 1,
+                packages: 2,
+            })
+            .kvm_hyperv(OnOff::On)
+            .max_phys_bits(40)
+            .affinity(vec![CpuAffinity {
+                vcpu: 0,
+                host_cpus: vec![1, 2],
+            }])
+            .features(CpuFeatures { amx: Some(true) })
+            .build()
+            .unwrap(),
+    );
+    ch.platform(
+        PlatformBuilder::default()
+            .num_pci_segments(2)
+            .serial_number("sn")
+            .oem_strings(vec!["a".to_string(), "b".to_string()])
+            .build()
+            .unwrap(),
+    );
+    ch.memory(
+        MemoryBuilder::default()
+            .size(ByteSize::gib(1))
+            .shared(OnOff::On)
+            .hotplug_method(MemoryHotplugMethod::VirtioMem)
+            .hotplug_size(ByteSize::mib(512))
+            .build()
+            .unwrap(),
+    );
+    ch.memory_zone(
+        MemoryZoneBuilder::default()
+            .size(ByteSize::mib(256))
+            .id("mem0")
+            .host_numa_node(0usize)
+            .build()
+            .unwrap(),
+    );
+    ch.kernel(PathBuf::from("/vmlinux"));
+    ch.cmdline("console=ttyS0 root=/dev/vda1".to_string());
+    ch.rate_limit_group(
+        RateLimitGroupBuilder::default()
+            .bw_size(ByteSize::kib(4))
+            .bw_one_time_burst(ByteSize::kib(8))
+            .bw_refill_time(100usize)
+            .id("group0")
+            .build()
+            .unwrap(),
+    );
+    ch.rate_limit_group(
+        RateLimitGroupBuilder::default()
+            .ops_size(10usize)
+            .id("group1")
+            .build()
+            .unwrap(),
+    );
+    ch.disk(
+        DiskBuilder::default()
+            .path(PathBuf::from("/disk0.raw"))
+            .readonly(OnOff::On)
+            .num_queues(2usize)
+            .id("disk0")
+            .rate_limit_group("group0")
+            .build()
+            .unwrap(),
+    );
+    ch.disk(
+        DiskBuilder::default()
+            .vhost_user(OnOff::On)
+            .socket(PathBuf::from("/disk1.sock"))
+            .bw_one_time_burst(ByteSize::kib(1))
+            .build()
+            .unwrap(),
+    );
+    ch.net(
+        NetBuilder::default()
+            .tap("tap0")
+            .ip(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)))
+            .mask(24)
+            .mac("12:34:56:78:90:ab")
+            .id("net0")
+            .build()
+            .unwrap(),
+    );
+    ch.net(
+        NetBuilder::default()
+            .fd(vec![3usize, 4])
+            .vhost_mode(VhostMode::Server)
+            .offload_tso(OnOff::Off)
+            .build()
+            .unwrap(),
+    );
+    ch.rng(Rng::Src(PathBuf::from("/dev/urandom")));
+    ch.balloon(
+        BalloonBuilder::default()
+            .size(ByteSize::mib(64))
+            .deflate_on_oom(OnOff::On)
+            .build()
+            .unwrap(),
+    );
+    ch.fs(FsBuilder::default()
+        .tag("share")
+        .socket(PathBuf::from("/virtiofs.sock"))
+        .build()
+        .unwrap());
+    ch.pmem(
+        PmemBuilder::default()
+            .file(PathBuf::from("/pmem"))
+            .size(1048576usize)
+            .discard_writes(OnOff::On)
+            .build()
+            .unwrap(),
+    );
+    ch.serial(Serial::Socket(PathBuf::from("/serial.sock")));
+    ch.console(Console::Off);
+    ch.device(
+        DeviceBuilder::default()
+            .path(PathBuf::from("/sys/bus/pci/devices/0000:01:00.0/"))
+            .id("vfio0")
+            .build()
+            .unwrap(),
+    );
+    ch.user_device(
+        UserDeviceBuilder::default()
+            .socket(PathBuf::from("/user.sock"))
+            .build()
+            .unwrap(),
+    );
+    ch.vdpa(
+        VdpaBuilder::default()
+            .path(PathBuf::from("/dev/vhost-vdpa-0"))
+            .num_queues(2usize)
+            .build()
+            .unwrap(),
+    );
+    ch.vsock(Vsock {
+        cid: Some("3".to_string()),
+        socket: Some(PathBuf::from("/vsock.sock")),
+        iommu: None,
+        id: None,
+        pci_segment: None,
+    });
+    ch.pvpanic(true);
+    ch.numa(
+        NumaBuilder::default()
+            .guest_numa_id("0")
+            .cpus(vec![0usize, 1])
+            .distances(vec![
+                NumaDistance {
+                    destination: 1,
+                    distance: 15,
+                },
+                NumaDistance {
+                    destination: 2,
+                    distance: 20,
+                },
+            ])
+            .memory_zones(vec!["mem0".to_string()])
+            .pci_segments(vec!["0".to_string(), "1".to_string()])
+            .build()
+            .unwrap(),
+    );
+    ch.watchdog(true);
+    ch.log_file(PathBuf::from("/ch.log"));
+    ch.api_socket(PathOrFileDescriptorOption::Path(PathBuf::from("/api.sock")));
+    ch.event_monitor(PathOrFileDescriptorOption::Fd(3));
+    ch.restore(Restore {
+        source_url: Some("file:///snapshot".to_string()),
+        prefault: Some(OnOff::Off),
+    });
+    ch.seccomp(SecComp::Log);
+    ch.tpm(PathBuf::from("/tpm.sock"));
+    ch.sgx_epc(SgxEpc {
+        id: Some("epc0".to_string()),
+        size: Some("64M".to_string()),
+        prefault: Some(OnOff::On),
+    });
+    ch.debug_console(DebugConsole {
+        console_type: Some(DebugConsoleType::File(PathBuf::from("/debug.log"))),
+        iobase: Some("0xe9".to_string()),
+    });
+    ch.landlock(true);
+    ch.landlock_rules(
+        LandlockRuleBuilder::default()
+            .path(PathBuf::from("/images"))
+            .access("rw")
+            .build()
+            .unwrap(),
+    );
+    ch.v();
+    ch.v();
+
+    ch
+}
+
+#[test]
+fn round_trip() {
+    let ch = full_instance();
+
+    assert_eq!(
+        CloudHypervisorInstance::from_command(&ch.to_command()).unwrap(),
+        ch
+    );
+}
+
+#[test]
+fn option_structs_round_trip() {
+    let ch = full_instance();
+    let args = ch.to_command();
+    let option = |flag: &str| {
+        let i = args.iter().position(|arg| arg == flag).unwrap();
+        args[i..i + 2].to_vec()
+    };
+
+    let cpus = cloud_hypervisor_command_builder::Cpus::from_command(&option("--cpus")).unwrap();
+    assert_eq!(cpus.to_command(), option("--cpus"));
+
+    let memory =
+        cloud_hypervisor_command_builder::Memory::from_command(&option("--memory")).unwrap();
+    assert_eq!(memory.to_command(), option("--memory"));
+}
+
+#[test]
+fn launch_script() {
+    let script = [
+        "cloud-hypervisor",
+        "--kernel",
+        "./vmlinux",
+        "--cpus",
+        "boot=4,affinity=[0@[0-2],1@[3]]",
+        "--memory=size=2G,hugepages=true",
+        "--disk",
+        "path=focal.raw",
+        "path=\"/images/a,b.raw\",id=data",
+        "--net",
+        "tap=,mac=,ip=10.0.0.1,mask=255.255.255.0",
+        "--api-socket",
+        "/tmp/ch.sock",
+        "--tpm",
+        "socket=/tmp/swtpm.sock",
+        "--numa",
+        "guest_numa_id=0,distances=[1@15,2@20]",
+        "-vvv",
+    ];
+
+    let ch = CloudHypervisorInstance::from_command(&script).unwrap();
+
+    let mut expected = CloudHypervisorInstance::new(PathBuf::from("cloud-hypervisor"));
+    expected.kernel(PathBuf::from("./vmlinux"));
+    expected.cpus(
+        CpusBuilder::default()
+            .boot(4)
+            .affinity(vec![
+                CpuAffinity {
+                    vcpu: 0,
+                    host_cpus: vec![0, 1, 2],
+                },
+                CpuAffinity {
+                    vcpu: 1,
+                    host_cpus: vec![3],
+                },
+            ])
+            .build()
+            .unwrap(),
+    );
+    expected.memory(
+        MemoryBuilder::default()
+            .size(ByteSize::gib(2))
+            .hugepages(OnOff::On)
+            .build()
+            .unwrap(),
+    );
+    expected.disk(
+        DiskBuilder::default()
+            .path(PathBuf::from("focal.raw"))
+            .build()
+            .unwrap(),
+    );
+    expected.disk(
+        DiskBuilder::default()
+            .path(PathBuf::from("/images/a,b.raw"))
+            .id("data")
+            .build()
+            .unwrap(),
+    );
+    expected.net(
+        NetBuilder::default()
+            .tap("")
+            .mac("")
+            .ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)))
+            .mask(24)
+            .build()
+            .unwrap(),
+    );
+    expected.api_socket(PathOrFileDescriptorOption::Path(PathBuf::from(
+        "/tmp/ch.sock",
+    )));
+    expected.tpm(PathBuf::from("/tmp/swtpm.sock"));
+    expected.numa(
+        NumaBuilder::default()
+            .guest_numa_id("0")
+            .distances(vec![
+                NumaDistance {
+                    destination: 1,
+                    distance: 15,
+                },
+                NumaDistance {
+                    destination: 2,
+                    distance: 20,
+                },
+            ])
+            .build()
+            .unwrap(),
+    );
+    expected.v().v().v();
+
+    assert_eq!(ch, expected);
+}
+
+#[test]
+fn errors_point_at_flag_and_key() {
+    assert_eq!(
+        CloudHypervisorInstance::from_command(&["ch", "--disk", "path=/a,num_queues=two"]),
+        Err(ParseError {
+            flag: "--disk".to_string(),
+            key: Some("num_queues".to_string()),
+            kind: ParseErrorKind::InvalidValue("two".to_string()),
+        })
+    );
+    assert_eq!(
+        CloudHypervisorInstance::from_command(&["ch", "--net", "tap=tap0,colour=blue"]),
+        Err(ParseError {
+            flag: "--net".to_string(),
+            key: Some("colour".to_string()),
+            kind: ParseErrorKind::UnknownKey,
+        })
+    );
+    assert_eq!(
+        CloudHypervisorInstance::from_command(&["ch", "--platform", "oem_strings=[a,b"]),
+        Err(ParseError {
+            flag: "--platform".to_string(),
+            key: None,
+            kind: ParseErrorKind::InvalidSyntax("oem_strings=[a,b".to_string()),
+        })
+    );
+    // Quotes do not protect list items from being split.
+    assert_eq!(
+        CloudHypervisorInstance::from_command(&["ch", "--platform", r#"oem_strings=["a,b",c]"#]),
+        Err(ParseError {
+            flag: "--platform".to_string(),
+            key: Some("oem_strings".to_string()),
+            kind: ParseErrorKind::InvalidValue(r#"["a,b",c]"#.to_string()),
+        })
+    );
+    assert_eq!(
+        CloudHypervisorInstance::from_command(&["ch", "--numa", r#"memory_zones=["mem0"]"#]),
+        Err(ParseError {
+            flag: "--numa".to_string(),
+            key: Some("memory_zones".to_string()),
+            kind: ParseErrorKind::InvalidValue(r#"["mem0"]"#.to_string()),
+        })
+    );
+    assert_eq!(
+        CloudHypervisorInstance::from_command(&["ch", "--net", "mask=255.0.255.0"]),
+        Err(ParseError {
+            flag: "--net".to_string(),
+            key: Some("mask".to_string()),
+            kind: ParseErrorKind::InvalidValue("255.0.255.0".to_string()),
+        })
+    );
+    assert_eq!(
+        CloudHypervisorInstance::from_command(&["ch", "--kernel"]),
+        Err(ParseError {
+            flag: "--kernel".to_string(),
+            key: None,
+            kind: ParseErrorKind::MissingValue,
+        })
+    );
+    assert_eq!(
+        CloudHypervisorInstance::from_command(&[
+            "ch", "--memory", "size=1G", "--memory", "size=2G"
+        ]),
+        Err(ParseError {
+            flag: "--memory".to_string(),
+            key: None,
+            kind: ParseErrorKind::RepeatedFlag,
+        })
+    );
+    assert_eq!(
+        CloudHypervisorInstance::from_command(&[
+            "ch",
+            "--memory-zone",
+            "id=m0,size=1G",
+            "id=m1,size=1G"
+        ])
+        .unwrap_err()
+        .to_string(),
+        "--memory-zone: flag may only be given once"
+    );
+    assert_eq!(
+        CloudHypervisorInstance::from_command(&["ch", "--colour"])
+            .unwrap_err()
+            .to_string(),
+        "--colour: unknown flag"
+    );
+}
+
+#[test]
+fn private_use_characters() {
+    // U+10FF41 is a valid character and must not be mistaken for a byte that is not UTF-8.
+    let ch = CloudHypervisorInstance::from_command(&[
+        "ch",
+        "--kernel",
+        "/boot/vmlinux-\u{10FF41}",
+        "--cmdline",
+        "console=\u{10FF41}",
+    ])
+    .unwrap();
+
+    let mut expected = CloudHypervisorInstance::new(PathBuf::from("ch"));
+    expected.kernel(PathBuf::from("/boot/vmlinux-\u{10FF41}"));
+    expected.cmdline("console=\u{10FF41}".to_string());
+    assert_eq!(ch, expected);
+}
@@ -0,0 +1,102 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cloud_hypervisor_command_builder::event_monitor::{Event, EventReader};
+use cloud_hypervisor_command_builder::lifecycle::{Lifecycle, Transition, VmState};
+
+fn events(events: &[(&str, &str)]) -> String {
+    events
+        .iter()
+        .enumerate()
+        .map(|(secs, (source, event))| {
+            format!(
+                r#"{{"timestamp":{{"secs":{},"nanos":0}},"source":"{}","event":"{}","properties":null}}"#,
+                secs, source, event
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn states(transitions: &[Transition]) -> Vec<VmState> {
+    transitions.iter().map(|transition| transition.to).collect()
+}
+
+#[test]
+fn lifecycle() {
+    let mut lifecycle = Lifecycle::new();
+    let seen = Arc::new(Mutex::new(vec![]));
+    let recorded = seen.clone();
+    lifecycle.on_transition(move |transition| recorded.lock().unwrap().push(transition.to));
+    let receiver = lifecycle.subscribe();
+
+    let input = events(&[
+        ("vmm", "starting"),
+        ("vm", "booting"),
+        ("vm", "booted"),
+        ("virtio-device", "activated"),
+        ("vm", "pausing"),
+        ("vm", "paused"),
+        ("vm", "resuming"),
+        ("vm", "resumed"),
+        ("guest", "panic"),
+        // Reset by the watchdog.
+        ("vm", "rebooting"),
+        ("vm", "rebooted"),
+        ("vm", "shutdown"),
+    ]);
+    let mut reader = EventReader::new(input.as_bytes());
+    assert_eq!(lifecycle.apply_all(&mut reader).unwrap(), 8);
+    assert_eq!(lifecycle.state(), VmState::Shutdown);
+
+    let expected = [
+        VmState::Booting,
+        VmState::Running,
+        VmState::Paused,
+        VmState::Running,
+        VmState::Panicked,
+        VmState::Rebooting,
+        VmState::Running,
+        VmState::Shutdown,
+    ];
+    assert_eq!(states(lifecycle.history()), expected);
+    assert_eq!(*seen.lock().unwrap(), states(lifecycle.history()));
+    assert_eq!(
+        receiver.try_iter().collect::<Vec<Transition>>(),
+        lifecycle.history()
+    );
+
+    let panic = &lifecycle.history()[4];
+    assert_eq!(panic.from, VmState::Running);
+    assert_eq!(panic.event, Some(Event::GuestPanic));
+    assert_eq!(panic.timestamp, Some(Duration::from_secs(8)));
+    assert_eq!(lifecycle.since(), lifecycle.history().last().unwrap().at);
+}
+
+#[test]
+fn exited() {
+    let mut lifecycle = Lifecycle::new();
+    let receiver = lifecycle.subscribe();
+    drop(receiver);
+
+    let input = events(&[("vm", "booting"), ("vm", "booted")]);
+    lifecycle
+        .apply_all(&mut EventReader::new(input.as_bytes()))
+        .unwrap();
+
+    let exit = lifecycle.process_exited().unwrap();
+    assert_eq!((exit.from, exit.to), (VmState::Running, VmState::Exited));
+    assert_eq!(exit.event, None);
+    assert_eq!(exit.to.to_string(), "exited");
+
+    // Nothing moves an exited VM.
+    let input = events(&[("vm", "booting"), ("vmm", "shutdown")]);
+    assert_eq!(
+        lifecycle
+            .apply_all(&mut EventReader::new(input.as_bytes()))
+            .unwrap(),
+        0
+    );
+    assert!(lifecycle.process_exited().is_none());
+    assert_eq!(lifecycle.state(), VmState::Exited);
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use cloud_hypervisor_command_builder::api::ApiClient;
+use cloud_hypervisor_command_builder::api::ApiError;
+use cloud_hypervisor_command_builder::migration::{
+    migrate, MigrationError, MigrationOptions, MigrationStage,
+};
+use cloud_hypervisor_command_builder::{CloudHypervisorInstance, PathOrFileDescriptorOption};
+
+use common::{script, serve, tmp};
+
+fn url_path(body: &str, key: &str) -> PathBuf {
+    let value: serde_json::Value = serde_json::from_str(body).unwrap();
+    PathBuf::from(value[key].as_str().unwrap().strip_prefix("unix:").unwrap())
+}
+
+// A destination VMM: the process only idles, its API is served in-process and receives the
+// migration by reading the socket until the sender closes it.
+fn destination(name: &str) -> (CloudHypervisorInstance, Arc<Mutex<Vec<String>>>) {
+    let bin_path = script(name, "while true; do sleep 0.01; done");
+
+    let api_socket = tmp(&format!("{}-api.sock", name));
+    let requests = serve(&api_socket, |request| match request.endpoint.as_str() {
+        "vmm.ping" => (
+            "200 OK",
+            r#"{"build_version":"v40.0.0","version":"40.0.0"}"#.to_string(),
+        ),
+        "vm.receive-migration" => {
+            let listener = UnixListener::bind(url_path(&request.body, "receiver_url")).unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = String::new();
+            stream.read_to_string(&mut received).unwrap();
+            if received == "guest memory" {
+                ("204 No Content", String::new())
+            } else {
+                ("500 Internal Server Error", "no data".to_string())
+            }
+        }
+        _ => ("404 Not Found", String::new()),
+    });
+
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    ch.api_socket(PathOrFileDescriptorOption::Path(api_socket));
+    (ch, requests)
+}
+
+fn source(name: &str, data: &'static str, status: &'static str) -> ApiClient {
+    let socket = tmp(&format!("{}-source.sock", name));
+    serve(&socket, move |request| {
+        let mut stream = UnixStream::connect(url_path(&request.body, "destination_url")).unwrap();
+        stream.write_all(data.as_bytes()).unwrap();
+        (status, String::new())
+    });
+    ApiClient::from_path(socket)
+}
+
+#[test]
+fn migrates() {
+    let (destination, requests) = destination("migrates");
+    let source = source("migrates", "guest memory", "204 No Content");
+    let socket = tmp("migrates-migration.sock");
+
+    let mut stages = vec![];
+    let mut process = migrate(
+        &source,
+        &destination,
+        &MigrationOptions::new(&socket).local(true),
+        |stage| stages.push(stage),
+    )
+    .unwrap();
+    assert_eq!(
+        stages,
+        vec![
+            MigrationStage::StartingDestination,
+            MigrationStage::Receiving,
+            MigrationStage::Sending,
+            MigrationStage::Completed,
+        ]
+    );
+    assert_eq!(
+        requests.lock().unwrap().last().unwrap(),
+        &format!(
+            r#"PUT /api/v1/vm.receive-migration HTTP/1.1 {{"receiver_url":"unix:{}"}}"#,
+            socket.display()
+        )
+    );
+    process.kill().unwrap();
+}
+
+#[test]
+fn failed_migration() {
+    let (destination, _) = destination("fails");
+    let source = source("fails", "", "500 Internal Server Error");
+
+    let err = migrate(
+        &source,
+        &destination,
+        &MigrationOptions::new(tmp("fails-migration.sock")),
+        |_| {},
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        MigrationError::Send(ApiError::Http { status: 500, .. })
+    ));
+
+    // A mistyped socket path must not delete what is there.
+    let file = tmp("fails-migration.txt");
+    fs::write(&file, "keep").unwrap();
+    match migrate(&source, &destination, &MigrationOptions::new(&file), |_| {}) {
+        Err(MigrationError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::AlreadyExists),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(fs::read_to_string(&file).unwrap(), "keep");
+
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.api_socket(PathOrFileDescriptorOption::Fd(3));
+    assert!(matches!(
+        migrate(
+            &source,
+            &ch,
+            &MigrationOptions::new(tmp("fd-migration.sock")),
+            |_| {}
+        ),
+        Err(MigrationError::NoApiSocket)
+    ));
+}
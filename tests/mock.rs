@@ -0,0 +1,137 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use cloud_hypervisor_command_builder::api::{ApiError, VmState};
+use cloud_hypervisor_command_builder::mock::{MockRequest, MockServer};
+use cloud_hypervisor_command_builder::snapshot::{AfterSnapshot, Snapshot};
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, CpusBuilder, DiskBuilder, PathOrFileDescriptorOption,
+};
+
+use common::tmp;
+
+fn instance(socket: PathBuf) -> CloudHypervisorInstance {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.cpus(CpusBuilder::default().boot(2).build().unwrap())
+        .api_socket(PathOrFileDescriptorOption::Path(socket));
+    ch
+}
+
+#[test]
+fn lifecycle() {
+    let server = MockServer::start(tmp("lifecycle.sock")).unwrap();
+    let api = server.client();
+
+    assert_eq!(api.vmm_ping().unwrap().version, "40.0.0");
+    assert!(matches!(
+        api.vm_boot(),
+        Err(ApiError::Http { status: 500, .. })
+    ));
+
+    let ch = instance(server.socket().to_path_buf());
+    api.vm_create(&ch.to_vm_config().unwrap()).unwrap();
+    assert_eq!(server.state(), Some(VmState::Created));
+    assert_eq!(server.config().unwrap().cpus.boot_vcpus, 2);
+
+    api.vm_boot().unwrap();
+    api.vm_pause().unwrap();
+    assert_eq!(api.vm_info().unwrap().state, VmState::Paused);
+    assert!(matches!(
+        api.vm_pause(),
+        Err(ApiError::Http { status: 500, .. })
+    ));
+    api.vm_resume().unwrap();
+    api.vm_shutdown().unwrap();
+    assert_eq!(server.state(), Some(VmState::Shutdown));
+    api.vm_boot().unwrap();
+    api.vm_delete().unwrap();
+    assert_eq!(server.state(), None);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 11);
+    assert_eq!(
+        requests[0],
+        MockRequest {
+            method: "GET".to_string(),
+            endpoint: "vmm.ping".to_string(),
+            body: String::new(),
+        }
+    );
+    assert_eq!(requests[3].endpoint, "vm.boot");
+}
+
+#[test]
+fn devices() {
+    let server = MockServer::start(tmp("devices.sock")).unwrap();
+    let api = server.client();
+    let mut ch = instance(server.socket().to_path_buf());
+    api.vm_create(&ch.to_vm_config().unwrap()).unwrap();
+    api.vm_boot().unwrap();
+
+    let info = ch
+        .hotplug_disk(
+            &api,
+            DiskBuilder::default().path("/data.raw").build().unwrap(),
+        )
+        .unwrap();
+    assert_eq!(info.id, "_disk0");
+    assert_eq!(server.config().unwrap().disks.unwrap().len(), 1);
+
+    server.set_counters(BTreeMap::from([(
+        "_disk0".to_string(),
+        BTreeMap::from([("read_bytes".to_string(), 512)]),
+    )]));
+    assert_eq!(api.vm_counters().unwrap()["_disk0"]["read_bytes"], 512);
+
+    assert!(ch.unplug(&api, "_disk0").unwrap());
+    assert_eq!(server.config().unwrap().disks, None);
+    assert!(matches!(
+        api.vm_remove_device("_disk0"),
+        Err(ApiError::Http { status: 500, .. })
+    ));
+}
+
+#[test]
+fn snapshot_and_restore() {
+    let server = MockServer::start(tmp("snapshot.sock")).unwrap();
+    let api = server.client();
+    let ch = instance(server.socket().to_path_buf());
+    api.vm_create(&ch.to_vm_config().unwrap()).unwrap();
+    api.vm_boot().unwrap();
+
+    let dir = tmp("snapshot");
+    let _ = fs::remove_dir_all(&dir);
+    let snapshot = Snapshot::take(&api, &dir, AfterSnapshot::Shutdown).unwrap();
+    assert_eq!(server.state(), Some(VmState::Shutdown));
+
+    api.vm_delete().unwrap();
+    api.vm_restore(&snapshot.source_url(), false).unwrap();
+    assert_eq!(server.state(), Some(VmState::Paused));
+    assert_eq!(server.config().unwrap().cpus.boot_vcpus, 2);
+}
+
+#[test]
+fn failures_and_latency() {
+    let server = MockServer::start(tmp("failures.sock")).unwrap();
+    let api = server.client();
+
+    server.fail_next("vmm.ping", 503, "busy");
+    match api.vmm_ping() {
+        Err(ApiError::Http { status, body, .. }) => {
+            assert_eq!(status, 503);
+            assert_eq!(body, "busy");
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    api.vmm_ping().unwrap();
+
+    server.set_latency(Duration::from_millis(50));
+    let start = Instant::now();
+    api.vmm_ping().unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(server.requests().len(), 3);
+}
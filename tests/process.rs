@@ -0,0 +1,280 @@
+mod common;
+
+use std::fs;
+use std::io::Read;
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use cloud_hypervisor_command_builder::error::Error;
+use cloud_hypervisor_command_builder::event_monitor::{Event, EventRecord};
+use cloud_hypervisor_command_builder::process::{ExitReason, InheritedFds};
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, Console, NetBuilder, PathOrFileDescriptorOption, Serial,
+};
+
+use common::{script, serve, tmp};
+
+fn read_all(mut stream: UnixStream) -> String {
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn remaps_fds() {
+    let (tap, tap_peer) = UnixStream::pair().unwrap();
+    let (api, api_peer) = UnixStream::pair().unwrap();
+    // Stays open in the parent without FD_CLOEXEC and must not reach the child.
+    let (stray, _stray_peer) = UnixStream::pair().unwrap();
+    unsafe {
+        libc::fcntl(stray.as_raw_fd(), libc::F_SETFD, 0);
+    }
+
+    let bin_path = script(
+        "remap",
+        &format!(
+            "echo \"$@\" >&5\necho api >&6\n[ -e /proc/$$/fd/{} ] && echo leaked >&5\nexit 0\n",
+            stray.as_raw_fd()
+        ),
+    );
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    ch.net(NetBuilder::default().fd(vec![5usize]).build().unwrap());
+    ch.api_socket(PathOrFileDescriptorOption::Fd(6));
+
+    let mut fds = InheritedFds::new();
+    fds.insert(5, OwnedFd::from(tap));
+    fds.insert_borrowed(6, api.as_fd()).unwrap();
+    let mut cmd = ch.to_process_command(fds).unwrap();
+    drop(api);
+
+    assert!(cmd.status().unwrap().success());
+    drop(cmd);
+
+    assert_eq!(read_all(tap_peer), "--net fd=[5] --api-socket fd=6\n");
+    assert_eq!(read_all(api_peer), "api\n");
+}
+
+#[test]
+fn swaps_fds() {
+    // Sources sitting on each other's target numbers must not clobber one another.
+    let (a, a_peer) = UnixStream::pair().unwrap();
+    let (b, b_peer) = UnixStream::pair().unwrap();
+    let (a_raw, b_raw) = (a.as_raw_fd(), b.as_raw_fd());
+
+    let bin_path = script("swap", &format!("echo a >&{}\necho b >&{}\n", b_raw, a_raw));
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    ch.net(
+        NetBuilder::default()
+            .fd(vec![b_raw as usize, a_raw as usize])
+            .build()
+            .unwrap(),
+    );
+
+    let mut fds = InheritedFds::new();
+    fds.insert(b_raw, OwnedFd::from(a));
+    fds.insert(a_raw, OwnedFd::from(b));
+    let mut cmd = ch.to_process_command(fds).unwrap();
+
+    assert!(cmd.status().unwrap().success());
+    drop(cmd);
+
+    assert_eq!(read_all(a_peer), "a\n");
+    assert_eq!(read_all(b_peer), "b\n");
+}
+
+#[test]
+fn missing_fds() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.event_monitor(PathOrFileDescriptorOption::Fd(4));
+
+    assert_eq!(
+        ch.to_process_command(InheritedFds::new()).unwrap_err(),
+        Error::MissingFd {
+            option: "--event-monitor".to_string(),
+            fd: 4,
+        }
+    );
+}
+
+#[test]
+fn failed_exit() {
+    let bin_path = script(
+        "failed",
+        "echo starting >&2\necho 'Error booting VM: bad kernel' >&2\nexit 1\n",
+    );
+    let ch = CloudHypervisorInstance::new(bin_path);
+    let mut vm = ch.spawn(InheritedFds::new()).unwrap();
+
+    let exit = vm.wait().unwrap();
+    assert_eq!(
+        exit.reason,
+        ExitReason::Failed {
+            code: 1,
+            message: Some("Error booting VM: bad kernel".to_string()),
+        }
+    );
+    assert_eq!(exit.stderr, "starting\nError booting VM: bad kernel\n");
+    assert_eq!(vm.wait().unwrap(), exit);
+}
+
+#[test]
+fn stdin() {
+    let bin_path = script("stdin", "readlink /proc/self/fd/0 >&2\n");
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    let stdin = |ch: &CloudHypervisorInstance| {
+        let exit = ch.spawn(InheritedFds::new()).unwrap().wait().unwrap();
+        exit.stderr.trim_end().to_string()
+    };
+    let inherited = fs::read_link("/proc/self/fd/0")
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+
+    // The default console is a tty.
+    assert_eq!(stdin(&ch), inherited);
+
+    ch.console(Console::Off);
+    assert_eq!(stdin(&ch), "/dev/null");
+
+    ch.serial(Serial::Tty);
+    assert_eq!(stdin(&ch), inherited);
+}
+
+#[test]
+fn kill() {
+    let bin_path = script("kill", "exec sleep 10\n");
+    let ch = CloudHypervisorInstance::new(bin_path);
+    let mut vm = ch.spawn(InheritedFds::new()).unwrap();
+
+    assert_eq!(vm.try_wait().unwrap(), None);
+    vm.kill().unwrap();
+    assert_eq!(
+        vm.wait().unwrap().reason,
+        ExitReason::Signaled {
+            signal: libc::SIGKILL
+        }
+    );
+}
+
+#[test]
+fn shutdown_through_api() {
+    let api_socket = tmp("api.sock");
+    let stop = tmp("stop");
+    let log_file = tmp("ch.log");
+
+    let bin_path = script(
+        "api",
+        &format!("while [ ! -e {} ]; do sleep 0.01; done\n", stop.display()),
+    );
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    ch.api_socket(PathOrFileDescriptorOption::Path(api_socket.clone()));
+    ch.log_file(log_file.clone());
+    let mut vm = ch.spawn(InheritedFds::new()).unwrap();
+    assert_eq!(vm.api_socket(), Some(api_socket.as_path()));
+    assert_eq!(vm.log_file(), Some(log_file.as_path()));
+
+    let requests = serve(&api_socket, move |_| {
+        fs::write(&stop, "").unwrap();
+        ("204 No Content", String::new())
+    });
+
+    let exit = vm.shutdown(Duration::from_secs(5)).unwrap();
+    assert_eq!(exit.reason, ExitReason::Exited);
+    assert_eq!(
+        *requests.lock().unwrap(),
+        ["PUT /api/v1/vmm.shutdown HTTP/1.1 "]
+    );
+}
+
+// Waits until the script installed its signal handler.
+fn trapping_binary(name: &str, trap: &str) -> (PathBuf, PathBuf) {
+    let ready = tmp(&format!("{}.ready", name));
+    let bin_path = script(
+        name,
+        &format!(
+            "trap '{}' TERM\ntouch {}\nwhile true; do sleep 0.01; done\n",
+            trap,
+            ready.display()
+        ),
+    );
+    (bin_path, ready)
+}
+
+fn wait_for(path: &Path) {
+    while !path.exists() {
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn shutdown_falls_back_to_signals() {
+    let (bin_path, ready) = trapping_binary("sigterm", "exit 3");
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    // Nothing listens here, so the API request fails.
+    ch.api_socket(PathOrFileDescriptorOption::Path(tmp("missing.sock")));
+    let mut vm = ch.spawn(InheritedFds::new()).unwrap();
+    wait_for(&ready);
+
+    let exit = vm.shutdown(Duration::from_secs(5)).unwrap();
+    assert_eq!(
+        exit.reason,
+        ExitReason::Failed {
+            code: 3,
+            message: None
+        }
+    );
+
+    let (bin_path, ready) = trapping_binary("sigkill", "");
+    let ch = CloudHypervisorInstance::new(bin_path);
+    let mut vm = ch.spawn(InheritedFds::new()).unwrap();
+    wait_for(&ready);
+
+    let exit = vm.shutdown(Duration::from_millis(100)).unwrap();
+    assert_eq!(
+        exit.reason,
+        ExitReason::Signaled {
+            signal: libc::SIGKILL
+        }
+    );
+}
+
+#[test]
+fn event_monitor_pipe() {
+    // Reports the fd it was given as an event property.
+    let bin_path = script(
+        "events",
+        concat!(
+            "for arg; do [ \"$prev\" = --event-monitor ] && fd=${arg#fd=}; prev=$arg; done\n",
+            "echo \"$@\" >&2\n",
+            "echo '{\"timestamp\":{\"secs\":0,\"nanos\":1},\"source\":\"vmm\",\"event\":\"starting\",\"properties\":null}' >&$fd\n",
+            "printf '{\"timestamp\":{\"secs\":0,\"nanos\":2},\"source\":\"vm\",\"event\":\"booted\",\"properties\":{\"fd\":\"%s\"}}' $fd >&$fd\n",
+        ),
+    );
+    let (api, _api_peer) = UnixStream::pair().unwrap();
+    let mut ch = CloudHypervisorInstance::new(bin_path);
+    ch.api_socket(PathOrFileDescriptorOption::Fd(3))
+        .event_monitor_pipe(true);
+
+    let mut fds = InheritedFds::new();
+    fds.insert(3, OwnedFd::from(api));
+    let mut vm = ch.spawn(fds).unwrap();
+    let events = vm.take_events().unwrap();
+    assert!(vm.take_events().is_none());
+
+    // The stream ends when the VMM exits.
+    let records = events.map(Result::unwrap).collect::<Vec<EventRecord>>();
+    assert_eq!(
+        records
+            .iter()
+            .map(|record| &record.event)
+            .collect::<Vec<&Event>>(),
+        [&Event::VmmStarting, &Event::VmBooted]
+    );
+    assert_eq!(records[1].properties["fd"], "4");
+
+    let exit = vm.wait().unwrap();
+    assert_eq!(exit.reason, ExitReason::Exited);
+    assert!(exit.stderr.contains("--event-monitor fd=4"));
+}
@@ -0,0 +1,258 @@
+mod common;
+
+use std::ffi::CString;
+use std::fs;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::thread;
+use std::time::Duration;
+
+use cloud_hypervisor_command_builder::api::{ApiError, VmInfo, VmState};
+use cloud_hypervisor_command_builder::event_monitor::Event;
+use cloud_hypervisor_command_builder::process::{ExitReason, InheritedFds};
+use cloud_hypervisor_command_builder::ready::{ReadyCondition, ReadyError, ReadyStage};
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, CpusBuilder, PathOrFileDescriptorOption,
+};
+
+use common::{script, serve, tmp};
+
+// A VMM that only idles; its API is served in-process.
+fn instance(name: &str, body: &str) -> CloudHypervisorInstance {
+    let mut ch = CloudHypervisorInstance::new(script(name, body));
+    ch.api_socket(PathOrFileDescriptorOption::Path(tmp(&format!(
+        "{}.sock",
+        name
+    ))));
+    ch
+}
+
+fn ping() -> (&'static str, String) {
+    (
+        "200 OK",
+        r#"{"build_version":"v40.0","version":"40.0"}"#.to_string(),
+    )
+}
+
+#[test]
+fn api() {
+    let ch = instance("api", "while true; do sleep 0.01; done");
+    let mut vm = ch.spawn(InheritedFds::new()).unwrap();
+    // The socket shows up a little after the process started.
+    let socket = vm.api_socket().unwrap().to_path_buf();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        serve(&socket, |_| ping());
+    });
+
+    assert_eq!(
+        vm.wait_ready(Duration::from_secs(5)).unwrap().version,
+        "40.0"
+    );
+    vm.kill().unwrap();
+}
+
+#[test]
+fn vm_running() {
+    let mut ch = instance("vm_running", "while true; do sleep 0.01; done");
+    ch.cpus(CpusBuilder::default().boot(1).build().unwrap());
+    let config = ch.to_vm_config().unwrap();
+    let mut vm = ch.spawn(InheritedFds::new()).unwrap();
+
+    let mut info_requests = 0;
+    serve(vm.api_socket().unwrap(), move |request| {
+        if request.endpoint != "vm.info" {
+            return ping();
+        }
+        // Booting takes a few polls.
+        info_requests += 1;
+        let state = match info_requests {
+            1 | 2 => VmState::Created,
+            _ => VmState::Running,
+        };
+        let info = VmInfo {
+            config: config.clone(),
+            state,
+            memory_actual_size: None,
+            device_tree: None,
+        };
+        ("200 OK", serde_json::to_string(&info).unwrap())
+    });
+
+    vm.wait_ready_for(Duration::from_secs(5), &ReadyCondition::VmRunning)
+        .unwrap();
+    vm.kill().unwrap();
+}
+
+#[test]
+fn event() {
+    let events = tmp("events.json");
+    let mut ch = instance("event", "while true; do sleep 0.01; done");
+    ch.event_monitor(PathOrFileDescriptorOption::Path(events.clone()));
+    let mut vm = ch.spawn(InheritedFds::new()).unwrap();
+    assert_eq!(vm.event_monitor(), Some(events.as_path()));
+    serve(vm.api_socket().unwrap(), |_| ping());
+
+    // The last event is written in two parts.
+    thread::spawn(move || {
+        let mut file = fs::File::create(&events).unwrap();
+        file.write_all(concat!(
+            r#"{"timestamp":{"secs":0,"nanos":1},"source":"vmm","event":"starting","properties":null}"#,
+            "\n",
+            r#"{"timestamp":{"secs":0,"nanos":2},"source":"vm","event":"boo"#,
+        ).as_bytes())
+        .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        file.write_all(b"ted\",\"properties\":null}\n").unwrap();
+    });
+
+    let booted = ReadyCondition::Event {
+        source: "vm".to_string(),
+        event: "booted".to_string(),
+    };
+    vm.wait_ready_for(Duration::from_secs(5), &booted).unwrap();
+    vm.kill().unwrap();
+}
+
+#[test]
+fn event_fifo() {
+    let events = tmp("events.fifo");
+    let path = CString::new(events.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o600) }, 0);
+    let mut ch = instance("event_fifo", "while true; do sleep 0.01; done");
+    ch.event_monitor(PathOrFileDescriptorOption::Path(events.clone()));
+    let mut vm = ch.spawn(InheritedFds::new()).unwrap();
+    serve(vm.api_socket().unwrap(), |_| ping());
+
+    let booted = ReadyCondition::Event {
+        source: "vm".to_string(),
+        event: "booted".to_string(),
+    };
+    // Nothing opens the fifo for writing, which must not block past the timeout.
+    match vm.wait_ready_for(Duration::from_millis(200), &booted) {
+        Err(ReadyError::Timeout {
+            waiting_for: ReadyStage::Event,
+            ..
+        }) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+
+    thread::spawn(move || {
+        let mut fifo = fs::OpenOptions::new().write(true).open(&events).unwrap();
+        fifo.write_all(
+            concat!(
+                r#"{"timestamp":{"secs":0,"nanos":1},"source":"vm","event":"booted","properties":null}"#,
+                "\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+    });
+    vm.wait_ready_for(Duration::from_secs(5), &booted).unwrap();
+    vm.kill().unwrap();
+}
+
+#[test]
+fn event_pipe() {
+    // Writes to the fd given as `--event-monitor fd=N`, the second event a little later.
+    let mut ch = instance(
+        "event_pipe",
+        concat!(
+            "for arg; do [ \"$prev\" = --event-monitor ] && fd=${arg#fd=}; prev=$arg; done\n",
+            "echo '{\"timestamp\":{\"secs\":0,\"nanos\":1},\"source\":\"vmm\",\"event\":\"starting\",\"properties\":null}' >&$fd\n",
+            "sleep 0.1\n",
+            "echo '{\"timestamp\":{\"secs\":0,\"nanos\":2},\"source\":\"vm\",\"event\":\"booted\",\"properties\":null}' >&$fd\n",
+            "while true; do sleep 0.01; done",
+        ),
+    );
+    ch.event_monitor_pipe(true);
+    let mut vm = ch.spawn(InheritedFds::new()).unwrap();
+    serve(vm.api_socket().unwrap(), |_| ping());
+
+    let booted = ReadyCondition::Event {
+        source: "vm".to_string(),
+        event: "booted".to_string(),
+    };
+    vm.wait_ready_for(Duration::from_secs(5), &booted).unwrap();
+
+    // Nothing was consumed while waiting.
+    let mut events = vm.take_events().unwrap();
+    assert_eq!(
+        events.next_event().unwrap().unwrap().event,
+        Event::VmmStarting
+    );
+    assert_eq!(events.next_event().unwrap().unwrap().event, Event::VmBooted);
+    vm.kill().unwrap();
+}
+
+#[test]
+fn exited() {
+    let ch = instance("exited", "echo 'Error booting VM' >&2\nexit 1");
+    let mut vm = ch.spawn(InheritedFds::new()).unwrap();
+
+    match vm.wait_ready(Duration::from_secs(5)) {
+        Err(ReadyError::Exited(exit)) => assert_eq!(
+            exit.reason,
+            ExitReason::Failed {
+                code: 1,
+                message: Some("Error booting VM".to_string())
+            }
+        ),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn timeout() {
+    let log_file = tmp("timeout.log");
+    let lines = (0..25).map(|n| format!("line {}\n", n)).collect::<String>();
+    fs::write(&log_file, lines).unwrap();
+
+    let mut ch = instance("timeout", "while true; do sleep 0.01; done");
+    ch.log_file(log_file);
+    let mut vm = ch.spawn(InheritedFds::new()).unwrap();
+
+    let err = vm.wait_ready(Duration::from_millis(100)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "timed out waiting for the api socket to appear (last log line: line 24)"
+    );
+    match err {
+        ReadyError::Timeout {
+            waiting_for,
+            last_error,
+            log,
+        } => {
+            assert_eq!(waiting_for, ReadyStage::Socket);
+            assert!(last_error.is_none());
+            assert_eq!(log.len(), 20);
+            assert_eq!(log[0], "line 5");
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert!(matches!(
+        vm.wait_ready_for(
+            Duration::from_millis(100),
+            &ReadyCondition::Event {
+                source: "vm".to_string(),
+                event: "booted".to_string(),
+            }
+        ),
+        Err(ReadyError::NoEventMonitor)
+    ));
+
+    // Every ping fails quickly; the last one is still what gets reported.
+    serve(vm.api_socket().unwrap(), |_| {
+        ("200 OK", "not json".to_string())
+    });
+    match vm.wait_ready(Duration::from_millis(100)) {
+        Err(ReadyError::Timeout {
+            waiting_for: ReadyStage::Api,
+            last_error: Some(err),
+            ..
+        }) => assert!(matches!(*err, ApiError::Json(_)), "{:?}", err),
+        other => panic!("unexpected {:?}", other),
+    }
+    vm.kill().unwrap();
+}
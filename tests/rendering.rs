@@ -0,0 +1,72 @@
+use bytesize::ByteSize;
+use std::path::PathBuf;
+
+use cloud_hypervisor_command_builder::to_command::ToCommand;
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, NetBuilder, NumaBuilder, NumaDistance, RateLimitGroupBuilder,
+};
+
+#[test]
+fn rate_limit_groups() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.rate_limit_group(
+        RateLimitGroupBuilder::default()
+            .bw_size(ByteSize::b(1024))
+            .bw_one_time_burst(ByteSize::kib(8))
+            .id("group0")
+            .build()
+            .unwrap(),
+    );
+    ch.rate_limit_group(
+        RateLimitGroupBuilder::default()
+            .ops_size(10usize)
+            .id("group1")
+            .build()
+            .unwrap(),
+    );
+
+    assert_eq!(
+        ch.to_command(),
+        vec![
+            "/cloud-hypervisor",
+            "--rate-limit-group",
+            "bw_size=1024,bw_one_time_burst=8192,id=group0",
+            "ops_size=10,id=group1",
+        ]
+    );
+}
+
+#[test]
+fn lists() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.net(NetBuilder::default().fd(vec![3usize, 4]).build().unwrap());
+    ch.numa(
+        NumaBuilder::default()
+            .guest_numa_id("0")
+            .cpus(vec![0usize, 1])
+            .distances(vec![
+                NumaDistance {
+                    destination: 1,
+                    distance: 15,
+                },
+                NumaDistance {
+                    destination: 2,
+                    distance: 20,
+                },
+            ])
+            .memory_zones(vec!["mem0".to_string()])
+            .build()
+            .unwrap(),
+    );
+
+    assert_eq!(
+        ch.to_command(),
+        vec![
+            "/cloud-hypervisor",
+            "--net",
+            "fd=[3,4]",
+            "--numa",
+            "guest_numa_id=0,cpus=[0,1],distances=[1@15,2@20],memory_zones=[mem0]",
+        ]
+    );
+}
@@ -0,0 +1,137 @@
+use bytesize::ByteSize;
+use std::path::PathBuf;
+
+use cloud_hypervisor_command_builder::error::Error;
+use cloud_hypervisor_command_builder::resize::{ResizeError, VmResizeBuilder};
+use cloud_hypervisor_command_builder::{
+    BalloonBuilder, CloudHypervisorInstance, CpusBuilder, MemoryBuilder, MemoryHotplugMethod,
+    MemoryZoneBuilder,
+};
+
+fn instance() -> CloudHypervisorInstance {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.cpus(CpusBuilder::default().boot(2).max(8).build().unwrap())
+        .memory(
+            MemoryBuilder::default()
+                .size(ByteSize::gib(1))
+                .hotplug_method(MemoryHotplugMethod::VirtioMem)
+                .hotplug_size(ByteSize::gib(3))
+                .build()
+                .unwrap(),
+        )
+        .memory_zone(
+            MemoryZoneBuilder::default()
+                .id("mem0")
+                .size(ByteSize::gib(1))
+                .hotplug_size(ByteSize::gib(1))
+                .build()
+                .unwrap(),
+        )
+        .balloon(
+            BalloonBuilder::default()
+                .size(ByteSize::b(0))
+                .build()
+                .unwrap(),
+        );
+    ch
+}
+
+#[test]
+fn resize() {
+    let ch = instance();
+
+    let resize = VmResizeBuilder::default()
+        .desired_vcpus(8)
+        .desired_ram(ByteSize::gib(4))
+        .desired_balloon(ByteSize::gib(2))
+        .build()
+        .unwrap();
+    assert_eq!(ch.check_resize(&resize), Ok(()));
+
+    let check = |resize| match ch.check_resize(&resize) {
+        Err(Error::Resize(err)) => err,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(
+        check(VmResizeBuilder::default().build().unwrap()),
+        ResizeError::Empty
+    );
+    assert_eq!(
+        check(VmResizeBuilder::default().desired_vcpus(9).build().unwrap()),
+        ResizeError::VcpusOutOfRange { desired: 9, max: 8 }
+    );
+    assert_eq!(
+        check(
+            VmResizeBuilder::default()
+                .desired_ram(ByteSize::mib(512))
+                .build()
+                .unwrap()
+        ),
+        ResizeError::RamOutOfRange {
+            desired: ByteSize::mib(512),
+            min: ByteSize::gib(1),
+            max: ByteSize::gib(4),
+        }
+    );
+    assert_eq!(
+        check(
+            VmResizeBuilder::default()
+                .desired_ram(ByteSize::gib(2))
+                .desired_balloon(ByteSize::gib(3))
+                .build()
+                .unwrap()
+        ),
+        ResizeError::BalloonTooLarge {
+            desired: ByteSize::gib(3),
+            ram: ByteSize::gib(2),
+        }
+    );
+
+    let ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    assert_eq!(
+        ch.check_resize(
+            &VmResizeBuilder::default()
+                .desired_ram(ByteSize::gib(1))
+                .build()
+                .unwrap()
+        ),
+        Err(Error::Resize(ResizeError::MemoryHotplugDisabled))
+    );
+}
+
+#[test]
+fn resize_zone() {
+    let mut ch = instance();
+
+    assert_eq!(ch.check_resize_zone("mem0", ByteSize::gib(2)), Ok(()));
+    assert_eq!(
+        ch.check_resize_zone("mem0", ByteSize::gib(3)),
+        Err(Error::Resize(ResizeError::ZoneRamOutOfRange {
+            id: "mem0".to_string(),
+            desired: ByteSize::gib(3),
+            min: ByteSize::gib(1),
+            max: ByteSize::gib(2),
+        }))
+    );
+    assert_eq!(
+        ch.check_resize_zone("mem1", ByteSize::gib(1)),
+        Err(Error::Resize(ResizeError::UnknownZone("mem1".to_string())))
+    );
+
+    ch.memory(
+        MemoryBuilder::default()
+            .size(ByteSize::gib(1))
+            .hotplug_method(MemoryHotplugMethod::Acpi)
+            .build()
+            .unwrap(),
+    );
+    let err = ch.check_resize_zone("mem0", ByteSize::gib(2)).unwrap_err();
+    assert_eq!(
+        err,
+        Error::Resize(ResizeError::ZoneHotplugRequiresVirtioMem)
+    );
+    assert_eq!(
+        err.to_string(),
+        "resize-zone: memory zones need --memory hotplug_method=virtio-mem"
+    );
+}
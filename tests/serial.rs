@@ -0,0 +1,103 @@
+mod common;
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use cloud_hypervisor_command_builder::serial::{Expected, SerialClient, SerialError};
+use cloud_hypervisor_command_builder::{CloudHypervisorInstance, Serial};
+
+use common::tmp;
+
+// A guest with a login prompt and a shell that runs `echo`.
+fn guest(stream: UnixStream) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut console = stream;
+    console
+        .write_all(b"[    0.000000] Linux version 6.1\n")
+        .unwrap();
+    thread::sleep(Duration::from_millis(20));
+    console.write_all(b"ci login: ").unwrap();
+
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    write!(console, "Welcome {}# ", line).unwrap();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).unwrap() == 0 {
+            return;
+        }
+        let output = line.trim_end().strip_prefix("echo ").unwrap_or_default();
+        // Written in two parts, like a slow console.
+        let (first, rest) = output.split_at(output.len() / 2);
+        console.write_all(first.as_bytes()).unwrap();
+        thread::sleep(Duration::from_millis(10));
+        write!(console, "{}\nrc=0\n# ", rest).unwrap();
+    }
+}
+
+#[test]
+fn login() {
+    let socket = tmp("login.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    thread::spawn(move || guest(listener.accept().unwrap().0));
+
+    let log = tmp("login.log");
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.serial(Serial::Socket(socket));
+    let mut serial = ch.serial_client().unwrap().tee(&log).unwrap();
+
+    let login = serial.expect("login: $", Duration::from_secs(5)).unwrap();
+    assert!(login.before.contains("Linux version 6.1"));
+    serial.send_line("root").unwrap();
+    serial.expect("# $", Duration::from_secs(5)).unwrap();
+
+    serial.send_line("echo smoke test").unwrap();
+    assert_eq!(
+        serial.expect(r"rc=(\d+)", Duration::from_secs(5)).unwrap(),
+        Expected {
+            before: "smoke test\n".to_string(),
+            matched: "rc=0".to_string(),
+            groups: vec![Some("0".to_string())],
+        }
+    );
+    assert_eq!(serial.output(), "\n# ");
+
+    let log = fs::read_to_string(&log).unwrap();
+    assert!(log.starts_with("[    0.000000] Linux version 6.1\nci login: Welcome root"));
+    assert!(log.ends_with("smoke test\nrc=0\n# "));
+}
+
+#[test]
+fn failures() {
+    let (mut console, stream) = UnixStream::pair().unwrap();
+    let mut serial = SerialClient::from_stream(stream).capacity(8);
+
+    console.write_all(b"0123456789abcdef\nlogin").unwrap();
+    match serial.expect("login: ", Duration::from_millis(50)) {
+        Err(err @ SerialError::Timeout { .. }) => assert_eq!(
+            err.to_string(),
+            r#"serial: timed out waiting for "login: ", last output: "login""#
+        ),
+        other => panic!("unexpected {:?}", other),
+    }
+    // Only the last 8 bytes are kept.
+    assert_eq!(serial.output(), "ef\nlogin");
+
+    assert!(matches!(
+        serial.expect("(", Duration::from_millis(50)),
+        Err(SerialError::Pattern(_))
+    ));
+
+    drop(console);
+    assert!(matches!(
+        serial.expect("login: ", Duration::from_secs(5)),
+        Err(SerialError::Closed { .. })
+    ));
+
+    let ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    assert!(matches!(ch.serial_client(), Err(SerialError::NoSocket)));
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::PathBuf;
+
+use cloud_hypervisor_command_builder::snapshot::{Snapshot, SnapshotError};
+use cloud_hypervisor_command_builder::to_command::ToCommand;
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, DiskBuilder, OnOff, PathOrFileDescriptorOption,
+};
+
+#[test]
+fn open() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("snapshot-open");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::write(dir.join("config.json"), r#"{"cpus":{"boot_vcpus":2}}"#).unwrap();
+    assert!(matches!(
+        Snapshot::open(&dir),
+        Err(SnapshotError::Missing { file, .. }) if file == "state.json"
+    ));
+    fs::write(dir.join("state.json"), "{}").unwrap();
+    assert!(matches!(
+        Snapshot::open(&dir),
+        Err(SnapshotError::Missing { file, .. }) if file == "memory file"
+    ));
+    fs::write(dir.join("memory-ranges"), "").unwrap();
+
+    let snapshot = Snapshot::open(&dir).unwrap();
+    assert_eq!(snapshot.config().unwrap().cpus.boot_vcpus, 2);
+    assert!(matches!(
+        Snapshot::open(dir.join("missing")),
+        Err(SnapshotError::Io(_))
+    ));
+
+    let mut base = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    base.disk(
+        DiskBuilder::default()
+            .path(PathBuf::from("/disk.raw"))
+            .build()
+            .unwrap(),
+    )
+    .api_socket(PathOrFileDescriptorOption::Path(PathBuf::from("/api.sock")));
+    assert_eq!(
+        snapshot
+            .restore_instance(&base, Some(OnOff::On))
+            .to_command(),
+        vec![
+            "/cloud-hypervisor".to_string(),
+            "--api-socket".to_string(),
+            "path=/api.sock".to_string(),
+            "--restore".to_string(),
+            format!("source_url=file://{},prefault=on", snapshot.dir().display()),
+        ]
+    );
+}
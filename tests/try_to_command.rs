@@ -0,0 +1,125 @@
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use cloud_hypervisor_command_builder::encode::EncodeErrorReason;
+use cloud_hypervisor_command_builder::error::Error;
+use cloud_hypervisor_command_builder::to_command::{ToCommand, TryToCommand};
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, Console, DebugConsole, DiskBuilder, NetBuilder, Rng, Serial, SgxEpc,
+};
+
+#[test]
+fn non_utf8_paths() {
+    let kernel = PathBuf::from(OsStr::from_bytes(b"/boot/vmlinux-\xff"));
+
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.kernel(kernel.clone());
+
+    assert_eq!(
+        ch.try_to_command().unwrap(),
+        [
+            OsString::from("/cloud-hypervisor"),
+            OsString::from("--kernel"),
+            kernel.clone().into_os_string(),
+        ]
+    );
+
+    ch.disk(DiskBuilder::default().path(kernel).build().unwrap());
+    match ch.try_to_command() {
+        Err(Error::Encode(err)) => {
+            assert_eq!(err.option, "--disk");
+            assert_eq!(err.key, "path");
+            assert_eq!(err.reason, EncodeErrorReason::NotUtf8);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn empty_options() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.disk(
+        DiskBuilder::default()
+            .path(PathBuf::from("/disk0"))
+            .build()
+            .unwrap(),
+    );
+    ch.disk(DiskBuilder::default().build().unwrap());
+
+    assert_eq!(
+        ch.try_to_command(),
+        Err(Error::EmptyOption {
+            option: "--disk".to_string()
+        })
+    );
+    assert_eq!(
+        ch.to_command(),
+        ["/cloud-hypervisor", "--disk", "path=/disk0"]
+    );
+
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.sgx_epc(SgxEpc {
+        id: None,
+        size: None,
+        prefault: None,
+    });
+    assert_eq!(
+        ch.try_to_command().unwrap_err().to_string(),
+        "--sgx-epc: no values set"
+    );
+
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.debug_console(DebugConsole {
+        console_type: None,
+        iobase: None,
+    });
+    assert_eq!(
+        ch.try_to_command().unwrap_err().to_string(),
+        "--debug-console: no values set"
+    );
+}
+
+#[test]
+fn empty_values_name_the_device() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.net(
+        NetBuilder::default()
+            .id("net0")
+            .socket(PathBuf::new())
+            .build()
+            .unwrap(),
+    );
+
+    assert_eq!(
+        ch.try_to_command().unwrap_err().to_string(),
+        "--net (id=net0) socket: cannot encode ``: value cannot be empty"
+    );
+
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.rng(Rng::Src(PathBuf::new()));
+
+    assert_eq!(
+        ch.try_to_command().unwrap_err().to_string(),
+        "--rng src: cannot encode ``: value cannot be empty"
+    );
+}
+
+#[test]
+fn conflicts() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.serial(Serial::Tty);
+    ch.console(Console::Tty);
+
+    assert_eq!(
+        ch.try_to_command(),
+        Err(Error::Conflict {
+            option: "--console".to_string(),
+            other: "--serial".to_string(),
+            reason: "only one device can use tty".to_string(),
+        })
+    );
+
+    ch.console(Console::Pty);
+    assert!(ch.try_to_command().is_ok());
+}
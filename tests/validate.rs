@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use cloud_hypervisor_command_builder::error::Error;
+use cloud_hypervisor_command_builder::validate::ValidationError;
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, DeviceBuilder, DiskBuilder, MemoryZoneBuilder, NetBuilder,
+    NumaBuilder, PlatformBuilder, RateLimitGroupBuilder, SgxEpc, Vsock,
+};
+
+#[test]
+fn valid_references() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.platform(
+        PlatformBuilder::default()
+            .num_pci_segments(2)
+            .build()
+            .unwrap(),
+    );
+    ch.rate_limit_group(
+        RateLimitGroupBuilder::default()
+            .id("group0")
+            .build()
+            .unwrap(),
+    );
+    ch.memory_zone(MemoryZoneBuilder::default().id("mem0").build().unwrap());
+    ch.disk(
+        DiskBuilder::default()
+            .id("disk0")
+            .rate_limit_group("group0")
+            .pci_segment("1")
+            .build()
+            .unwrap(),
+    );
+    ch.numa(
+        NumaBuilder::default()
+            .guest_numa_id("0")
+            .memory_zones(vec!["mem0".to_string()])
+            .pci_segments(vec!["0".to_string(), "1".to_string()])
+            .build()
+            .unwrap(),
+    );
+
+    assert_eq!(ch.validate(), Ok(()));
+}
+
+#[test]
+fn reports_every_error() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.rate_limit_group(
+        RateLimitGroupBuilder::default()
+            .id("group0")
+            .build()
+            .unwrap(),
+    );
+    ch.sgx_epc(SgxEpc {
+        id: Some("epc0".to_string()),
+        size: None,
+        prefault: None,
+    });
+    ch.disk(
+        DiskBuilder::default()
+            .id("disk0")
+            .rate_limit_group("group1")
+            .build()
+            .unwrap(),
+    );
+    ch.net(
+        NetBuilder::default()
+            .id("disk0")
+            .pci_segment("1")
+            .build()
+            .unwrap(),
+    );
+    ch.device(
+        DeviceBuilder::default()
+            .id("vfio0")
+            .pci_segment("first")
+            .build()
+            .unwrap(),
+    );
+    ch.vsock(Vsock {
+        cid: None,
+        socket: None,
+        iommu: None,
+        id: Some("vfio0".to_string()),
+        pci_segment: None,
+    });
+    ch.numa(
+        NumaBuilder::default()
+            .guest_numa_id("0")
+            .memory_zones(vec!["mem0".to_string()])
+            .sgx_epc_sections(vec!["epc0".to_string(), "epc1".to_string()])
+            .build()
+            .unwrap(),
+    );
+
+    assert_eq!(
+        ch.validate(),
+        Err(Error::Validation(vec![
+            ValidationError::UnknownRateLimitGroup {
+                disk: Some("disk0".to_string()),
+                rate_limit_group: "group1".to_string(),
+            },
+            ValidationError::UnknownMemoryZone {
+                guest_numa_id: Some("0".to_string()),
+                memory_zone: "mem0".to_string(),
+            },
+            ValidationError::UnknownSgxEpc {
+                guest_numa_id: Some("0".to_string()),
+                sgx_epc: "epc1".to_string(),
+            },
+            ValidationError::PciSegmentOutOfRange {
+                option: "--net".to_string(),
+                id: Some("disk0".to_string()),
+                pci_segment: 1,
+                num_pci_segments: 1,
+            },
+            ValidationError::InvalidPciSegment {
+                option: "--device".to_string(),
+                id: Some("vfio0".to_string()),
+                pci_segment: "first".to_string(),
+            },
+            ValidationError::DuplicateId {
+                id: "disk0".to_string(),
+                options: vec!["--disk".to_string(), "--net".to_string()],
+            },
+            ValidationError::DuplicateId {
+                id: "vfio0".to_string(),
+                options: vec!["--device".to_string(), "--vsock".to_string()],
+            },
+        ]))
+    );
+}
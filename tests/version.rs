@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+
+use cloud_hypervisor_command_builder::error::Error;
+use cloud_hypervisor_command_builder::to_command::{ToCommand, TryToCommand};
+use cloud_hypervisor_command_builder::version::ChVersion;
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, LandlockRuleBuilder, NumaBuilder, SgxEpc,
+};
+
+fn sgx_instance() -> CloudHypervisorInstance {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.sgx_epc(SgxEpc {
+        id: Some("epc0".to_string()),
+        size: Some("64M".to_string()),
+        prefault: None,
+    });
+    ch
+}
+
+fn landlock_instance() -> CloudHypervisorInstance {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.landlock(true);
+    ch.landlock_rules(
+        LandlockRuleBuilder::default()
+            .path(PathBuf::from("/images"))
+            .access("r")
+            .build()
+            .unwrap(),
+    );
+    ch
+}
+
+#[test]
+fn parses_versions() {
+    assert_eq!("v40.0".parse(), Ok(ChVersion::V40));
+    assert_eq!("41".parse(), Ok(ChVersion::V41));
+    assert_eq!("v39.1.2-12-gdeadbeef".parse(), Ok(ChVersion::new(39, 1, 2)));
+    assert!("v40.0.0.1".parse::<ChVersion>().is_err());
+    assert!("latest".parse::<ChVersion>().is_err());
+    assert_eq!(ChVersion::new(38, 0, 1).to_string(), "v38.0.1");
+}
+
+#[test]
+fn removed_options() {
+    let mut ch = sgx_instance();
+    assert!(ch.try_to_command().is_ok());
+
+    ch.target_version(ChVersion::V39);
+    assert!(ch.try_to_command().is_ok());
+
+    ch.target_version(ChVersion::V40);
+    assert_eq!(
+        ch.try_to_command(),
+        Err(Error::Unsupported {
+            option: "--sgx-epc".to_string(),
+            key: None,
+            version: ChVersion::V40,
+            added: None,
+            removed: Some(ChVersion::V40),
+        })
+    );
+    assert_eq!(
+        ch.try_to_command().unwrap_err().to_string(),
+        "--sgx-epc is not supported by cloud-hypervisor v40.0.0 (removed in v40.0.0)"
+    );
+
+    // lossy rendering passes unsupported options through
+    assert_eq!(
+        ch.to_command(),
+        vec!["/cloud-hypervisor", "--sgx-epc", "id=epc0,size=64M"]
+    );
+}
+
+#[test]
+fn removed_keys() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.target_version(ChVersion::V41);
+    ch.numa(
+        NumaBuilder::default()
+            .guest_numa_id("0")
+            .sgx_epc_sections(vec!["epc0".to_string()])
+            .build()
+            .unwrap(),
+    );
+
+    assert_eq!(
+        ch.try_to_command().unwrap_err().to_string(),
+        "--numa sgx_epc_sections is not supported by cloud-hypervisor v41.0.0 (removed in v40.0.0)"
+    );
+}
+
+#[test]
+fn added_options() {
+    let mut ch = landlock_instance();
+    ch.target_version(ChVersion::V39);
+    assert_eq!(
+        ch.try_to_command(),
+        Err(Error::Unsupported {
+            option: "--landlock".to_string(),
+            key: None,
+            version: ChVersion::V39,
+            added: Some(ChVersion::V40),
+            removed: None,
+        })
+    );
+
+    ch.target_version(ChVersion::V40);
+    assert_eq!(
+        ch.try_to_command().unwrap(),
+        vec![
+            "/cloud-hypervisor",
+            "--landlock",
+            "--landlock-rules",
+            "path=/images,access=r"
+        ]
+    );
+}
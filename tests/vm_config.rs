@@ -0,0 +1,328 @@
+use bytesize::ByteSize;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use cloud_hypervisor_command_builder::error::Error;
+use cloud_hypervisor_command_builder::from_command::{ParseError, ParseErrorKind};
+use cloud_hypervisor_command_builder::vm_config::VmConfig;
+use cloud_hypervisor_command_builder::{
+    CloudHypervisorInstance, Console, CpuAffinity, CpusBuilder, DebugConsole, DebugConsoleType,
+    DiskBuilder, FsBuilder, MemoryBuilder, MemoryZoneBuilder, NetBuilder, NumaBuilder,
+    NumaDistance, OnOff, PathOrFileDescriptorOption, PlatformBuilder, RateLimitGroupBuilder, Rng,
+    Serial, Vsock,
+};
+
+#[test]
+fn defaults() {
+    let ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+
+    assert_eq!(
+        serde_json::to_value(ch.to_vm_config().unwrap()).unwrap(),
+        json!({
+            "cpus": {"boot_vcpus": 1, "max_vcpus": 1},
+            "memory": {"size": 536870912},
+        })
+    );
+}
+
+fn instance() -> CloudHypervisorInstance {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.cpus(
+        CpusBuilder::default()
+            .boot(2)
+            .max(2)
+            .kvm_hyperv(OnOff::On)
+            .affinity(vec![CpuAffinity {
+                vcpu: 0,
+                host_cpus: vec![1, 2],
+            }])
+            .build()
+            .unwrap(),
+    )
+    .platform(
+        PlatformBuilder::default()
+            .num_pci_segments(2)
+            .build()
+            .unwrap(),
+    )
+    .memory(
+        MemoryBuilder::default()
+            .size(ByteSize::gib(1))
+            .shared(OnOff::On)
+            .build()
+            .unwrap(),
+    )
+    .memory_zone(
+        MemoryZoneBuilder::default()
+            .size(ByteSize::mib(256))
+            .id("mem0")
+            .host_numa_node(0usize)
+            .build()
+            .unwrap(),
+    )
+    .kernel(PathBuf::from("/vmlinux"))
+    .cmdline("console=ttyS0".to_string())
+    .rate_limit_group(
+        RateLimitGroupBuilder::default()
+            .bw_size(ByteSize::kib(4))
+            .bw_refill_time(100usize)
+            .id("group0")
+            .build()
+            .unwrap(),
+    )
+    .disk(
+        DiskBuilder::default()
+            .path(PathBuf::from("/disk.raw"))
+            .readonly(OnOff::On)
+            .queue_size(128usize)
+            .rate_limit_group("group0")
+            .pci_segment("1")
+            .queue_affinity("[0@[0,1],1@[2]]")
+            .build()
+            .unwrap(),
+    )
+    .net(
+        NetBuilder::default()
+            .tap("tap0")
+            .ip(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)))
+            .mask(24)
+            .fd(vec![3, 4])
+            .ops_size(10usize)
+            .ops_refill_time(50usize)
+            .build()
+            .unwrap(),
+    )
+    .fs(FsBuilder::default()
+        .tag("share")
+        .socket(PathBuf::from("/fs.sock"))
+        .build()
+        .unwrap())
+    .rng(Rng::Src(PathBuf::from("/dev/hwrng")))
+    .serial(Serial::Socket(PathBuf::from("/serial.sock")))
+    .console(Console::Off)
+    .debug_console(DebugConsole {
+        console_type: Some(DebugConsoleType::Pty),
+        iobase: Some("0xe9".to_string()),
+    })
+    .vsock(Vsock {
+        cid: Some("3".to_string()),
+        socket: Some(PathBuf::from("/vsock.sock")),
+        ..Default::default()
+    })
+    .numa(
+        NumaBuilder::default()
+            .guest_numa_id("0")
+            .cpus(vec![0, 1])
+            .distances(vec![
+                NumaDistance {
+                    destination: 0,
+                    distance: 10,
+                },
+                NumaDistance {
+                    destination: 1,
+                    distance: 20,
+                },
+            ])
+            .memory_zones(vec!["mem0".to_string()])
+            .build()
+            .unwrap(),
+    )
+    .watchdog(true)
+    .tpm(PathBuf::from("/swtpm.sock"));
+    ch
+}
+
+#[test]
+fn vm_config() {
+    let mut ch = instance();
+    ch.api_socket(PathOrFileDescriptorOption::Path(PathBuf::from("/api.sock")));
+
+    let config = ch.to_vm_config().unwrap();
+    assert_eq!(
+        serde_json::to_value(&config).unwrap(),
+        json!({
+            "cpus": {
+                "boot_vcpus": 2,
+                "max_vcpus": 2,
+                "kvm_hyperv": true,
+                "affinity": [{"vcpu": 0, "host_cpus": [1, 2]}],
+            },
+            "memory": {
+                "size": 1073741824,
+                "shared": true,
+                "zones": [{"id": "mem0", "size": 268435456, "host_numa_node": 0}],
+            },
+            "payload": {"kernel": "/vmlinux", "cmdline": "console=ttyS0"},
+            "rate_limit_groups": [{
+                "id": "group0",
+                "rate_limiter_config": {"bandwidth": {"size": 4096, "refill_time": 100}},
+            }],
+            "disks": [{
+                "path": "/disk.raw",
+                "readonly": true,
+                "queue_size": 128,
+                "rate_limit_group": "group0",
+                "pci_segment": 1,
+                "queue_affinity": [
+                    {"queue_index": 0, "host_cpus": [0, 1]},
+                    {"queue_index": 1, "host_cpus": [2]},
+                ],
+            }],
+            "net": [{
+                "tap": "tap0",
+                "ip": "192.168.1.1",
+                "mask": "255.255.255.0",
+                "fds": [3, 4],
+                "rate_limiter_config": {"ops": {"size": 10, "refill_time": 50}},
+            }],
+            "rng": {"src": "/dev/hwrng"},
+            "fs": [{"tag": "share", "socket": "/fs.sock"}],
+            "serial": {"mode": "Socket", "socket": "/serial.sock"},
+            "console": {"mode": "Off"},
+            "debug_console": {"mode": "Pty", "iobase": 233},
+            "vsock": {"cid": 3, "socket": "/vsock.sock"},
+            "numa": [{
+                "guest_numa_id": 0,
+                "cpus": [0, 1],
+                "distances": [
+                    {"destination": 0, "distance": 10},
+                    {"destination": 1, "distance": 20},
+                ],
+                "memory_zones": ["mem0"],
+            }],
+            "watchdog": true,
+            "platform": {"num_pci_segments": 2},
+            "tpm": {"socket": "/swtpm.sock"},
+        })
+    );
+
+    // unknown fields survive a round trip through the API types
+    let mut value = serde_json::to_value(&config).unwrap();
+    value["iommu"] = json!(true);
+    value["disks"][0]["serial"] = json!("disk0");
+    let parsed: VmConfig = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(parsed.disks.as_ref().unwrap()[0].queue_size, Some(128));
+    assert_eq!(serde_json::to_value(&parsed).unwrap(), value);
+}
+
+#[test]
+fn vm_config_errors() {
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.vsock(Vsock {
+        socket: Some(PathBuf::from("/vsock.sock")),
+        ..Default::default()
+    });
+    assert_eq!(
+        ch.to_vm_config().unwrap_err(),
+        Error::Parse(ParseError {
+            flag: "--vsock".to_string(),
+            key: Some("cid".to_string()),
+            kind: ParseErrorKind::MissingValue,
+        })
+    );
+
+    let mut ch = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    ch.disk(DiskBuilder::default().pci_segment("one").build().unwrap());
+    assert_eq!(
+        ch.to_vm_config().unwrap_err(),
+        Error::Parse(ParseError {
+            flag: "--disk".to_string(),
+            key: Some("pci_segment".to_string()),
+            kind: ParseErrorKind::InvalidValue("one".to_string()),
+        })
+    );
+}
+
+#[test]
+fn import() {
+    let ch = instance();
+    let config = ch.to_vm_config().unwrap();
+
+    let imported =
+        CloudHypervisorInstance::from_vm_config(PathBuf::from("/cloud-hypervisor"), &config);
+    assert_eq!(imported.instance, ch);
+    assert!(imported.unrepresentable.is_empty());
+
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("vm-config-snapshot");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("config.json"),
+        serde_json::to_vec(&config).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(VmConfig::read(&dir).unwrap(), config);
+    assert_eq!(VmConfig::read(dir.join("config.json")).unwrap(), config);
+}
+
+#[test]
+fn import_unrepresentable() {
+    let config: VmConfig = serde_json::from_value(json!({
+        "cpus": {"boot_vcpus": 1, "max_vcpus": 2},
+        "memory": {
+            "size": 1073741824,
+            "zones": [{"id": "mem0", "size": 1024}, {"id": "mem1", "size": 1024}],
+        },
+        "payload": {"kernel": "/vmlinux", "igvm": "/guest.igvm"},
+        "disks": [{"path": "/disk.raw", "serial": "disk0"}],
+        "net": [{"tap": "tap0", "mask": "255.0.255.0"}],
+        "console": {"mode": "Socket", "socket": "/console.sock"},
+        "numa": [{"guest_numa_id": 0, "distances": [{"destination": 1, "distance": 20}]}],
+        "iommu": false,
+    }))
+    .unwrap();
+
+    let imported =
+        CloudHypervisorInstance::from_vm_config(PathBuf::from("/cloud-hypervisor"), &config);
+    assert_eq!(
+        imported.unrepresentable,
+        vec![
+            "iommu",
+            "memory.zones[1]",
+            "payload.igvm",
+            "disks[0].serial",
+            "net[0].mask",
+            "console.socket",
+            "console.mode",
+        ]
+    );
+
+    let mut expected = CloudHypervisorInstance::new(PathBuf::from("/cloud-hypervisor"));
+    expected
+        .cpus(CpusBuilder::default().boot(1).max(2).build().unwrap())
+        .memory(
+            MemoryBuilder::default()
+                .size(ByteSize::gib(1))
+                .build()
+                .unwrap(),
+        )
+        .memory_zone(
+            MemoryZoneBuilder::default()
+                .id("mem0")
+                .size(ByteSize::kib(1))
+                .build()
+                .unwrap(),
+        )
+        .kernel(PathBuf::from("/vmlinux"))
+        .disk(
+            DiskBuilder::default()
+                .path(PathBuf::from("/disk.raw"))
+                .build()
+                .unwrap(),
+        )
+        .net(NetBuilder::default().tap("tap0").build().unwrap())
+        .numa(
+            NumaBuilder::default()
+                .guest_numa_id("0")
+                .distances(vec![NumaDistance {
+                    destination: 1,
+                    distance: 20,
+                }])
+                .build()
+                .unwrap(),
+        );
+    assert_eq!(imported.instance, expected);
+}